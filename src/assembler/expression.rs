@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Number(u16),
+    Identifier(String),
+    Location,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    OpenParen,
+    CloseParen,
+}
+
+/// Names and location counter that expressions are evaluated against
+pub struct Context<'a> {
+    pub symbols: &'a HashMap<String, u16>,
+    pub location: u16,
+}
+
+/// Reason an expression could not be evaluated
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExpressionError {
+    Undefined(String),
+    Invalid(String),
+}
+
+impl ExpressionError {
+    pub fn message(&self) -> String {
+        match self {
+            ExpressionError::Undefined(name) => format!("undefined symbol '{}'", name),
+            ExpressionError::Invalid(message) => message.clone(),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, ExpressionError>;
+
+fn invalid<T>(message: String) -> Result<T> {
+    Err(ExpressionError::Invalid(message))
+}
+
+pub fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_?@.".contains(c)
+}
+
+/// Parses a number literal: decimal, hexadecimal with an `H` suffix or `0x`
+/// prefix, octal with an `O` or `Q` suffix, or binary with a `B` suffix
+pub fn parse_number(text: &str) -> Option<u16> {
+    let upper = text.to_ascii_uppercase();
+    let (digits, radix) = if let Some(hex) = upper.strip_prefix("0X") {
+        (hex, 16)
+    } else if let Some(hex) = upper.strip_suffix('H') {
+        (hex, 16)
+    } else if let Some(octal) = upper.strip_suffix(['O', 'Q']) {
+        (octal, 8)
+    } else if let Some(binary) = upper.strip_suffix('B') {
+        (binary, 2)
+    } else if let Some(decimal) = upper.strip_suffix('D') {
+        (decimal, 10)
+    } else {
+        (upper.as_str(), 10)
+    };
+
+    u32::from_str_radix(digits, radix)
+        .ok()
+        .and_then(|value| u16::try_from(value).ok())
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            _ if c.is_whitespace() => continue,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '$' => Token::Location,
+            '\'' => {
+                let mut value: u16 = 0;
+                let mut length = 0;
+
+                loop {
+                    let character = match chars.next() {
+                        Some((_, '\'')) if chars.peek().is_some_and(|&(_, next)| next == '\'') => {
+                            chars.next();
+                            '\''
+                        }
+                        Some((_, '\'')) => break,
+                        Some((_, character)) => character,
+                        None => return invalid(String::from("unterminated character literal")),
+                    };
+
+                    value = value << 8 | (character as u16 & 0xFF);
+                    length += 1;
+                }
+
+                if length == 0 || length > 2 {
+                    return invalid(format!(
+                        "character literal must hold 1 or 2 characters, found {}",
+                        length
+                    ));
+                }
+
+                Token::Number(value)
+            }
+            _ if is_identifier_char(c) => {
+                let mut end = start + c.len_utf8();
+
+                while let Some(&(index, next)) = chars.peek() {
+                    if !is_identifier_char(next) {
+                        break;
+                    }
+
+                    end = index + next.len_utf8();
+                    chars.next();
+                }
+
+                let word = &text[start..end];
+
+                if c.is_ascii_digit() {
+                    Token::Number(parse_number(word).ok_or_else(|| {
+                        ExpressionError::Invalid(format!("invalid number '{}'", word))
+                    })?)
+                } else {
+                    Token::Identifier(word.to_ascii_uppercase())
+                }
+            }
+            _ => return invalid(format!("unexpected character '{}'", c)),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    context: &'a Context<'a>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Returns the keyword operator among `keywords` at the current token
+    fn peek_keyword(&self, keywords: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Identifier(word)) => {
+                keywords.iter().copied().find(|&keyword| keyword == word)
+            }
+            _ => None,
+        }
+    }
+
+    fn advance(&mut self) -> Option<&Token> {
+        self.position += 1;
+        self.tokens.get(self.position - 1)
+    }
+
+    /// Lowest precedence: `OR` and `XOR`
+    fn parse_or(&mut self) -> Result<u16> {
+        let mut value = self.parse_and()?;
+
+        while let Some(keyword) = self.peek_keyword(&["OR", "XOR"]) {
+            self.advance();
+            let right = self.parse_and()?;
+
+            value = match keyword {
+                "OR" => value | right,
+                _ => value ^ right,
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn parse_and(&mut self) -> Result<u16> {
+        let mut value = self.parse_not()?;
+
+        while self.peek_keyword(&["AND"]).is_some() {
+            self.advance();
+            value &= self.parse_not()?;
+        }
+
+        Ok(value)
+    }
+
+    fn parse_not(&mut self) -> Result<u16> {
+        if self.peek_keyword(&["NOT"]).is_some() {
+            self.advance();
+            return Ok(!self.parse_not()?);
+        }
+
+        self.parse_sum()
+    }
+
+    fn parse_sum(&mut self) -> Result<u16> {
+        let mut value = self.parse_product()?;
+
+        loop {
+            match self.peek() {
+                Some(Token::Plus) => {
+                    self.advance();
+                    value = value.wrapping_add(self.parse_product()?);
+                }
+                Some(Token::Minus) => {
+                    self.advance();
+                    value = value.wrapping_sub(self.parse_product()?);
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    fn parse_product(&mut self) -> Result<u16> {
+        let mut value = self.parse_unary()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Star) => "*",
+                Some(Token::Slash) => "/",
+                _ => match self.peek_keyword(&["MOD", "SHL", "SHR"]) {
+                    Some(keyword) => keyword,
+                    None => return Ok(value),
+                },
+            };
+
+            self.advance();
+            let right = self.parse_unary()?;
+
+            value = match operator {
+                "*" => value.wrapping_mul(right),
+                "SHL" => value.checked_shl(right as u32).unwrap_or(0),
+                "SHR" => value.checked_shr(right as u32).unwrap_or(0),
+                _ if right == 0 => return invalid(String::from("division by zero")),
+                "/" => value / right,
+                _ => value % right,
+            };
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<u16> {
+        match self.peek() {
+            Some(Token::Minus) => {
+                self.advance();
+                Ok(self.parse_unary()?.wrapping_neg())
+            }
+            Some(Token::Plus) => {
+                self.advance();
+                self.parse_unary()
+            }
+            _ => match self.peek_keyword(&["HIGH", "LOW"]) {
+                Some(keyword) => {
+                    self.advance();
+                    let [low, high] = self.parse_unary()?.to_le_bytes();
+
+                    Ok(if keyword == "HIGH" { high } else { low } as u16)
+                }
+                None => self.parse_primary(),
+            },
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<u16> {
+        let context = self.context;
+
+        match self.advance() {
+            Some(Token::Number(value)) => Ok(*value),
+            Some(Token::Location) => Ok(context.location),
+            Some(Token::Identifier(name)) => context
+                .symbols
+                .get(name)
+                .copied()
+                .ok_or_else(|| ExpressionError::Undefined(name.clone())),
+            Some(Token::OpenParen) => {
+                let value = self.parse_or()?;
+
+                match self.advance() {
+                    Some(Token::CloseParen) => Ok(value),
+                    _ => invalid(String::from("missing ')'")),
+                }
+            }
+            Some(token) => invalid(format!("unexpected {:?} in expression", token)),
+            None => invalid(String::from("unexpected end of expression")),
+        }
+    }
+}
+
+/// Evaluates `text` using 16-bit wrapping arithmetic
+pub fn evaluate(text: &str, context: &Context) -> Result<u16> {
+    let tokens = tokenize(text)?;
+
+    if tokens.is_empty() {
+        return invalid(String::from("missing expression"));
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        context,
+    };
+    let value = parser.parse_or()?;
+
+    match parser.peek() {
+        None => Ok(value),
+        Some(token) => invalid(format!("unexpected {:?} in expression", token)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate_with(text: &str, symbols: &[(&str, u16)]) -> Result<u16> {
+        let symbols = symbols
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value))
+            .collect();
+
+        evaluate(
+            text,
+            &Context {
+                symbols: &symbols,
+                location: 0x0100,
+            },
+        )
+    }
+
+    #[test]
+    fn should_evaluate_expressions() {
+        assert_eq!(evaluate_with("1 + 2 * 3", &[]), Ok(7));
+        assert_eq!(evaluate_with("(1 + 2) * 3", &[]), Ok(9));
+        assert_eq!(evaluate_with("0FFH + 0x10 + 101B + 17Q", &[]), Ok(0x123));
+        assert_eq!(evaluate_with("$ + 3", &[]), Ok(0x0103));
+        assert_eq!(evaluate_with("-1", &[]), Ok(0xFFFF));
+        assert_eq!(evaluate_with("HIGH 1234H + LOW 1234H", &[]), Ok(0x46));
+        assert_eq!(
+            evaluate_with("HIGH (START + 100H)", &[("START", 0x0200)]),
+            Ok(0x03)
+        );
+        assert_eq!(evaluate_with("1 SHL 4 OR 3 AND NOT 2", &[]), Ok(0x11));
+        assert_eq!(evaluate_with("10 MOD 4", &[]), Ok(2));
+        assert_eq!(evaluate_with("'A' + 'bc'", &[]), Ok(0x41 + 0x6263));
+        assert_eq!(evaluate_with("''''", &[]), Ok(0x27));
+        assert_eq!(evaluate_with("loop", &[("LOOP", 5)]), Ok(5));
+    }
+
+    #[test]
+    fn should_report_invalid_expressions() {
+        assert_eq!(
+            evaluate_with("START + 1", &[]),
+            Err(ExpressionError::Undefined(String::from("START")))
+        );
+        assert_eq!(
+            evaluate_with("12G", &[]),
+            Err(ExpressionError::Invalid(String::from(
+                "invalid number '12G'"
+            )))
+        );
+        assert_eq!(
+            evaluate_with("(1 + 2", &[]),
+            Err(ExpressionError::Invalid(String::from("missing ')'")))
+        );
+        assert_eq!(
+            evaluate_with("1 / 0", &[]).unwrap_err().message(),
+            "division by zero"
+        );
+        assert_eq!(
+            evaluate_with("", &[]).unwrap_err().message(),
+            "missing expression"
+        );
+    }
+}
@@ -0,0 +1,975 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Display,
+};
+
+use crate::{
+    instructions::{Condition, Instruction, Register, RegisterPair},
+    symbols::SymbolTable,
+};
+
+use expression::{evaluate, is_identifier_char, Context, ExpressionError};
+
+mod expression;
+
+/// Column at which source text starts in the listing, matching the listings
+/// read by `SymbolTable::parse_prn`
+const LISTING_SOURCE_COLUMN: usize = 16;
+
+/// Maximum number of bytes shown on a single listing line
+const LISTING_BYTES_PER_LINE: usize = 4;
+
+/// Error in the source, with the 1-based line it was found on
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Output of a successful assembly
+#[derive(Debug)]
+pub struct Assembly {
+    /// Address of the first byte of `bytes`
+    pub origin: u16,
+    /// Image from the lowest to the highest address written, with gaps
+    /// left by `ORG` and `DS` filled with zeros
+    pub bytes: Vec<u8>,
+    /// Source annotated with addresses and assembled bytes
+    pub listing: String,
+    /// Labels and `EQU` definitions
+    pub symbols: SymbolTable,
+}
+
+/// Source line split into its fields
+#[derive(Default)]
+struct Statement<'a> {
+    label: Option<&'a str>,
+    operation: Option<String>,
+    operands: Vec<&'a str>,
+}
+
+/// Removes the comment from `line`, ignoring `;` inside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+
+    for (index, c) in line.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+/// Splits operands at commas outside of quotes
+fn split_operands(text: &str) -> Vec<&str> {
+    let text = text.trim();
+
+    if text.is_empty() {
+        return vec![];
+    }
+
+    let mut operands = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ',' if !quoted => {
+                operands.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    operands.push(text[start..].trim());
+    operands
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| !c.is_ascii_digit() && is_identifier_char(c))
+        && name.chars().all(is_identifier_char)
+}
+
+/// Whether `word` is a directive or mnemonic rather than a label
+fn is_operation(word: &str) -> bool {
+    const OPERATIONS: [&str; 60] = [
+        "ORG", "EQU", "DB", "DW", "DS", "END", "MOV", "MVI", "LXI", "LDA", "STA", "LHLD", "SHLD",
+        "LDAX", "STAX", "XCHG", "ADD", "ADI", "ADC", "ACI", "SUB", "SUI", "SBB", "SBI", "INR",
+        "DCR", "INX", "DCX", "DAD", "DAA", "ANA", "ANI", "XRA", "XRI", "ORA", "ORI", "CMP", "CPI",
+        "RLC", "RRC", "RAL", "RAR", "CMA", "CMC", "STC", "JMP", "CALL", "RET", "RST", "PCHL",
+        "PUSH", "POP", "XTHL", "SPHL", "IN", "OUT", "EI", "DI", "HLT", "NOP",
+    ];
+    let word = word.to_ascii_uppercase();
+
+    OPERATIONS.contains(&word.as_str())
+        || (word.len() > 1
+            && word.starts_with(['J', 'C', 'R'])
+            && parse_condition(&word[1..]).is_some())
+}
+
+fn parse_statement(line: &str) -> Result<Statement<'_>, String> {
+    let code = strip_comment(line);
+    let rest = code.trim_start();
+
+    if rest.is_empty() {
+        return Ok(Statement::default());
+    }
+
+    let word_end = rest
+        .find(|c: char| !is_identifier_char(c))
+        .unwrap_or(rest.len());
+    let (word, after_word) = rest.split_at(word_end);
+    let next_word = after_word.split_whitespace().next().unwrap_or_default();
+
+    let (label, rest) = if let Some(after_colon) = after_word.strip_prefix(':') {
+        (Some(word), after_colon.trim_start())
+    } else if next_word.eq_ignore_ascii_case("EQU")
+        || (!code.starts_with([' ', '\t']) && !is_operation(word))
+    {
+        (Some(word), after_word.trim_start())
+    } else {
+        (None, rest)
+    };
+
+    if let Some(label) = label {
+        if !is_identifier(label) {
+            return Err(format!("invalid label '{}'", label));
+        }
+    }
+
+    let (operation, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+    Ok(Statement {
+        label,
+        operation: (!operation.is_empty()).then(|| operation.to_ascii_uppercase()),
+        operands: split_operands(operands),
+    })
+}
+
+fn parse_register(text: &str) -> Result<Register, String> {
+    match text.to_ascii_uppercase().as_str() {
+        "A" => Ok(Register::A),
+        "B" => Ok(Register::B),
+        "C" => Ok(Register::C),
+        "D" => Ok(Register::D),
+        "E" => Ok(Register::E),
+        "H" => Ok(Register::H),
+        "L" => Ok(Register::L),
+        "M" => Ok(Register::Memory),
+        _ => Err(format!("invalid register '{}'", text)),
+    }
+}
+
+/// Parses a register pair, accepting both the Intel names (`B`, `D`, `H`)
+/// and the full names used by the disassembler (`BC`, `DE`, `HL`)
+fn parse_register_pair(text: &str) -> Result<RegisterPair, String> {
+    match text.to_ascii_uppercase().as_str() {
+        "B" | "BC" => Ok(RegisterPair::BC),
+        "D" | "DE" => Ok(RegisterPair::DE),
+        "H" | "HL" => Ok(RegisterPair::HL),
+        "SP" => Ok(RegisterPair::SP),
+        _ => Err(format!("invalid register pair '{}'", text)),
+    }
+}
+
+fn parse_condition(text: &str) -> Option<Condition> {
+    match text {
+        "NZ" => Some(Condition::NotZero),
+        "Z" => Some(Condition::Zero),
+        "NC" => Some(Condition::NoCarry),
+        "C" => Some(Condition::Carry),
+        "PO" => Some(Condition::OddParity),
+        "PE" => Some(Condition::EvenParity),
+        "P" => Some(Condition::Plus),
+        "M" => Some(Condition::Minus),
+        _ => None,
+    }
+}
+
+fn to_byte(value: u16) -> Result<u8, String> {
+    match value {
+        0x0000..=0x00FF => Ok(value as u8),
+        // Negative values written as 16-bit two's complement
+        0xFF00..=0xFFFF => Ok(value as u8),
+        _ => Err(format!("value {:#06x} does not fit in a byte", value)),
+    }
+}
+
+/// Parses an instruction from its upper-case mnemonic and operands, using
+/// `evaluate` for the value of any expression
+fn parse_instruction(
+    mnemonic: &str,
+    operands: &[&str],
+    evaluate: &dyn Fn(&str) -> Result<u16, String>,
+) -> Result<Instruction, String> {
+    let expect = |count: usize| {
+        if operands.len() == count {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} expects {} operand{}, found {}",
+                mnemonic,
+                count,
+                if count == 1 { "" } else { "s" },
+                operands.len()
+            ))
+        }
+    };
+    let register = |index: usize| parse_register(operands[index]);
+    let pair = |index: usize| parse_register_pair(operands[index]);
+    let byte = |index: usize| to_byte(evaluate(operands[index])?);
+    let word = |index: usize| evaluate(operands[index]);
+
+    let no_operands = match mnemonic {
+        "XCHG" => Some(Instruction::ExchangeHLWithDE),
+        "DAA" => Some(Instruction::DecimalAdjustAccum),
+        "RLC" => Some(Instruction::RotateLeft),
+        "RRC" => Some(Instruction::RotateRight),
+        "RAL" => Some(Instruction::RotateLeftThroughCarry),
+        "RAR" => Some(Instruction::RotateRightThroughCarry),
+        "CMA" => Some(Instruction::ComplementAccum),
+        "CMC" => Some(Instruction::ComplementCarry),
+        "STC" => Some(Instruction::SetCarry),
+        "RET" => Some(Instruction::Return),
+        "PCHL" => Some(Instruction::JumpHLIndirect),
+        "XTHL" => Some(Instruction::ExchangeStackTopWithHL),
+        "SPHL" => Some(Instruction::MoveHLToSP),
+        "EI" => Some(Instruction::EnableInterrupts),
+        "DI" => Some(Instruction::DisableInterrupts),
+        "HLT" => Some(Instruction::Halt),
+        "NOP" => Some(Instruction::NoOp),
+        _ => None,
+    };
+
+    if let Some(instruction) = no_operands {
+        expect(0)?;
+        return Ok(instruction);
+    }
+
+    let single_register: Option<fn(Register) -> Instruction> = match mnemonic {
+        "ADD" => Some(Instruction::Add),
+        "ADC" => Some(Instruction::AddWithCarry),
+        "SUB" => Some(Instruction::Subtract),
+        "SBB" => Some(Instruction::SubtractWithBorrow),
+        "ANA" => Some(Instruction::And),
+        "XRA" => Some(Instruction::Xor),
+        "ORA" => Some(Instruction::Or),
+        "CMP" => Some(Instruction::Compare),
+        "INR" => Some(Instruction::Increment),
+        "DCR" => Some(Instruction::Decrement),
+        _ => None,
+    };
+
+    if let Some(constructor) = single_register {
+        expect(1)?;
+        return Ok(constructor(register(0)?));
+    }
+
+    let immediate: Option<fn(u8) -> Instruction> = match mnemonic {
+        "ADI" => Some(Instruction::AddImmediate),
+        "ACI" => Some(Instruction::AddImmediateWithCarry),
+        "SUI" => Some(Instruction::SubtractImmediate),
+        "SBI" => Some(Instruction::SubtractImmediateWithBorrow),
+        "ANI" => Some(Instruction::AndImmediate),
+        "XRI" => Some(Instruction::XorImmediate),
+        "ORI" => Some(Instruction::OrImmediate),
+        "CPI" => Some(Instruction::CompareImmediate),
+        "IN" => Some(Instruction::Input),
+        "OUT" => Some(Instruction::Output),
+        _ => None,
+    };
+
+    if let Some(constructor) = immediate {
+        expect(1)?;
+        return Ok(constructor(byte(0)?));
+    }
+
+    let address: Option<fn(u16) -> Instruction> = match mnemonic {
+        "LDA" => Some(Instruction::LoadAccumDirect),
+        "STA" => Some(Instruction::StoreAccumDirect),
+        "LHLD" => Some(Instruction::LoadHLDirect),
+        "SHLD" => Some(Instruction::StoreHLDirect),
+        "JMP" => Some(Instruction::Jump),
+        "CALL" => Some(Instruction::Call),
+        _ => None,
+    };
+
+    if let Some(constructor) = address {
+        expect(1)?;
+        return Ok(constructor(word(0)?));
+    }
+
+    match mnemonic {
+        "MOV" => {
+            expect(2)?;
+
+            let (destination, source) = (register(0)?, register(1)?);
+
+            if matches!(
+                (&destination, &source),
+                (Register::Memory, Register::Memory)
+            ) {
+                return Err(String::from("MOV M, M is not a valid instruction"));
+            }
+
+            Ok(Instruction::Move(source, destination))
+        }
+        "MVI" => {
+            expect(2)?;
+            Ok(Instruction::MoveImmediate(register(0)?, byte(1)?))
+        }
+        "LXI" => {
+            expect(2)?;
+            Ok(Instruction::LoadRegisterPairImmediate(pair(0)?, word(1)?))
+        }
+        "LDAX" | "STAX" => {
+            expect(1)?;
+
+            let pair = pair(0)?;
+
+            if !matches!(pair, RegisterPair::BC | RegisterPair::DE) {
+                return Err(format!("{} only accepts register pairs B and D", mnemonic));
+            }
+
+            Ok(if mnemonic == "LDAX" {
+                Instruction::LoadAccumIndirect(pair)
+            } else {
+                Instruction::StoreAccumIndirect(pair)
+            })
+        }
+        "INX" | "DCX" | "DAD" => {
+            expect(1)?;
+
+            let pair = pair(0)?;
+
+            Ok(match mnemonic {
+                "INX" => Instruction::IncrementRegPair(pair),
+                "DCX" => Instruction::DecrementRegPair(pair),
+                _ => Instruction::AddRegPairToHL(pair),
+            })
+        }
+        "PUSH" | "POP" => {
+            expect(1)?;
+
+            let is_push = mnemonic == "PUSH";
+
+            if operands[0].eq_ignore_ascii_case("PSW") {
+                return Ok(if is_push {
+                    Instruction::PushPSW
+                } else {
+                    Instruction::PopPSW
+                });
+            }
+
+            match pair(0)? {
+                RegisterPair::SP => Err(format!("{} does not accept SP, use PSW", mnemonic)),
+                pair if is_push => Ok(Instruction::PushRegPair(pair)),
+                pair => Ok(Instruction::PopRegPair(pair)),
+            }
+        }
+        "RST" => {
+            expect(1)?;
+
+            match word(0)? {
+                n @ 0..=7 => Ok(Instruction::Restart(n as u8)),
+                n => Err(format!("restart number {} is not between 0 and 7", n)),
+            }
+        }
+        _ => {
+            let (prefix, suffix) = mnemonic.split_at(mnemonic.len().min(1));
+            let condition = parse_condition(suffix)
+                .ok_or_else(|| format!("unknown instruction '{}'", mnemonic))?;
+
+            match prefix {
+                "J" => {
+                    expect(1)?;
+                    Ok(Instruction::ConditionalJump(condition, word(0)?))
+                }
+                "C" => {
+                    expect(1)?;
+                    Ok(Instruction::ConditionalCall(condition, word(0)?))
+                }
+                "R" => {
+                    expect(0)?;
+                    Ok(Instruction::ConditionalReturn(condition))
+                }
+                _ => Err(format!("unknown instruction '{}'", mnemonic)),
+            }
+        }
+    }
+}
+
+/// Parses a `DB` operand that consists of a single quoted string
+fn parse_string(text: &str) -> Option<Vec<u8>> {
+    let inner = text.strip_prefix('\'')?.strip_suffix('\'')?;
+
+    if inner.replace("''", "").contains('\'') {
+        return None;
+    }
+
+    Some(inner.replace("''", "'").bytes().collect())
+}
+
+/// Bytes emitted by a data directive or instruction
+fn statement_bytes(
+    operation: &str,
+    operands: &[&str],
+    evaluate: &dyn Fn(&str) -> Result<u16, String>,
+) -> Result<Vec<u8>, String> {
+    match operation {
+        "DB" | "DW" if operands.is_empty() => {
+            Err(format!("{} expects at least one value", operation))
+        }
+        "DB" => {
+            let mut bytes = vec![];
+
+            for operand in operands {
+                match parse_string(operand) {
+                    Some(string) if string.len() != 1 => bytes.extend(string),
+                    _ => bytes.push(to_byte(evaluate(operand)?)?),
+                }
+            }
+
+            Ok(bytes)
+        }
+        "DW" => {
+            let mut bytes = vec![];
+
+            for operand in operands {
+                bytes.extend(evaluate(operand)?.to_le_bytes());
+            }
+
+            Ok(bytes)
+        }
+        _ => Ok(parse_instruction(operation, operands, evaluate)?.encode()),
+    }
+}
+
+fn listing_line(prefix: &str, source: &str) -> String {
+    format!(
+        "{:<width$}{}",
+        prefix,
+        source,
+        width = LISTING_SOURCE_COLUMN
+    )
+    .trim_end()
+    .to_string()
+        + "\n"
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+struct Assembler<'a> {
+    lines: Vec<(&'a str, Result<Statement<'a>, String>)>,
+    symbols: HashMap<String, u16>,
+    /// Operands of `ORG` and `DS`, keyed by line index, as evaluated in the
+    /// first pass so that both passes lay out the program the same way
+    layout: HashMap<usize, u16>,
+    errors: Vec<AssemblyError>,
+}
+
+impl Assembler<'_> {
+    fn error(&mut self, index: usize, message: String) {
+        self.errors.push(AssemblyError {
+            line: index + 1,
+            message,
+        });
+    }
+
+    fn evaluate(&self, text: &str, location: u16) -> Result<u16, ExpressionError> {
+        evaluate(
+            text,
+            &Context {
+                symbols: &self.symbols,
+                location,
+            },
+        )
+    }
+
+    /// Evaluates the operand of `ORG` or `DS` during the first pass, which
+    /// cannot refer to symbols defined further on as it decides the layout
+    /// of the program
+    fn evaluate_layout(&mut self, index: usize, operands: &[&str], location: u16) -> Option<u16> {
+        let result = match operands {
+            [operand] => self
+                .evaluate(operand, location)
+                .map_err(|error| match error {
+                    ExpressionError::Undefined(name) => {
+                        format!("symbol '{}' must be defined before it is used here", name)
+                    }
+                    error => error.message(),
+                }),
+            _ => Err(format!("expected 1 operand, found {}", operands.len())),
+        };
+
+        match result {
+            Ok(value) => {
+                self.layout.insert(index, value);
+                Some(value)
+            }
+            Err(message) => {
+                self.error(index, message);
+                None
+            }
+        }
+    }
+
+    fn define(&mut self, index: usize, name: &str, value: u16) {
+        let name = name.to_ascii_uppercase();
+
+        match self.symbols.entry(name) {
+            Entry::Occupied(entry) => {
+                let message = format!("symbol '{}' is already defined", entry.key());
+                self.error(index, message);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+    }
+
+    /// Defines labels and equates, leaving out equates that refer to symbols
+    /// defined further on, which are resolved afterwards from their line and
+    /// location
+    fn first_pass(&mut self) -> Vec<(usize, u16)> {
+        let mut location: u16 = 0;
+        let mut pending_equates = vec![];
+
+        for index in 0..self.lines.len() {
+            let Ok(statement) = &self.lines[index].1 else {
+                continue;
+            };
+            let label = statement.label;
+            let operation = statement.operation.clone();
+            let operands = statement.operands.clone();
+
+            match operation.as_deref() {
+                Some("EQU") => {
+                    let operand = operands.first().copied().unwrap_or_default();
+
+                    match (label, self.evaluate(operand, location)) {
+                        (Some(label), Ok(value)) => self.define(index, label, value),
+                        (Some(_), Err(_)) => pending_equates.push((index, location)),
+                        (None, _) => {}
+                    }
+
+                    continue;
+                }
+                Some("ORG") => {
+                    if let Some(address) = self.evaluate_layout(index, &operands, location) {
+                        location = address;
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(label) = label {
+                self.define(index, label, location);
+            }
+
+            let size = match operation.as_deref() {
+                Some("END") => break,
+                Some("DS") => self
+                    .evaluate_layout(index, &operands, location)
+                    .unwrap_or(0),
+                Some("ORG") | None => 0,
+                Some(operation) => {
+                    let evaluate = |text: &str| match self.evaluate(text, location) {
+                        Ok(value) => Ok(value),
+                        Err(ExpressionError::Undefined(_)) => Ok(0),
+                        Err(error) => Err(error.message()),
+                    };
+
+                    statement_bytes(operation, &operands, &evaluate)
+                        .map_or(0, |bytes| bytes.len() as u16)
+                }
+            };
+
+            location = location.wrapping_add(size);
+        }
+
+        pending_equates
+    }
+
+    /// Resolves equates that refer to symbols defined further on, as long as
+    /// each round defines at least one of them
+    fn resolve_equates(&mut self, mut pending: Vec<(usize, u16)>) {
+        loop {
+            let count = pending.len();
+            let mut unresolved = vec![];
+
+            for (index, location) in pending {
+                let Ok(statement) = &self.lines[index].1 else {
+                    continue;
+                };
+                let label = statement.label.unwrap_or_default();
+                let operand = statement.operands.first().copied().unwrap_or_default();
+
+                match self.evaluate(operand, location) {
+                    Ok(value) => self.define(index, label, value),
+                    Err(_) => unresolved.push((index, location)),
+                }
+            }
+
+            pending = unresolved;
+
+            if pending.len() == count {
+                return;
+            }
+        }
+    }
+
+    /// Emits the bytes of every statement, returning them along with the
+    /// address they start at, together with the listing
+    fn second_pass(&mut self) -> (Vec<(u16, Vec<u8>)>, String) {
+        let mut location: u16 = 0;
+        let mut chunks = vec![];
+        let mut listing = String::new();
+        let mut ended = false;
+
+        for index in 0..self.lines.len() {
+            let source = self.lines[index].0;
+
+            if ended {
+                listing += &listing_line("", source);
+                continue;
+            }
+
+            let statement = match &self.lines[index].1 {
+                Ok(statement) => statement,
+                Err(message) => {
+                    let message = message.clone();
+                    self.error(index, message);
+                    listing += &listing_line("", source);
+                    continue;
+                }
+            };
+            let label = statement.label;
+            let operation = statement.operation.clone();
+            let operands = statement.operands.clone();
+
+            let result = match operation.as_deref() {
+                Some("EQU") => {
+                    let result = match (label, operands.len()) {
+                        (None, _) => Err(String::from("EQU requires a label")),
+                        (_, 1) => self
+                            .evaluate(operands[0], location)
+                            .map_err(|error| error.message()),
+                        _ => Err(String::from("EQU expects 1 operand")),
+                    };
+
+                    match result {
+                        Ok(value) => listing += &listing_line(&format!(" {:04X} =", value), source),
+                        Err(message) => {
+                            self.error(index, message);
+                            listing += &listing_line("", source);
+                        }
+                    }
+
+                    continue;
+                }
+                Some("ORG") => {
+                    if let Some(&address) = self.layout.get(&index) {
+                        location = address;
+                    }
+
+                    Ok(vec![])
+                }
+                Some("DS") => Ok(vec![]),
+                Some("END") => {
+                    ended = true;
+                    Ok(vec![])
+                }
+                Some(operation) => {
+                    let evaluate = |text: &str| {
+                        self.evaluate(text, location)
+                            .map_err(|error| error.message())
+                    };
+
+                    statement_bytes(operation, &operands, &evaluate)
+                }
+                None => Ok(vec![]),
+            };
+
+            let bytes = match result {
+                Ok(bytes) => bytes,
+                Err(message) => {
+                    self.error(index, message);
+                    vec![]
+                }
+            };
+
+            let prefix = if operation.as_deref() == Some("END")
+                || (operation.is_none() && label.is_none())
+            {
+                String::new()
+            } else {
+                format!(
+                    " {:04X} {}",
+                    location,
+                    format_bytes(&bytes[..bytes.len().min(LISTING_BYTES_PER_LINE)])
+                )
+            };
+
+            listing += &listing_line(&prefix, source);
+
+            for (offset, line_bytes) in bytes.chunks(LISTING_BYTES_PER_LINE).enumerate().skip(1) {
+                let address = location.wrapping_add((offset * LISTING_BYTES_PER_LINE) as u16);
+                listing += &listing_line(
+                    &format!(" {:04X} {}", address, format_bytes(line_bytes)),
+                    "",
+                );
+            }
+
+            let size = match operation.as_deref() {
+                Some("DS") => self.layout.get(&index).copied().unwrap_or(0),
+                _ => bytes.len() as u16,
+            };
+
+            if !bytes.is_empty() {
+                chunks.push((location, bytes));
+            }
+
+            location = location.wrapping_add(size);
+        }
+
+        (chunks, listing)
+    }
+}
+
+/// Assembles Intel 8080 source into a binary image
+///
+/// Supports all 8080 mnemonics, labels, `ORG`, `EQU`, `DB`, `DW`, `DS` and
+/// `END`, and expressions with `$`, `HIGH`, `LOW` and the Intel operators.
+/// All errors found are returned, in line order.
+pub fn assemble(source: &str) -> Result<Assembly, Vec<AssemblyError>> {
+    let mut assembler = Assembler {
+        lines: source
+            .lines()
+            .map(|line| (line, parse_statement(line)))
+            .collect(),
+        symbols: HashMap::new(),
+        layout: HashMap::new(),
+        errors: vec![],
+    };
+
+    let pending_equates = assembler.first_pass();
+    assembler.resolve_equates(pending_equates);
+
+    let (chunks, listing) = assembler.second_pass();
+
+    if !assembler.errors.is_empty() {
+        assembler.errors.sort_by_key(|error| error.line);
+        return Err(assembler.errors);
+    }
+
+    let origin = chunks
+        .iter()
+        .map(|(address, _)| *address)
+        .min()
+        .unwrap_or(0);
+    let end = chunks
+        .iter()
+        .map(|(address, bytes)| (*address - origin) as usize + bytes.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut bytes = vec![0; end];
+
+    for (address, chunk) in chunks {
+        let offset = (address - origin) as usize;
+        bytes[offset..offset + chunk.len()].copy_from_slice(&chunk);
+    }
+
+    let mut symbols = SymbolTable::new();
+
+    for (name, value) in &assembler.symbols {
+        symbols.insert(name, *value);
+    }
+
+    Ok(Assembly {
+        origin,
+        bytes,
+        listing,
+        symbols,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::{memory::bus::MemoryBus, program_counter::ProgramCounter};
+
+    fn assemble_bytes(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().bytes
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        assemble(source)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn should_round_trip_every_opcode() {
+        for opcode in 0..=0xFF {
+            let mut memory = MemoryBus::new();
+            memory.load(0x0000, vec![opcode, 0x34, 0x12]);
+
+            let expected = ProgramCounter::new().get_next_instruction(&memory);
+            let assembly = assemble(&format!("        {}", expected)).unwrap();
+
+            let mut memory = MemoryBus::new();
+            memory.load(0x0000, assembly.bytes);
+
+            assert_eq!(
+                ProgramCounter::new().get_next_instruction(&memory),
+                expected,
+                "opcode {:#04x}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn should_assemble_intel_syntax() {
+        let source = "\
+BDOS    EQU     5
+        ORG     100H
+START:  LXI     D, MESSAGE      ; print the message
+        MVI     C, 9
+        CALL    BDOS
+LOOP    JMP     $
+        MOV     M, A
+        PUSH    PSW
+        LDAX    B
+        RST     7
+        MVI     A, HIGH (START + 0x200)
+        ADI     LOW -2
+MESSAGE DB      'Hi;', 0DH, 'A' + 1, '$'
+        DW      START, LENGTH
+        DS      2
+        DB      0
+LENGTH  EQU     $ - START
+        END     START
+        NOP
+";
+
+        assert_eq!(
+            assemble_bytes(source),
+            vec![
+                0x11, 0x13, 0x01, // LXI D, MESSAGE
+                0x0E, 0x09, // MVI C, 9
+                0xCD, 0x05, 0x00, // CALL BDOS
+                0xC3, 0x08, 0x01, // JMP $
+                0x77, // MOV M, A
+                0xF5, // PUSH PSW
+                0x0A, // LDAX B
+                0xFF, // RST 7
+                0x3E, 0x03, // MVI A, HIGH (START + 0x200)
+                0xC6, 0xFE, // ADI LOW -2
+                0x48, 0x69, 0x3B, 0x0D, 0x42, 0x24, // DB
+                0x00, 0x01, 0x20, 0x00, // DW START, LENGTH
+                0x00, 0x00, // DS 2
+                0x00, // DB 0
+            ]
+        );
+
+        let assembly = assemble(source).unwrap();
+
+        assert_eq!(assembly.origin, 0x0100);
+        assert_eq!(assembly.symbols.get_address("LOOP"), Some(0x0108));
+        assert_eq!(assembly.symbols.get_address("LENGTH"), Some(0x0020));
+    }
+
+    #[test]
+    fn should_produce_listing() {
+        let assembly = assemble(
+            "; hello\nBDOS EQU 5\n\tORG 100H\nSTART:\tCALL BDOS\n\tDB 1, 2, 3, 4, 5\n\tEND\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            assembly.listing,
+            "                ; hello\n \
+             0005 =         BDOS EQU 5\n \
+             0100           \tORG 100H\n \
+             0100 CD0500    START:\tCALL BDOS\n \
+             0103 01020304  \tDB 1, 2, 3, 4, 5\n \
+             0107 05\n\
+             \x20               \tEND\n"
+        );
+
+        let symbols = SymbolTable::parse_prn(&assembly.listing);
+
+        assert_eq!(symbols.get_address("START"), Some(0x0100));
+        assert_eq!(symbols.get_address("BDOS"), Some(0x0005));
+    }
+
+    #[test]
+    fn should_report_errors_with_line_numbers() {
+        assert_eq!(
+            errors(
+                "        MVI A, 256\n\
+                 \x20       MOV A\n\
+                 \x20       FOO\n\
+                 \x20       JMP NOWHERE\n\
+                 LOOP:   NOP\n\
+                 LOOP:   NOP\n\
+                 \x20       LDAX H\n\
+                 \x20       RST 8\n\
+                 \x20       ORG LATER\n\
+                 LATER:  DB 'open\n\
+                 1ST:    NOP\n"
+            ),
+            vec![
+                "line 1: value 0x0100 does not fit in a byte",
+                "line 2: MOV expects 2 operands, found 1",
+                "line 3: unknown instruction 'FOO'",
+                "line 4: undefined symbol 'NOWHERE'",
+                "line 6: symbol 'LOOP' is already defined",
+                "line 7: LDAX only accepts register pairs B and D",
+                "line 8: restart number 8 is not between 0 and 7",
+                "line 9: symbol 'LATER' must be defined before it is used here",
+                "line 10: unterminated character literal",
+                "line 11: invalid label '1ST'",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_resolve_forward_equates_at_their_location() {
+        let assembly = assemble(
+            "        ORG     200H\n\
+             HERE    EQU     $ + SIZE\n\
+             \x20       DW      HERE\n\
+             SIZE    EQU     4\n",
+        )
+        .unwrap();
+
+        assert_eq!(assembly.symbols.get_address("HERE"), Some(0x0204));
+        assert_eq!(assembly.bytes, vec![0x04, 0x02]);
+
+        assert_eq!(
+            errors("X       EQU     LATER\nX       EQU     3\nLATER:  NOP\n"),
+            vec!["line 1: symbol 'X' is already defined"]
+        );
+    }
+}
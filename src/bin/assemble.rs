@@ -0,0 +1,105 @@
+use std::{env, fs, path::Path, process};
+
+use emulator_8080::assembler::assemble;
+
+const USAGE: &str = "\
+Usage: assemble [OPTIONS] <SOURCE>
+
+Options:
+  -o, --output <FILE>       Binary image to write (default: SOURCE with a .bin extension)
+  -l, --listing <FILE>      Write a listing with addresses and assembled bytes
+  -s, --symbols <FILE>      Write the symbol table as name = address lines
+  -h, --help                Print this message";
+
+struct Options {
+    source_path: String,
+    output_path: Option<String>,
+    listing_path: Option<String>,
+    symbols_path: Option<String>,
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        source_path: String::new(),
+        output_path: None,
+        listing_path: None,
+        symbols_path: None,
+    };
+    let mut source_path = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for '{}'", arg))
+        };
+
+        match arg.as_str() {
+            "-o" | "--output" => options.output_path = Some(value()?),
+            "-l" | "--listing" => options.listing_path = Some(value()?),
+            "-s" | "--symbols" => options.symbols_path = Some(value()?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if source_path.is_none() => source_path = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    options.source_path = source_path.ok_or("missing source path")?;
+
+    Ok(options)
+}
+
+fn write(path: &str, contents: impl AsRef<[u8]>) {
+    fs::write(path, contents).unwrap_or_else(|error| {
+        eprintln!("error: cannot write '{}': {}", path, error);
+        process::exit(1);
+    });
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1).collect()).unwrap_or_else(|message| {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    });
+
+    let source = fs::read_to_string(&options.source_path).unwrap_or_else(|error| {
+        eprintln!("error: cannot read '{}': {}", options.source_path, error);
+        process::exit(1);
+    });
+
+    let assembly = assemble(&source).unwrap_or_else(|errors| {
+        for error in errors {
+            eprintln!("{}: {}", options.source_path, error);
+        }
+
+        process::exit(1);
+    });
+
+    let output_path = options.output_path.clone().unwrap_or_else(|| {
+        Path::new(&options.source_path)
+            .with_extension("bin")
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    write(&output_path, &assembly.bytes);
+
+    if let Some(path) = &options.listing_path {
+        write(path, &assembly.listing);
+    }
+
+    if let Some(path) = &options.symbols_path {
+        write(path, assembly.symbols.to_string());
+    }
+
+    println!(
+        "{}: {} bytes at {:#06x}",
+        output_path,
+        assembly.bytes.len(),
+        assembly.origin
+    );
+}
@@ -0,0 +1,204 @@
+use std::{env, fs, path::Path, process};
+
+use emulator_8080::cpm::{
+    disk::{DiskFormat, DiskImage, FORMAT_NAMES},
+    fcb::FileName,
+    filesystem::FileSystem,
+    machine::SYSTEM_SIZE,
+};
+
+const USAGE: &str = "\
+Usage: cpmdisk [OPTIONS] <COMMAND> <IMAGE> [ARGUMENTS...]
+
+Commands:
+  list <IMAGE>                      List the files of every user area
+  get <IMAGE> <[USER:]NAME> [FILE]  Copy a file out of the image, to NAME by default
+  put <IMAGE> <FILE> [[USER:]NAME]  Copy a host file into the image, replacing any file of
+                                    that name, named after FILE by default
+  delete <IMAGE> <[USER:]PATTERN>   Delete the files matching a name such as *.BAK
+  format <IMAGE>                    Create a blank image
+
+Files are copied whole, as 128-byte records. USER is a user area from 0 to 15, 0 by default.
+
+Options:
+  -f, --format <FORMAT>     Disk format: ibm-3740 (8\" SSSD, default) or z80pack-hd
+  -s, --system <FILE>       With format, write a CCP and BDOS assembled for 0xE400 to the
+                            system tracks
+  -h, --help                Print this message";
+
+struct Options {
+    format: DiskFormat,
+    system_path: Option<String>,
+    arguments: Vec<String>,
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        format: DiskFormat::default(),
+        system_path: None,
+        arguments: vec![],
+    };
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for '{}'", arg))
+        };
+
+        match arg.as_str() {
+            "-f" | "--format" => {
+                let name = value()?;
+                options.format = DiskFormat::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown disk format '{}', expected one of {}",
+                        name,
+                        FORMAT_NAMES.join(", ")
+                    )
+                })?;
+            }
+            "-s" | "--system" => options.system_path = Some(value()?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => options.arguments.push(arg),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Parses a file name such as `3:NOTES.TXT` into a user number and name
+fn parse_file(text: &str, wildcards: bool) -> Result<(u8, FileName), String> {
+    let (user, name) = match text.split_once(':') {
+        Some((user, name)) => (
+            user.parse()
+                .ok()
+                .filter(|user| *user <= 15)
+                .ok_or_else(|| format!("invalid user number '{}'", user))?,
+            name,
+        ),
+        None => (0, text),
+    };
+
+    let name = FileName::parse(name, wildcards)
+        .ok_or_else(|| format!("invalid CP/M file name '{}'", name))?;
+
+    Ok((user, name))
+}
+
+fn open(path: &str, options: &Options) -> Result<FileSystem, String> {
+    DiskImage::load(path, options.format.clone()).map(FileSystem::new)
+}
+
+fn list(filesystem: &FileSystem) {
+    let files = filesystem.list();
+
+    for file in &files {
+        let attributes = match (file.read_only, file.system) {
+            (true, true) => "R/O SYS",
+            (true, false) => "R/O",
+            (false, true) => "SYS",
+            (false, false) => "",
+        };
+
+        let line = format!(
+            "{:>2}: {:<12} {:>7} {:>5}K  {}",
+            file.user,
+            file.name.to_string(),
+            file.get_size(),
+            file.allocated / 1024,
+            attributes
+        );
+
+        println!("{}", line.trim_end());
+    }
+
+    println!(
+        "{} files, {}K free",
+        files.len(),
+        filesystem.get_free_space() / 1024
+    );
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let arguments: Vec<_> = options.arguments.iter().map(String::as_str).collect();
+
+    match arguments[..] {
+        ["list", image_path] => list(&open(image_path, options)?),
+        ["get", image_path, name] | ["get", image_path, name, _] => {
+            let (user, name) = parse_file(name, false)?;
+            let data = open(image_path, options)?.read_file(user, &name)?;
+            let path = arguments
+                .get(3)
+                .map_or(name.to_string(), |path| path.to_string());
+
+            fs::write(&path, data)
+                .map_err(|error| format!("cannot write '{}': {}", path, error))?;
+        }
+        ["put", image_path, path] | ["put", image_path, path, _] => {
+            let data =
+                fs::read(path).map_err(|error| format!("cannot read '{}': {}", path, error))?;
+            let name = match arguments.get(3) {
+                Some(name) => name.to_string(),
+                None => Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            };
+            let (user, name) = parse_file(&name, false)?;
+            let mut filesystem = open(image_path, options)?;
+
+            filesystem.write_file(user, &name, &data)?;
+            filesystem.get_image().save(image_path)?;
+        }
+        ["delete", image_path, pattern] => {
+            let (user, pattern) = parse_file(pattern, true)?;
+            let mut filesystem = open(image_path, options)?;
+
+            if filesystem.delete_files(user, &pattern) == 0 {
+                return Err(format!("no files match {}:{}", user, pattern));
+            }
+
+            filesystem.get_image().save(image_path)?;
+        }
+        ["format", image_path] => {
+            let mut image = DiskImage::new(options.format.clone());
+
+            if let Some(path) = &options.system_path {
+                let system =
+                    fs::read(path).map_err(|error| format!("cannot read '{}': {}", path, error))?;
+
+                if system.len() > SYSTEM_SIZE {
+                    return Err(format!(
+                        "system of {} bytes is larger than {} bytes",
+                        system.len(),
+                        SYSTEM_SIZE
+                    ));
+                }
+
+                image.write_system(&system)?;
+            }
+
+            image.save(image_path)?;
+        }
+        [] => return Err(String::from("missing command")),
+        [command, ..] => return Err(format!("invalid arguments for '{}'", command)),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1).collect()).unwrap_or_else(|message| {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    });
+
+    if let Err(message) = run(&options) {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
@@ -1,20 +1,72 @@
-use std::fs;
+use std::{env, path::Path, process};
 
-use emulator_8080::system::test::TestSystem;
+use emulator_8080::system::exerciser::{Exerciser, Verdict, EXERCISERS};
+
+const USAGE: &str = "\
+Usage: run_test_suite [OPTIONS] [ROM...]
+
+Runs the CPU exercisers in test_roms/, or only those named (TST8080, 8080PRE, CPUTEST,
+8080EXM), printing their console output and a verdict for each.
+
+Options:
+  -d, --directory <DIR>     Directory holding the test ROMs (default: ./test_roms)
+  -q, --quiet               Only print verdicts, not console output
+  -h, --help                Print this message";
 
 fn main() {
-    let test_rom = fs::read("./test_roms/CPUTEST.COM").expect("Cannot load test ROM TST8080.COM!");
+    let mut directory = String::from("./test_roms");
+    let mut quiet = false;
+    let mut exercisers = vec![];
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--directory" => {
+                directory = args.next().unwrap_or_else(|| {
+                    eprintln!("error: missing value for '{}'\n\n{}", arg, USAGE);
+                    process::exit(2);
+                })
+            }
+            "-q" | "--quiet" => quiet = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => exercisers.push(Exerciser::find(&arg).unwrap_or_else(|| {
+                eprintln!("error: unknown test ROM '{}'\n\n{}", arg, USAGE);
+                process::exit(2);
+            })),
+        }
+    }
 
-    let mut system = TestSystem::new();
+    if exercisers.is_empty() {
+        exercisers = EXERCISERS.iter().collect();
+    }
+
+    let mut failures = 0;
+
+    for exerciser in exercisers {
+        let program = exerciser
+            .load(Path::new(&directory))
+            .unwrap_or_else(|message| {
+                eprintln!("error: {}", message);
+                process::exit(1);
+            });
 
-    system.load_test_program(test_rom);
+        let report = exerciser.run(program, usize::MAX);
 
-    let mut instruction_count: usize = 0;
+        if !quiet {
+            println!("{}", report.output.trim_end());
+        }
 
-    while system.state.enabled {
-        instruction_count += 1;
-        system.run_current_instruction();
+        println!("{}", report);
+
+        if let Verdict::Failed(_) = report.verdict {
+            failures += 1;
+        }
     }
 
-    println!("Instruction count: {}", instruction_count);
+    if failures > 0 {
+        process::exit(1);
+    }
 }
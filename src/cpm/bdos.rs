@@ -0,0 +1,642 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    devices::console::Console,
+    internal::{
+        instructions::{Register, RegisterPair},
+        memory::AddressableMemory,
+        state::State,
+    },
+};
+
+use super::fcb::{self, FileName, FCB_SIZE, RECORD_SIZE};
+
+/// Version reported by function 12, CP/M 2.2
+const VERSION: u16 = 0x0022;
+
+/// Address of the record buffer after a disk system reset
+pub const DEFAULT_DMA: u16 = 0x0080;
+
+/// Address of the I/O byte in page zero
+const IOBYTE: u16 = 0x0003;
+
+/// Return code of directory functions when no file matched
+const NOT_FOUND: u8 = 0xFF;
+
+/// Return code of reads at the end of a file
+const END_OF_FILE: u8 = 0x01;
+
+/// Return code of random reads of records which were never written
+const UNWRITTEN_DATA: u8 = 0x01;
+
+/// Return code of writes which could not be completed
+const DISK_FULL: u8 = 0x02;
+
+/// Return code of random accesses beyond the 65536 records of a file
+const SEEK_PAST_END: u8 = 0x06;
+
+const CONTROL_C: u8 = 0x03;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+const END_OF_TEXT: u8 = 0x1A;
+
+/// Outcome of a BDOS call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BdosCall {
+    /// The call returned to the program
+    Returned,
+    /// The program asked for a warm boot, through function 0 or a control-C
+    /// at the start of a line
+    Exited,
+}
+
+/// File found in a drive directory
+struct DirectoryFile {
+    name: FileName,
+    path: PathBuf,
+    records: usize,
+}
+
+fn get_records(length: u64) -> usize {
+    (length as usize).div_ceil(RECORD_SIZE)
+}
+
+/// Lists the files of `directory` with valid CP/M names matching `pattern`,
+/// sorted by name
+fn list_files(directory: &Path, pattern: &FileName) -> Vec<DirectoryFile> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return vec![];
+    };
+
+    let mut files: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+
+            if !metadata.is_file() {
+                return None;
+            }
+
+            let name = FileName::parse(entry.file_name().to_str()?, false)?;
+
+            name.matches(pattern).then(|| DirectoryFile {
+                name,
+                path: entry.path(),
+                records: get_records(metadata.len()),
+            })
+        })
+        .collect();
+
+    files.sort_by_key(|file| file.name);
+    files
+}
+
+/// CP/M 2.2 BDOS serviced on the host, with each drive backed by a host
+/// directory
+///
+/// Files are looked up by name on every call rather than held open, so
+/// programs which never close their files lose nothing. Every user number
+/// sees the same files.
+pub struct Bdos {
+    console: Box<dyn Console>,
+    drives: [Option<PathBuf>; 16],
+    current_drive: u8,
+    user: u8,
+    dma: u16,
+    search_results: VecDeque<DirectoryFile>,
+}
+
+impl Bdos {
+    pub fn new(console: impl Console + 'static) -> Self {
+        Bdos {
+            console: Box::new(console),
+            drives: Default::default(),
+            current_drive: 0,
+            user: 0,
+            dma: DEFAULT_DMA,
+            search_results: VecDeque::new(),
+        }
+    }
+
+    /// Backs `drive`, 0 for A: to 15 for P:, with a host directory
+    pub fn set_drive(&mut self, drive: u8, directory: impl Into<PathBuf>) {
+        self.drives[drive as usize & 0x0F] = Some(directory.into());
+    }
+
+    pub fn get_current_drive(&self) -> u8 {
+        self.current_drive
+    }
+
+    pub fn get_dma(&self) -> u16 {
+        self.dma
+    }
+
+    /// Performs the function in register C with the parameter in E or DE,
+    /// returning its result in A and HL, and in B and L for compatibility
+    pub fn call(&mut self, state: &mut State) -> BdosCall {
+        let function = state.get_register(&Register::C);
+        let parameter = state.get_register_pair(&RegisterPair::DE);
+        let byte_parameter = parameter as u8;
+
+        let result: u16 = match function {
+            0 => return BdosCall::Exited,
+            1 => {
+                let character = self.read_console();
+                self.echo(character);
+                character as u16
+            }
+            2 => {
+                self.console.write(byte_parameter);
+                0
+            }
+            3 => END_OF_TEXT as u16,
+            4 | 5 => 0,
+            6 => match byte_parameter {
+                0xFF => {
+                    if self.console.is_ready() {
+                        self.read_console() as u16
+                    } else {
+                        0
+                    }
+                }
+                0xFE => self.get_console_status() as u16,
+                _ => {
+                    self.console.write(byte_parameter);
+                    0
+                }
+            },
+            7 => state.memory.get(IOBYTE) as u16,
+            8 => {
+                state.memory.set(IOBYTE, byte_parameter);
+                0
+            }
+            9 => {
+                self.print_string(state, parameter);
+                0
+            }
+            10 => {
+                if !self.read_buffer(state, parameter) {
+                    return BdosCall::Exited;
+                }
+
+                0
+            }
+            11 => self.get_console_status() as u16,
+            12 => VERSION,
+            13 => {
+                self.current_drive = 0;
+                self.dma = DEFAULT_DMA;
+                0
+            }
+            14 => self.select_drive(byte_parameter) as u16,
+            24 => self.get_login_vector(),
+            25 => self.current_drive as u16,
+            26 => {
+                self.dma = parameter;
+                0
+            }
+            // Allocation vector, write protection and disk parameters are
+            // meaningless for host directories
+            27..=29 | 31 => 0,
+            32 => {
+                if byte_parameter == 0xFF {
+                    self.user as u16
+                } else {
+                    self.user = byte_parameter & 0x0F;
+                    0
+                }
+            }
+            15..=36 | 40 => self.call_file_function(state, function, parameter) as u16,
+            _ => 0,
+        };
+
+        let [high, low] = result.to_be_bytes();
+
+        state.set_register_pair(&RegisterPair::HL, result);
+        state.set_register(&Register::A, low);
+        state.set_register(&Register::B, high);
+
+        BdosCall::Returned
+    }
+
+    fn read_console(&mut self) -> u8 {
+        self.console.read().unwrap_or(END_OF_TEXT)
+    }
+
+    fn get_console_status(&mut self) -> u8 {
+        if self.console.is_ready() {
+            0xFF
+        } else {
+            0x00
+        }
+    }
+
+    /// Echoes printable characters and line endings, as the console input
+    /// functions do
+    fn echo(&mut self, character: u8) {
+        if character >= b' ' || matches!(character, b'\r' | b'\n' | b'\t' | BACKSPACE) {
+            self.console.write(character);
+        }
+    }
+
+    fn print_string(&mut self, state: &State, address: u16) {
+        let mut address = address;
+
+        loop {
+            let character = state.memory.get(address);
+
+            if character == b'$' {
+                break;
+            }
+
+            self.console.write(character);
+            address = address.wrapping_add(1);
+        }
+    }
+
+    /// Reads an edited line into the buffer at `address`, which holds its
+    /// capacity followed by the length read and the characters, returning
+    /// false if the line was abandoned with control-C
+    fn read_buffer(&mut self, state: &mut State, address: u16) -> bool {
+        let capacity = state.memory.get(address) as usize;
+        let mut line: Vec<u8> = vec![];
+
+        while line.len() < capacity {
+            let Some(character) = self.console.read() else {
+                break;
+            };
+
+            match character {
+                b'\r' | b'\n' => break,
+                CONTROL_C if line.is_empty() => {
+                    self.console.write(b'^');
+                    self.console.write(b'C');
+                    return false;
+                }
+                BACKSPACE | DELETE => {
+                    if line.pop().is_some() {
+                        self.console.write(BACKSPACE);
+                        self.console.write(b' ');
+                        self.console.write(BACKSPACE);
+                    }
+                }
+                _ => {
+                    self.echo(character);
+                    line.push(character);
+                }
+            }
+        }
+
+        self.console.write(b'\r');
+
+        state.memory.set(address.wrapping_add(1), line.len() as u8);
+
+        for (offset, character) in line.into_iter().enumerate() {
+            state
+                .memory
+                .set(address.wrapping_add(2 + offset as u16), character);
+        }
+
+        true
+    }
+
+    fn get_login_vector(&self) -> u16 {
+        self.drives
+            .iter()
+            .enumerate()
+            .filter(|(_, directory)| directory.is_some())
+            .fold(0, |vector, (drive, _)| vector | 1 << drive)
+    }
+
+    fn select_drive(&mut self, drive: u8) -> u8 {
+        if self
+            .drives
+            .get(drive as usize)
+            .map_or(true, Option::is_none)
+        {
+            return NOT_FOUND;
+        }
+
+        self.current_drive = drive;
+        0
+    }
+
+    /// Directory of the drive named by the first byte of a file control
+    /// block, 0 for the current drive
+    fn get_directory(&self, drive: u8) -> Option<&Path> {
+        let drive = match drive {
+            0 | b'?' => self.current_drive,
+            _ => (drive - 1) & 0x0F,
+        };
+
+        self.drives[drive as usize].as_deref()
+    }
+
+    fn call_file_function(&mut self, state: &mut State, function: u8, address: u16) -> u8 {
+        let mut fcb: Vec<u8> = (0..FCB_SIZE as u16)
+            .map(|offset| state.memory.get(address.wrapping_add(offset)))
+            .collect();
+        let name = FileName::from_bytes(&fcb[fcb::NAME..]);
+
+        let result = match self.get_directory(fcb[fcb::DRIVE]).map(Path::to_path_buf) {
+            None if function == 18 => self.search_next(state),
+            None => NOT_FOUND,
+            Some(directory) => match function {
+                15 => self.open(&directory, &name, &mut fcb),
+                16 | 30 => Self::find(&directory, &name).map_or(NOT_FOUND, |_| 0),
+                17 => self.search_first(state, &directory, &name, fcb[fcb::DRIVE] == b'?'),
+                18 => self.search_next(state),
+                19 => Self::delete(&directory, &name),
+                20 => self.read_sequential(state, &directory, &name, &mut fcb),
+                21 => self.write_sequential(state, &directory, &name, &mut fcb),
+                22 => Self::make(&directory, &name, &mut fcb),
+                23 => Self::rename(&directory, &name, &fcb),
+                33 => self.read_random(state, &directory, &name, &mut fcb),
+                34 | 40 => self.write_random(state, &directory, &name, &mut fcb),
+                35 => match Self::find(&directory, &name) {
+                    Some(file) => {
+                        fcb::set_random_record(&mut fcb, file.records);
+                        0
+                    }
+                    None => NOT_FOUND,
+                },
+                36 => {
+                    let record = fcb::get_position(&fcb);
+                    fcb::set_random_record(&mut fcb, record);
+                    0
+                }
+                _ => 0,
+            },
+        };
+
+        // Write back the fields a file function may update, leaving the
+        // random record of a 33-byte FCB untouched unless it was used
+        let length = if matches!(function, 33..=36 | 40) {
+            FCB_SIZE
+        } else {
+            fcb::RANDOM_RECORD
+        };
+
+        for (offset, byte) in fcb.iter().take(length).enumerate() {
+            state.memory.set(address.wrapping_add(offset as u16), *byte);
+        }
+
+        result
+    }
+
+    fn find(directory: &Path, name: &FileName) -> Option<DirectoryFile> {
+        list_files(directory, name).into_iter().next()
+    }
+
+    fn open(&self, directory: &Path, name: &FileName, fcb: &mut [u8]) -> u8 {
+        let Some(file) = Self::find(directory, name) else {
+            return NOT_FOUND;
+        };
+
+        // Opening selects the extent in the FCB, keeping its current record
+        let current_record = fcb[fcb::CURRENT_RECORD];
+        fcb[fcb::CURRENT_RECORD] = 0;
+
+        let record = fcb::get_position(fcb);
+
+        if record > 0 && record >= file.records {
+            return NOT_FOUND;
+        }
+
+        fcb[fcb::NAME..fcb::NAME + 11].copy_from_slice(&file.name.to_bytes());
+        fcb[fcb::EXTENT + 1] = 0;
+        fcb[fcb::RECORD_COUNT + 1..fcb::CURRENT_RECORD].fill(0);
+
+        fcb::set_position(fcb, record, file.records);
+        fcb[fcb::CURRENT_RECORD] = current_record;
+
+        0
+    }
+
+    fn search_first(
+        &mut self,
+        state: &mut State,
+        directory: &Path,
+        name: &FileName,
+        any: bool,
+    ) -> u8 {
+        let pattern = if any {
+            FileName::parse("*.*", true).unwrap()
+        } else {
+            *name
+        };
+
+        self.search_results = list_files(directory, &pattern).into();
+        self.search_next(state)
+    }
+
+    /// Writes the directory entry of the next search result to the start of
+    /// the DMA buffer
+    fn search_next(&mut self, state: &mut State) -> u8 {
+        let Some(file) = self.search_results.pop_front() else {
+            return NOT_FOUND;
+        };
+
+        let last_extent = file.records.saturating_sub(1) / fcb::RECORDS_PER_EXTENT;
+        let mut entry = [0; 32];
+
+        entry[0] = self.user;
+        entry[fcb::NAME..fcb::NAME + 11].copy_from_slice(&file.name.to_bytes());
+        entry[fcb::EXTENT] = (last_extent % fcb::EXTENTS_PER_MODULE) as u8;
+        entry[fcb::MODULE] = (last_extent / fcb::EXTENTS_PER_MODULE) as u8;
+        entry[fcb::RECORD_COUNT] = (file.records - last_extent * fcb::RECORDS_PER_EXTENT)
+            .min(fcb::RECORDS_PER_EXTENT) as u8;
+
+        for (offset, byte) in entry.iter().enumerate() {
+            state
+                .memory
+                .set(self.dma.wrapping_add(offset as u16), *byte);
+        }
+
+        0
+    }
+
+    fn delete(directory: &Path, name: &FileName) -> u8 {
+        let files = list_files(directory, name);
+
+        if files.is_empty() {
+            return NOT_FOUND;
+        }
+
+        for file in files {
+            let _ = fs::remove_file(file.path);
+        }
+
+        0
+    }
+
+    fn make(directory: &Path, name: &FileName, fcb: &mut [u8]) -> u8 {
+        if name.is_ambiguous() {
+            return NOT_FOUND;
+        }
+
+        let path = match Self::find(directory, name) {
+            Some(file) => file.path,
+            None => directory.join(name.to_string()),
+        };
+
+        if File::create(path).is_err() {
+            return NOT_FOUND;
+        }
+
+        fcb[fcb::EXTENT + 1..fcb::CURRENT_RECORD].fill(0);
+        fcb[fcb::CURRENT_RECORD] = 0;
+
+        0
+    }
+
+    fn rename(directory: &Path, name: &FileName, fcb: &[u8]) -> u8 {
+        let new_name = FileName::from_bytes(&fcb[16 + fcb::NAME..]);
+
+        if new_name.is_ambiguous() || Self::find(directory, &new_name).is_some() {
+            return NOT_FOUND;
+        }
+
+        match Self::find(directory, name) {
+            Some(file) => match fs::rename(file.path, directory.join(new_name.to_string())) {
+                Ok(()) => 0,
+                Err(_) => NOT_FOUND,
+            },
+            None => NOT_FOUND,
+        }
+    }
+
+    /// Reads `record` into the DMA buffer, padding a short final record with
+    /// end of text characters
+    fn read_record(&self, state: &mut State, path: &Path, record: usize) -> bool {
+        let mut buffer = [END_OF_TEXT; RECORD_SIZE];
+
+        let result = File::open(path).and_then(|mut file| {
+            file.seek(SeekFrom::Start((record * RECORD_SIZE) as u64))?;
+
+            let mut length = 0;
+
+            while length < RECORD_SIZE {
+                match file.read(&mut buffer[length..])? {
+                    0 => break,
+                    count => length += count,
+                }
+            }
+
+            Ok(length)
+        });
+
+        if !matches!(result, Ok(length) if length > 0) {
+            return false;
+        }
+
+        for (offset, byte) in buffer.iter().enumerate() {
+            state
+                .memory
+                .set(self.dma.wrapping_add(offset as u16), *byte);
+        }
+
+        true
+    }
+
+    /// Writes the DMA buffer to `record`, extending the file with zeros if
+    /// it ends before that record
+    fn write_record(&self, state: &State, path: &Path, record: usize) -> bool {
+        let buffer: Vec<u8> = (0..RECORD_SIZE as u16)
+            .map(|offset| state.memory.get(self.dma.wrapping_add(offset)))
+            .collect();
+
+        OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start((record * RECORD_SIZE) as u64))?;
+                file.write_all(&buffer)
+            })
+            .is_ok()
+    }
+
+    fn read_sequential(
+        &self,
+        state: &mut State,
+        directory: &Path,
+        name: &FileName,
+        fcb: &mut [u8],
+    ) -> u8 {
+        let Some(file) = Self::find(directory, name) else {
+            return NOT_FOUND;
+        };
+        let record = fcb::get_position(fcb);
+
+        if record >= file.records || !self.read_record(state, &file.path, record) {
+            return END_OF_FILE;
+        }
+
+        fcb::set_position(fcb, record + 1, file.records);
+        0
+    }
+
+    fn write_sequential(
+        &self,
+        state: &State,
+        directory: &Path,
+        name: &FileName,
+        fcb: &mut [u8],
+    ) -> u8 {
+        let Some(file) = Self::find(directory, name) else {
+            return NOT_FOUND;
+        };
+        let record = fcb::get_position(fcb);
+
+        if !self.write_record(state, &file.path, record) {
+            return DISK_FULL;
+        }
+
+        fcb::set_position(fcb, record + 1, file.records.max(record + 1));
+        0
+    }
+
+    fn read_random(
+        &self,
+        state: &mut State,
+        directory: &Path,
+        name: &FileName,
+        fcb: &mut [u8],
+    ) -> u8 {
+        let Some(record) = fcb::get_random_record(fcb) else {
+            return SEEK_PAST_END;
+        };
+        let Some(file) = Self::find(directory, name) else {
+            return NOT_FOUND;
+        };
+
+        fcb::set_position(fcb, record, file.records);
+
+        if record >= file.records || !self.read_record(state, &file.path, record) {
+            return UNWRITTEN_DATA;
+        }
+
+        0
+    }
+
+    fn write_random(&self, state: &State, directory: &Path, name: &FileName, fcb: &mut [u8]) -> u8 {
+        let Some(record) = fcb::get_random_record(fcb) else {
+            return SEEK_PAST_END;
+        };
+        let Some(file) = Self::find(directory, name) else {
+            return NOT_FOUND;
+        };
+
+        if !self.write_record(state, &file.path, record) {
+            return DISK_FULL;
+        }
+
+        fcb::set_position(fcb, record, file.records.max(record + 1));
+        0
+    }
+}
@@ -0,0 +1,417 @@
+use std::{fs, path::Path};
+
+/// Bytes in a sector, the unit the BIOS transfers
+pub const SECTOR_SIZE: usize = 128;
+
+/// Fill byte of freshly formatted sectors, which also marks unused
+/// directory entries
+pub const EMPTY: u8 = 0xE5;
+
+/// CP/M 2.2 disk parameter block, describing the file system layout of a
+/// drive to the BDOS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskParameterBlock {
+    /// 128-byte records per track (SPT)
+    pub sectors_per_track: u16,
+    /// log2 of the records in an allocation block (BSH)
+    pub block_shift: u8,
+    /// Records in an allocation block minus one (BLM)
+    pub block_mask: u8,
+    /// Logical extents per directory entry minus one (EXM)
+    pub extent_mask: u8,
+    /// Number of the last allocation block (DSM)
+    pub max_block: u16,
+    /// Number of the last directory entry (DRM)
+    pub max_directory_entry: u16,
+    /// Blocks reserved for the directory, from the most significant bit (AL0
+    /// and AL1)
+    pub directory_allocation: u16,
+    /// Directory entries checked for disk changes (CKS)
+    pub check_size: u16,
+    /// Tracks reserved for the system before the directory (OFF)
+    pub reserved_tracks: u16,
+}
+
+impl DiskParameterBlock {
+    /// Lays out a file system over `tracks` of `sectors_per_track` records,
+    /// following the rules of the CP/M 2.2 alteration guide
+    pub fn new(
+        sectors_per_track: u16,
+        tracks: u16,
+        reserved_tracks: u16,
+        block_size: usize,
+        directory_entries: usize,
+        removable: bool,
+    ) -> Result<Self, String> {
+        if !matches!(block_size, 1024 | 2048 | 4096 | 8192 | 16384) {
+            return Err(format!("invalid block size {}", block_size));
+        }
+
+        let records = tracks.saturating_sub(reserved_tracks) as usize * sectors_per_track as usize;
+        let blocks = records * SECTOR_SIZE / block_size;
+        let directory_blocks = (directory_entries * 32).div_ceil(block_size);
+
+        if blocks == 0 || blocks > 0x10000 {
+            return Err(format!("{} blocks cannot be addressed", blocks));
+        }
+
+        if directory_entries == 0 || directory_blocks > 16 || directory_blocks >= blocks {
+            return Err(format!(
+                "{} directory entries do not fit",
+                directory_entries
+            ));
+        }
+
+        // Directory entries hold 16 one-byte block numbers on small disks
+        // and 8 two-byte block numbers on large ones
+        let extent_mask = if blocks <= 256 {
+            block_size / 1024 - 1
+        } else if block_size == 1024 {
+            return Err(String::from("1024-byte blocks need fewer than 257 blocks"));
+        } else {
+            block_size / 2048 - 1
+        };
+
+        Ok(DiskParameterBlock {
+            sectors_per_track,
+            block_shift: (block_size / SECTOR_SIZE).trailing_zeros() as u8,
+            block_mask: (block_size / SECTOR_SIZE - 1) as u8,
+            extent_mask: extent_mask as u8,
+            max_block: (blocks - 1) as u16,
+            max_directory_entry: (directory_entries - 1) as u16,
+            directory_allocation: !0xFFFFu16.checked_shr(directory_blocks as u32).unwrap_or(0),
+            check_size: if removable {
+                directory_entries.div_ceil(4) as u16
+            } else {
+                0
+            },
+            reserved_tracks,
+        })
+    }
+
+    pub fn get_block_size(&self) -> usize {
+        SECTOR_SIZE << self.block_shift
+    }
+
+    pub fn get_directory_entries(&self) -> usize {
+        self.max_directory_entry as usize + 1
+    }
+
+    pub fn get_directory_blocks(&self) -> usize {
+        self.directory_allocation.count_ones() as usize
+    }
+
+    /// Whether block numbers in directory entries take two bytes
+    pub fn has_large_blocks(&self) -> bool {
+        self.max_block > 0xFF
+    }
+
+    /// Bytes of the allocation vector the BIOS reserves for this drive
+    pub fn get_allocation_vector_size(&self) -> usize {
+        self.max_block as usize / 8 + 1
+    }
+
+    /// Encodes the block in the layout the BDOS reads from memory
+    pub fn to_bytes(&self) -> [u8; 15] {
+        let [spt_low, spt_high] = self.sectors_per_track.to_le_bytes();
+        let [dsm_low, dsm_high] = self.max_block.to_le_bytes();
+        let [drm_low, drm_high] = self.max_directory_entry.to_le_bytes();
+        let [al0, al1] = self.directory_allocation.to_be_bytes();
+        let [cks_low, cks_high] = self.check_size.to_le_bytes();
+        let [off_low, off_high] = self.reserved_tracks.to_le_bytes();
+
+        [
+            spt_low,
+            spt_high,
+            self.block_shift,
+            self.block_mask,
+            self.extent_mask,
+            dsm_low,
+            dsm_high,
+            drm_low,
+            drm_high,
+            al0,
+            al1,
+            cks_low,
+            cks_high,
+            off_low,
+            off_high,
+        ]
+    }
+}
+
+/// Physical geometry of a disk along with its file system layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskFormat {
+    pub name: &'static str,
+    pub tracks: u16,
+    /// Distance between logically consecutive sectors, 1 for none
+    pub skew: u16,
+    pub parameters: DiskParameterBlock,
+}
+
+/// Names accepted by `DiskFormat::from_name`
+pub const FORMAT_NAMES: [&str; 2] = ["ibm-3740", "z80pack-hd"];
+
+impl DiskFormat {
+    /// Standard 8" single sided, single density disk of 77 tracks of 26
+    /// sectors, with a skew of 6 and two system tracks
+    pub fn ibm_3740() -> Self {
+        DiskFormat {
+            name: "ibm-3740",
+            tracks: 77,
+            skew: 6,
+            parameters: DiskParameterBlock::new(26, 77, 2, 1024, 64, true)
+                .expect("standard format is valid"),
+        }
+    }
+
+    /// 4MB hard disk of 255 tracks of 128 sectors, as used by z80pack
+    pub fn z80pack_hd() -> Self {
+        DiskFormat {
+            name: "z80pack-hd",
+            tracks: 255,
+            skew: 1,
+            parameters: DiskParameterBlock::new(128, 255, 0, 2048, 1024, false)
+                .expect("standard format is valid"),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ibm-3740" | "8sssd" => Some(Self::ibm_3740()),
+            "z80pack-hd" | "hd" => Some(Self::z80pack_hd()),
+            _ => None,
+        }
+    }
+
+    pub fn get_sectors_per_track(&self) -> usize {
+        self.parameters.sectors_per_track as usize
+    }
+
+    /// Size of an image file of this format in bytes
+    pub fn get_size(&self) -> usize {
+        self.tracks as usize * self.get_sectors_per_track() * SECTOR_SIZE
+    }
+
+    /// Physical sector number, counted from 1, of each logical sector of a
+    /// track, as held in the BIOS translation table
+    ///
+    /// Each logical sector is `skew` sectors after the previous one, moving
+    /// on by one whenever that sector is already taken.
+    pub fn get_translation_table(&self) -> Vec<u8> {
+        let sectors = self.get_sectors_per_track();
+        let mut taken = vec![false; sectors];
+        let mut table = Vec::with_capacity(sectors);
+        let mut sector = 0;
+
+        for _ in 0..sectors {
+            while taken[sector] {
+                sector = (sector + 1) % sectors;
+            }
+
+            taken[sector] = true;
+            table.push((sector + 1) as u8);
+            sector = (sector + self.skew as usize) % sectors;
+        }
+
+        table
+    }
+}
+
+impl Default for DiskFormat {
+    fn default() -> Self {
+        Self::ibm_3740()
+    }
+}
+
+/// Disk image held in memory, with sectors stored in physical order
+pub struct DiskImage {
+    format: DiskFormat,
+    data: Vec<u8>,
+}
+
+impl DiskImage {
+    /// Creates a freshly formatted disk
+    pub fn new(format: DiskFormat) -> Self {
+        let data = vec![EMPTY; format.get_size()];
+
+        DiskImage { format, data }
+    }
+
+    /// Wraps the contents of an image file, padding a short image as if the
+    /// missing sectors were freshly formatted
+    pub fn from_bytes(format: DiskFormat, mut data: Vec<u8>) -> Result<Self, String> {
+        if data.len() > format.get_size() {
+            return Err(format!(
+                "image of {} bytes is larger than the {} bytes of {}",
+                data.len(),
+                format.get_size(),
+                format.name
+            ));
+        }
+
+        data.resize(format.get_size(), EMPTY);
+
+        Ok(DiskImage { format, data })
+    }
+
+    pub fn load(path: impl AsRef<Path>, format: DiskFormat) -> Result<Self, String> {
+        let path = path.as_ref();
+        let data = fs::read(path)
+            .map_err(|error| format!("cannot read '{}': {}", path.display(), error))?;
+
+        DiskImage::from_bytes(format, data)
+            .map_err(|message| format!("{}: {}", path.display(), message))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+
+        fs::write(path, &self.data)
+            .map_err(|error| format!("cannot write '{}': {}", path.display(), error))
+    }
+
+    pub fn get_format(&self) -> &DiskFormat {
+        &self.format
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Byte offset of a physical sector, counted from 0, in the image
+    pub fn get_offset(&self, track: usize, sector: usize) -> Option<usize> {
+        if track >= self.format.tracks as usize || sector >= self.format.get_sectors_per_track() {
+            return None;
+        }
+
+        Some((track * self.format.get_sectors_per_track() + sector) * SECTOR_SIZE)
+    }
+
+    pub fn read_sector(&self, track: usize, sector: usize) -> Option<&[u8]> {
+        let offset = self.get_offset(track, sector)?;
+
+        Some(&self.data[offset..offset + SECTOR_SIZE])
+    }
+
+    /// Writes the first `SECTOR_SIZE` bytes of `data`, returning false if the
+    /// sector does not exist or `data` is too short
+    pub fn write_sector(&mut self, track: usize, sector: usize, data: &[u8]) -> bool {
+        let (Some(offset), Some(data)) = (self.get_offset(track, sector), data.get(..SECTOR_SIZE))
+        else {
+            return false;
+        };
+
+        self.data[offset..offset + SECTOR_SIZE].copy_from_slice(data);
+        true
+    }
+
+    /// Locations of the sectors holding `size` bytes of system, which start
+    /// after the boot sector and run unskewed through the reserved tracks
+    fn get_system_sectors(&self, size: usize) -> Result<Vec<(usize, usize)>, String> {
+        let sectors_per_track = self.format.get_sectors_per_track();
+        let capacity = self.format.parameters.reserved_tracks as usize * sectors_per_track;
+        let sectors = size.div_ceil(SECTOR_SIZE);
+
+        if sectors >= capacity {
+            return Err(format!(
+                "system tracks of {} hold {} bytes, not {}",
+                self.format.name,
+                capacity.saturating_sub(1) * SECTOR_SIZE,
+                size
+            ));
+        }
+
+        Ok((1..=sectors)
+            .map(|index| (index / sectors_per_track, index % sectors_per_track))
+            .collect())
+    }
+
+    /// Reads the CCP and BDOS from the system tracks
+    pub fn read_system(&self, size: usize) -> Result<Vec<u8>, String> {
+        let mut system: Vec<u8> = self
+            .get_system_sectors(size)?
+            .into_iter()
+            .flat_map(|(track, sector)| self.read_sector(track, sector).unwrap_or_default())
+            .copied()
+            .collect();
+
+        system.truncate(size);
+        Ok(system)
+    }
+
+    /// Writes a CCP and BDOS to the system tracks, as SYSGEN does
+    pub fn write_system(&mut self, system: &[u8]) -> Result<(), String> {
+        let sectors = self.get_system_sectors(system.len())?;
+
+        for ((track, sector), chunk) in sectors.into_iter().zip(system.chunks(SECTOR_SIZE)) {
+            let mut buffer = [0; SECTOR_SIZE];
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            self.write_sector(track, sector, &buffer);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_derive_standard_parameters() {
+        let format = DiskFormat::ibm_3740();
+
+        assert_eq!(
+            format.parameters.to_bytes(),
+            [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xC0, 0, 16, 0, 2, 0]
+        );
+        assert_eq!(format.get_size(), 256_256);
+        assert_eq!(
+            format.get_translation_table(),
+            [
+                1, 7, 13, 19, 25, 5, 11, 17, 23, 3, 9, 15, 21, 2, 8, 14, 20, 26, 6, 12, 18, 24, 4,
+                10, 16, 22
+            ]
+        );
+
+        let format = DiskFormat::z80pack_hd();
+
+        assert_eq!(
+            format.parameters.to_bytes(),
+            [128, 0, 4, 15, 0, 0xF7, 0x07, 0xFF, 0x03, 0xFF, 0xFF, 0, 0, 0, 0]
+        );
+        assert_eq!(format.get_translation_table()[..4], [1, 2, 3, 4]);
+        assert!(DiskParameterBlock::new(26, 77, 2, 1000, 64, true).is_err());
+    }
+
+    #[test]
+    fn should_read_and_write_sectors() {
+        let mut image = DiskImage::from_bytes(DiskFormat::ibm_3740(), vec![0; 300]).unwrap();
+
+        assert_eq!(image.read_sector(0, 2).unwrap()[..44], [0; 44]);
+        assert_eq!(image.read_sector(0, 2).unwrap()[44..], [EMPTY; 84]);
+        assert!(image.write_sector(1, 25, &[0xAA; SECTOR_SIZE]));
+        assert_eq!(image.as_bytes()[51 * SECTOR_SIZE], 0xAA);
+        assert!(!image.write_sector(77, 0, &[0; SECTOR_SIZE]));
+        assert!(!image.write_sector(0, 0, &[0; SECTOR_SIZE - 1]));
+        assert_eq!(image.read_sector(0, 26), None);
+    }
+
+    #[test]
+    fn should_read_and_write_system_tracks() {
+        let mut image = DiskImage::new(DiskFormat::ibm_3740());
+        let system: Vec<u8> = (0..=255).cycle().take(5000).collect();
+
+        image.write_system(&system).unwrap();
+
+        assert_eq!(image.read_system(5000).unwrap(), system);
+        assert_eq!(image.read_sector(1, 0).unwrap()[0], (25 * 128 % 256) as u8);
+        assert!(image.write_system(&[0; 51 * SECTOR_SIZE]).is_ok());
+        assert!(image.write_system(&[0; 52 * SECTOR_SIZE]).is_err());
+        assert!(DiskImage::new(DiskFormat::z80pack_hd())
+            .read_system(128)
+            .is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+
+/// Size of a file control block including the random record field
+pub const FCB_SIZE: usize = 36;
+
+/// Offsets of the fields of a file control block
+pub const DRIVE: usize = 0;
+pub const NAME: usize = 1;
+pub const EXTENT: usize = 12;
+pub const MODULE: usize = 14;
+pub const RECORD_COUNT: usize = 15;
+pub const CURRENT_RECORD: usize = 32;
+pub const RANDOM_RECORD: usize = 33;
+
+/// Bytes in a CP/M record, the unit of all file transfers
+pub const RECORD_SIZE: usize = 128;
+
+/// Records covered by one logical extent
+pub const RECORDS_PER_EXTENT: usize = 128;
+
+/// Logical extents in a module, counted by the S2 byte
+pub const EXTENTS_PER_MODULE: usize = 32;
+
+const INVALID_CHARACTERS: &[u8] = b"<>.,;:=?*[]_%|()/\\";
+
+/// 8.3 file name as held in file control blocks and directory entries,
+/// upper case and padded with spaces, where `?` matches any character
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileName {
+    pub name: [u8; 8],
+    pub extension: [u8; 3],
+}
+
+fn is_valid_character(character: u8) -> bool {
+    character.is_ascii_graphic() && !INVALID_CHARACTERS.contains(&character)
+}
+
+/// Pads `text` with spaces into `field`, expanding `*` into `?`, returning
+/// false if it does not fit or holds characters CP/M cannot use
+fn fill_field(field: &mut [u8], text: &str, wildcards: bool) -> bool {
+    let characters = text.bytes().map(|character| character.to_ascii_uppercase());
+
+    for (length, character) in characters.enumerate() {
+        match character {
+            b'*' if wildcards => {
+                field[length..].fill(b'?');
+                return true;
+            }
+            b'?' if wildcards => {}
+            _ if !is_valid_character(character) => return false,
+            _ => {}
+        }
+
+        if length == field.len() {
+            return false;
+        }
+
+        field[length] = character;
+    }
+
+    true
+}
+
+impl FileName {
+    /// Reads the 11 name bytes following the drive byte of a file control
+    /// block or directory entry, ignoring attribute bits
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut file_name = FileName {
+            name: [b' '; 8],
+            extension: [b' '; 3],
+        };
+
+        for (index, byte) in bytes[..11].iter().enumerate() {
+            let character = (byte & 0x7F).to_ascii_uppercase();
+
+            if index < 8 {
+                file_name.name[index] = character;
+            } else {
+                file_name.extension[index - 8] = character;
+            }
+        }
+
+        file_name
+    }
+
+    /// Parses a name such as `DUMP.COM`, or a pattern such as `*.AS?` when
+    /// `wildcards` is set
+    pub fn parse(text: &str, wildcards: bool) -> Option<Self> {
+        let (name, extension) = text.split_once('.').unwrap_or((text, ""));
+        let mut file_name = FileName {
+            name: [b' '; 8],
+            extension: [b' '; 3],
+        };
+
+        if name.is_empty()
+            || !fill_field(&mut file_name.name, name, wildcards)
+            || !fill_field(&mut file_name.extension, extension, wildcards)
+        {
+            return None;
+        }
+
+        Some(file_name)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 11] {
+        let mut bytes = [0; 11];
+        bytes[..8].copy_from_slice(&self.name);
+        bytes[8..].copy_from_slice(&self.extension);
+        bytes
+    }
+
+    pub fn is_ambiguous(&self) -> bool {
+        self.to_bytes().contains(&b'?')
+    }
+
+    /// Whether this name matches `pattern`, where `?` matches anything
+    pub fn matches(&self, pattern: &FileName) -> bool {
+        self.to_bytes()
+            .iter()
+            .zip(pattern.to_bytes())
+            .all(|(character, expected)| expected == b'?' || *character == expected)
+    }
+}
+
+impl fmt::Display for FileName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = String::from_utf8_lossy(&self.name);
+        let extension = String::from_utf8_lossy(&self.extension);
+
+        write!(f, "{}", name.trim_end())?;
+
+        if !extension.trim_end().is_empty() {
+            write!(f, ".{}", extension.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses a command line argument such as `B:FILE.TXT` into a drive number,
+/// 0 for the default drive or 1 for A:, and a file name
+pub fn parse_argument(text: &str) -> Option<(u8, FileName)> {
+    let (drive, name) = match text.as_bytes() {
+        [letter @ (b'A'..=b'P' | b'a'..=b'p'), b':', ..] => {
+            (letter.to_ascii_uppercase() - b'A' + 1, &text[2..])
+        }
+        _ => (0, text),
+    };
+
+    let file_name = if name.is_empty() {
+        FileName::from_bytes(&[b' '; 11])
+    } else {
+        FileName::parse(name, true)?
+    };
+
+    Some((drive, file_name))
+}
+
+/// Record number of the sequential position held in a file control block
+pub fn get_position(fcb: &[u8]) -> usize {
+    let extent = (fcb[MODULE] as usize & 0x3F) * EXTENTS_PER_MODULE + (fcb[EXTENT] as usize & 0x1F);
+
+    extent * RECORDS_PER_EXTENT + (fcb[CURRENT_RECORD] as usize).min(RECORDS_PER_EXTENT)
+}
+
+/// Moves the sequential position of a file control block to `record`, and
+/// sets its record count from the file length in records
+pub fn set_position(fcb: &mut [u8], record: usize, file_records: usize) {
+    let extent = record / RECORDS_PER_EXTENT;
+    let extent_start = extent * RECORDS_PER_EXTENT;
+
+    fcb[CURRENT_RECORD] = (record % RECORDS_PER_EXTENT) as u8;
+    fcb[EXTENT] = (extent % EXTENTS_PER_MODULE) as u8;
+    fcb[MODULE] = (extent / EXTENTS_PER_MODULE) as u8;
+    fcb[RECORD_COUNT] = file_records
+        .saturating_sub(extent_start)
+        .min(RECORDS_PER_EXTENT) as u8;
+}
+
+/// Record number held in the random record field, or `None` if it is beyond
+/// the 65536 records CP/M 2.2 can address
+pub fn get_random_record(fcb: &[u8]) -> Option<usize> {
+    if fcb[RANDOM_RECORD + 2] != 0 {
+        return None;
+    }
+
+    Some(u16::from_le_bytes([fcb[RANDOM_RECORD], fcb[RANDOM_RECORD + 1]]) as usize)
+}
+
+pub fn set_random_record(fcb: &mut [u8], record: usize) {
+    fcb[RANDOM_RECORD] = record as u8;
+    fcb[RANDOM_RECORD + 1] = (record >> 8) as u8;
+    fcb[RANDOM_RECORD + 2] = (record >> 16) as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_and_match_names() {
+        let name = FileName::parse("dump.com", false).unwrap();
+
+        assert_eq!(&name.to_bytes(), b"DUMP    COM");
+        assert_eq!(name.to_string(), "DUMP.COM");
+        assert_eq!(
+            FileName::parse("README", false).unwrap().to_string(),
+            "README"
+        );
+        assert_eq!(FileName::parse("TOOLONGNAME.TXT", false), None);
+        assert_eq!(FileName::parse("A.B.C", false), None);
+        assert_eq!(FileName::parse("*.COM", false), None);
+
+        let pattern = FileName::parse("*.C?M", true).unwrap();
+
+        assert_eq!(&pattern.to_bytes(), b"????????C?M");
+        assert!(pattern.is_ambiguous());
+        assert!(name.matches(&pattern));
+        assert!(!FileName::parse("DUMP.ASM", false)
+            .unwrap()
+            .matches(&pattern));
+
+        assert_eq!(
+            parse_argument("b:*.asm"),
+            Some((2, FileName::parse("????????.ASM", true).unwrap()))
+        );
+        assert_eq!(
+            parse_argument("X.TXT"),
+            Some((0, FileName::parse("X.TXT", false).unwrap()))
+        );
+    }
+
+    #[test]
+    fn should_track_positions_across_extents() {
+        let mut fcb = [0; FCB_SIZE];
+
+        set_position(&mut fcb, 4100, 5000);
+
+        assert_eq!(fcb[CURRENT_RECORD], 4);
+        assert_eq!(fcb[EXTENT], 0);
+        assert_eq!(fcb[MODULE], 1);
+        assert_eq!(fcb[RECORD_COUNT], 128);
+        assert_eq!(get_position(&fcb), 4100);
+
+        set_position(&mut fcb, 4890, 4900);
+
+        assert_eq!(fcb[EXTENT], 6);
+        assert_eq!(fcb[RECORD_COUNT], 36);
+        assert_eq!(get_random_record(&fcb), Some(0));
+
+        set_random_record(&mut fcb, 0x12345);
+
+        assert_eq!(get_random_record(&fcb), None);
+    }
+}
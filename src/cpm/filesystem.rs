@@ -0,0 +1,520 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    disk::{DiskFormat, DiskImage, DiskParameterBlock, EMPTY, SECTOR_SIZE},
+    fcb::{FileName, EXTENTS_PER_MODULE, RECORDS_PER_EXTENT},
+};
+
+/// Bytes in a directory entry
+const ENTRY_SIZE: usize = 32;
+
+/// Highest user number, entries above it are not files
+const MAX_USER: u8 = 15;
+
+/// Fill byte of the unused part of the last record of a file
+const END_OF_TEXT: u8 = 0x1A;
+
+/// Offsets of the fields of a directory entry
+const EXTENT: usize = 12;
+const MODULE: usize = 14;
+const RECORD_COUNT: usize = 15;
+const ALLOCATION: usize = 16;
+
+/// Directory entry describing up to 16KB of a file
+struct Extent {
+    slot: usize,
+    user: u8,
+    name: FileName,
+    read_only: bool,
+    system: bool,
+    /// Logical extent number of the last extent the entry covers
+    number: usize,
+    /// Records in the last logical extent
+    record_count: usize,
+    blocks: Vec<usize>,
+}
+
+/// File found in the directory of a disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    pub user: u8,
+    pub name: FileName,
+    pub records: usize,
+    /// Blocks allocated to the file, a multiple of the block size
+    pub allocated: usize,
+    pub read_only: bool,
+    pub system: bool,
+}
+
+impl FileEntry {
+    pub fn get_size(&self) -> usize {
+        self.records * SECTOR_SIZE
+    }
+}
+
+/// CP/M 2.2 file system on a disk image
+///
+/// Files are read and written whole, as raw records, so text files keep the
+/// end of text characters padding their last record.
+pub struct FileSystem {
+    image: DiskImage,
+    translation_table: Vec<u8>,
+}
+
+impl FileSystem {
+    pub fn new(image: DiskImage) -> Self {
+        let translation_table = image.get_format().get_translation_table();
+
+        FileSystem {
+            image,
+            translation_table,
+        }
+    }
+
+    /// Creates a blank, formatted disk
+    pub fn format(format: DiskFormat) -> Self {
+        Self::new(DiskImage::new(format))
+    }
+
+    pub fn get_image(&self) -> &DiskImage {
+        &self.image
+    }
+
+    pub fn get_image_mut(&mut self) -> &mut DiskImage {
+        &mut self.image
+    }
+
+    pub fn into_image(self) -> DiskImage {
+        self.image
+    }
+
+    fn get_parameters(&self) -> &DiskParameterBlock {
+        &self.image.get_format().parameters
+    }
+
+    fn get_records_per_block(&self) -> usize {
+        self.get_parameters().get_block_size() / SECTOR_SIZE
+    }
+
+    /// Block numbers a directory entry can hold
+    fn get_blocks_per_entry(&self) -> usize {
+        if self.get_parameters().has_large_blocks() {
+            8
+        } else {
+            16
+        }
+    }
+
+    fn get_records_per_entry(&self) -> usize {
+        (self.get_parameters().extent_mask as usize + 1) * RECORDS_PER_EXTENT
+    }
+
+    /// Track and physical sector of a record counted from the start of the
+    /// data tracks, going through the skew as the BDOS does
+    fn locate_record(&self, record: usize) -> (usize, usize) {
+        let parameters = self.get_parameters();
+        let sectors_per_track = parameters.sectors_per_track as usize;
+        let track = parameters.reserved_tracks as usize + record / sectors_per_track;
+        let sector = self.translation_table[record % sectors_per_track] as usize - 1;
+
+        (track, sector)
+    }
+
+    fn read_record(&self, record: usize) -> &[u8] {
+        let (track, sector) = self.locate_record(record);
+
+        self.image
+            .read_sector(track, sector)
+            .expect("blocks are within the disk")
+    }
+
+    fn write_record(&mut self, record: usize, data: &[u8]) {
+        let (track, sector) = self.locate_record(record);
+
+        self.image.write_sector(track, sector, data);
+    }
+
+    fn read_entry(&self, slot: usize) -> [u8; ENTRY_SIZE] {
+        let offset = slot % 4 * ENTRY_SIZE;
+        let record = self.read_record(slot / 4);
+
+        record[offset..offset + ENTRY_SIZE]
+            .try_into()
+            .expect("entries are 32 bytes")
+    }
+
+    fn write_entry(&mut self, slot: usize, entry: &[u8; ENTRY_SIZE]) {
+        let offset = slot % 4 * ENTRY_SIZE;
+        let mut record = self.read_record(slot / 4).to_vec();
+
+        record[offset..offset + ENTRY_SIZE].copy_from_slice(entry);
+        self.write_record(slot / 4, &record);
+    }
+
+    fn get_extents(&self) -> Vec<Extent> {
+        let large_blocks = self.get_parameters().has_large_blocks();
+
+        (0..self.get_parameters().get_directory_entries())
+            .filter_map(|slot| {
+                let entry = self.read_entry(slot);
+
+                if entry[0] > MAX_USER {
+                    return None;
+                }
+
+                let blocks: Vec<usize> = if large_blocks {
+                    entry[ALLOCATION..]
+                        .chunks(2)
+                        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as usize)
+                        .collect()
+                } else {
+                    entry[ALLOCATION..]
+                        .iter()
+                        .map(|block| *block as usize)
+                        .collect()
+                };
+
+                Some(Extent {
+                    slot,
+                    user: entry[0],
+                    name: FileName::from_bytes(&entry[1..]),
+                    read_only: entry[9] & 0x80 != 0,
+                    system: entry[10] & 0x80 != 0,
+                    number: (entry[MODULE] as usize & 0x3F) * EXTENTS_PER_MODULE
+                        + (entry[EXTENT] as usize & 0x1F),
+                    record_count: (entry[RECORD_COUNT] as usize).min(RECORDS_PER_EXTENT),
+                    blocks: blocks.into_iter().filter(|block| *block != 0).collect(),
+                })
+            })
+            .collect()
+    }
+
+    /// Lists the files of every user area, sorted by user and name
+    pub fn list(&self) -> Vec<FileEntry> {
+        let block_size = self.get_parameters().get_block_size();
+        let mut files: BTreeMap<(u8, FileName), FileEntry> = BTreeMap::new();
+
+        for extent in self.get_extents() {
+            let file = files
+                .entry((extent.user, extent.name))
+                .or_insert(FileEntry {
+                    user: extent.user,
+                    name: extent.name,
+                    records: 0,
+                    allocated: 0,
+                    read_only: extent.read_only,
+                    system: extent.system,
+                });
+
+            file.records = file
+                .records
+                .max(extent.number * RECORDS_PER_EXTENT + extent.record_count);
+            file.allocated += extent.blocks.len() * block_size;
+        }
+
+        files.into_values().collect()
+    }
+
+    /// Reads the whole of a file in the user area `user`
+    pub fn read_file(&self, user: u8, name: &FileName) -> Result<Vec<u8>, String> {
+        let extents: Vec<_> = self
+            .get_extents()
+            .into_iter()
+            .filter(|extent| extent.user == user && extent.name == *name)
+            .collect();
+
+        if extents.is_empty() {
+            return Err(format!("{}:{} not found", user, name));
+        }
+
+        let records = extents
+            .iter()
+            .map(|extent| extent.number * RECORDS_PER_EXTENT + extent.record_count)
+            .max()
+            .unwrap_or(0);
+        let records_per_block = self.get_records_per_block();
+        let extent_mask = self.get_parameters().extent_mask as usize;
+        let max_block = self.get_parameters().max_block as usize;
+        let mut data = vec![0; records * SECTOR_SIZE];
+
+        if extents
+            .iter()
+            .any(|extent| extent.blocks.iter().any(|block| *block > max_block))
+        {
+            return Err(format!(
+                "{}:{} has blocks beyond the end of the disk",
+                user, name
+            ));
+        }
+
+        for extent in &extents {
+            let first_record = (extent.number & !extent_mask) * RECORDS_PER_EXTENT;
+
+            for (index, block) in extent.blocks.iter().enumerate() {
+                for offset in 0..records_per_block {
+                    let record = first_record + index * records_per_block + offset;
+
+                    if record >= records {
+                        break;
+                    }
+
+                    let contents = self.read_record(block * records_per_block + offset);
+                    data[record * SECTOR_SIZE..(record + 1) * SECTOR_SIZE]
+                        .copy_from_slice(contents);
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Blocks neither holding the directory nor allocated to a file
+    fn get_free_blocks(&self) -> Vec<usize> {
+        let parameters = self.get_parameters();
+        let mut used = vec![false; parameters.max_block as usize + 1];
+
+        used[..parameters.get_directory_blocks()].fill(true);
+
+        for block in self.get_extents().iter().flat_map(|extent| &extent.blocks) {
+            if let Some(used) = used.get_mut(*block) {
+                *used = true;
+            }
+        }
+
+        (0..used.len()).filter(|block| !used[*block]).collect()
+    }
+
+    /// Bytes left for new files
+    pub fn get_free_space(&self) -> usize {
+        self.get_free_blocks().len() * self.get_parameters().get_block_size()
+    }
+
+    /// Writes a file to the user area `user`, replacing any file of the same
+    /// name, and padding its last record with end of text characters
+    pub fn write_file(&mut self, user: u8, name: &FileName, data: &[u8]) -> Result<(), String> {
+        if user > MAX_USER {
+            return Err(format!("invalid user number {}", user));
+        }
+
+        if name.is_ambiguous() {
+            return Err(format!("invalid file name {}", name));
+        }
+
+        let records_per_block = self.get_records_per_block();
+        let records_per_entry = self.get_records_per_entry();
+        let records = data.len().div_ceil(SECTOR_SIZE);
+        let blocks = records.div_ceil(records_per_block);
+        let entries = records.div_ceil(records_per_entry).max(1);
+
+        let replaced: Vec<_> = self
+            .get_extents()
+            .into_iter()
+            .filter(|extent| extent.user == user && extent.name == *name)
+            .collect();
+        let mut free_blocks = self.get_free_blocks();
+        free_blocks.extend(replaced.iter().flat_map(|extent| extent.blocks.clone()));
+        free_blocks.sort_unstable();
+
+        let mut free_slots: Vec<_> = (0..self.get_parameters().get_directory_entries())
+            .filter(|slot| self.read_entry(*slot)[0] == EMPTY)
+            .chain(replaced.iter().map(|extent| extent.slot))
+            .collect();
+        free_slots.sort_unstable();
+
+        if free_blocks.len() < blocks {
+            return Err(format!("disk full, {} needs {} blocks", name, blocks));
+        }
+
+        if free_slots.len() < entries {
+            return Err(format!(
+                "directory full, {} needs {} entries",
+                name, entries
+            ));
+        }
+
+        for extent in &replaced {
+            self.write_entry(extent.slot, &[EMPTY; ENTRY_SIZE]);
+        }
+
+        let blocks = &free_blocks[..blocks];
+
+        for (record, chunk) in data.chunks(SECTOR_SIZE).enumerate() {
+            let mut buffer = [END_OF_TEXT; SECTOR_SIZE];
+            buffer[..chunk.len()].copy_from_slice(chunk);
+
+            let block = blocks[record / records_per_block];
+            self.write_record(
+                block * records_per_block + record % records_per_block,
+                &buffer,
+            );
+        }
+
+        let blocks_per_entry = self.get_blocks_per_entry();
+        let large_blocks = self.get_parameters().has_large_blocks();
+
+        for (index, slot) in free_slots.into_iter().take(entries).enumerate() {
+            let first_record = index * records_per_entry;
+            let entry_records = records.saturating_sub(first_record).min(records_per_entry);
+            let last_extent = entry_records.saturating_sub(1) / RECORDS_PER_EXTENT;
+            let number = index * records_per_entry / RECORDS_PER_EXTENT + last_extent;
+
+            let mut entry = [0; ENTRY_SIZE];
+            entry[0] = user;
+            entry[1..12].copy_from_slice(&name.to_bytes());
+            entry[EXTENT] = (number % EXTENTS_PER_MODULE) as u8;
+            entry[MODULE] = (number / EXTENTS_PER_MODULE) as u8;
+            entry[RECORD_COUNT] = (entry_records - last_extent * RECORDS_PER_EXTENT) as u8;
+
+            let entry_blocks = blocks
+                .iter()
+                .skip(index * blocks_per_entry)
+                .take(blocks_per_entry);
+
+            for (position, block) in entry_blocks.enumerate() {
+                if large_blocks {
+                    let offset = ALLOCATION + position * 2;
+                    entry[offset..offset + 2].copy_from_slice(&(*block as u16).to_le_bytes());
+                } else {
+                    entry[ALLOCATION + position] = *block as u8;
+                }
+            }
+
+            self.write_entry(slot, &entry);
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the files of user area `user` matching `pattern`, returning
+    /// how many were deleted
+    pub fn delete_files(&mut self, user: u8, pattern: &FileName) -> usize {
+        let extents: Vec<_> = self
+            .get_extents()
+            .into_iter()
+            .filter(|extent| extent.user == user && extent.name.matches(pattern))
+            .collect();
+
+        for extent in &extents {
+            let mut entry = self.read_entry(extent.slot);
+            entry[0] = EMPTY;
+            self.write_entry(extent.slot, &entry);
+        }
+
+        let names: BTreeSet<_> = extents.iter().map(|extent| extent.name).collect();
+        names.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(text: &str) -> FileName {
+        FileName::parse(text, false).unwrap()
+    }
+
+    #[test]
+    fn should_write_read_and_delete_files() {
+        let mut filesystem = FileSystem::format(DiskFormat::ibm_3740());
+        let large: Vec<u8> = (0..=255).cycle().take(40_000).collect();
+
+        assert_eq!(filesystem.get_free_space(), 241 * 1024);
+
+        filesystem
+            .write_file(0, &name("LARGE.BIN"), &large)
+            .unwrap();
+        filesystem
+            .write_file(3, &name("NOTE.TXT"), b"hello")
+            .unwrap();
+        filesystem.write_file(0, &name("EMPTY"), b"").unwrap();
+
+        let files = filesystem.list();
+
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].name, name("EMPTY"));
+        assert_eq!(files[0].records, 0);
+        assert_eq!(files[1].records, 313);
+        assert_eq!(files[1].allocated, 40 * 1024);
+        assert_eq!((files[2].user, files[2].records), (3, 1));
+        assert_eq!(filesystem.get_free_space(), 200 * 1024);
+
+        let mut expected = large.clone();
+        expected.resize(313 * SECTOR_SIZE, END_OF_TEXT);
+
+        assert_eq!(
+            filesystem.read_file(0, &name("LARGE.BIN")).unwrap(),
+            expected
+        );
+        assert_eq!(
+            &filesystem.read_file(3, &name("NOTE.TXT")).unwrap()[..6],
+            b"hello\x1A"
+        );
+        assert!(filesystem.read_file(0, &name("NOTE.TXT")).is_err());
+
+        filesystem
+            .write_file(0, &name("LARGE.BIN"), &large[..1000])
+            .unwrap();
+
+        assert_eq!(filesystem.get_free_space(), 239 * 1024);
+
+        let pattern = FileName::parse("*.*", true).unwrap();
+
+        assert_eq!(filesystem.delete_files(0, &pattern), 2);
+        assert_eq!(filesystem.list().len(), 1);
+        assert_eq!(filesystem.get_free_space(), 240 * 1024);
+    }
+
+    #[test]
+    fn should_count_deleted_files_once() {
+        let mut filesystem = FileSystem::format(DiskFormat::ibm_3740());
+
+        filesystem
+            .write_file(0, &name("A.DAT"), &[0; 20_000])
+            .unwrap();
+        filesystem.write_file(0, &name("B.DAT"), b"b").unwrap();
+
+        // Growing A reuses its entries and takes the one after B's
+        filesystem
+            .write_file(0, &name("A.DAT"), &[0; 40_000])
+            .unwrap();
+
+        let pattern = FileName::parse("*.DAT", true).unwrap();
+
+        assert_eq!(filesystem.delete_files(0, &pattern), 2);
+        assert!(filesystem.list().is_empty());
+    }
+
+    #[test]
+    fn should_lay_out_entries_like_the_bdos() {
+        let mut filesystem = FileSystem::format(DiskFormat::ibm_3740());
+
+        filesystem
+            .write_file(0, &name("A.COM"), &[0; 17_000])
+            .unwrap();
+
+        let image = filesystem.get_image();
+        let translation_table = image.get_format().get_translation_table();
+        let directory = image
+            .read_sector(2, translation_table[0] as usize - 1)
+            .unwrap();
+
+        assert_eq!(&directory[..16], b"\0A       COM\0\0\0\x80");
+        assert_eq!(&directory[16..32], &(2..18).collect::<Vec<u8>>()[..]);
+        assert_eq!(&directory[32..48], b"\0A       COM\x01\0\0\x05");
+        assert_eq!(&directory[48..52], &[18, 0, 0, 0]);
+        assert_eq!(directory[64], EMPTY);
+
+        let mut filesystem = FileSystem::format(DiskFormat::z80pack_hd());
+
+        filesystem
+            .write_file(1, &name("B.DAT"), &[1; 40_000])
+            .unwrap();
+
+        let directory = filesystem.get_image().read_sector(0, 0).unwrap();
+
+        assert_eq!(&directory[..16], b"\x01B       DAT\0\0\0\x80");
+        assert_eq!(&directory[16..22], &[16, 0, 17, 0, 18, 0]);
+        assert_eq!(&directory[64..80], b"\x01B       DAT\x02\0\0\x39");
+        assert_eq!(filesystem.list()[0].records, 313);
+    }
+}
@@ -0,0 +1,693 @@
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    devices::console::Console,
+    internal::{
+        instructions::{Register, RegisterPair},
+        memory::AddressableMemory,
+    },
+    system::{trace::Tracer, Machine, State, System},
+};
+
+use super::disk::{DiskFormat, DiskImage, SECTOR_SIZE};
+
+/// Load address of the CCP in a 64K system
+pub const CCP_ADDRESS: u16 = 0xE400;
+
+/// Load address of the BDOS, whose entry point is 6 bytes in
+pub const BDOS_ADDRESS: u16 = 0xEC00;
+
+/// Address of the BIOS jump table
+pub const BIOS_ADDRESS: u16 = 0xFA00;
+
+/// Size of the CCP and BDOS, loaded from the system tracks on boot
+pub const SYSTEM_SIZE: usize = (BIOS_ADDRESS - CCP_ADDRESS) as usize;
+
+/// Drives the BIOS provides, A: to D:
+pub const DRIVES: usize = 4;
+
+/// BIOS entry points, in jump table order
+const BIOS_ENTRIES: usize = 17;
+
+const BOOT: u16 = 0;
+const WARM_BOOT: u16 = 1;
+const CONSOLE_STATUS: u16 = 2;
+const CONSOLE_INPUT: u16 = 3;
+const CONSOLE_OUTPUT: u16 = 4;
+const LIST: u16 = 5;
+const PUNCH: u16 = 6;
+const READER: u16 = 7;
+const HOME: u16 = 8;
+const SELECT_DISK: u16 = 9;
+const SET_TRACK: u16 = 10;
+const SET_SECTOR: u16 = 11;
+const SET_DMA: u16 = 12;
+const READ: u16 = 13;
+const WRITE: u16 = 14;
+const LIST_STATUS: u16 = 15;
+const SECTOR_TRANSLATE: u16 = 16;
+
+/// Each jump table entry jumps to a `RET` in this block, where the call is
+/// serviced before the `RET` executes
+const TRAPS_ADDRESS: u16 = BIOS_ADDRESS + 3 * BIOS_ENTRIES as u16;
+
+/// Start of the directory buffer, translation tables, disk parameter blocks
+/// and per-drive scratch areas the BDOS works with
+const DATA_ADDRESS: u16 = TRAPS_ADDRESS + BIOS_ENTRIES as u16;
+
+const JMP: u8 = 0xC3;
+const RET: u8 = 0xC9;
+
+const END_OF_TEXT: u8 = 0x1A;
+
+/// Lays out the directory buffer followed by the translation table, disk
+/// parameter block, check and allocation vectors and disk parameter header
+/// of each drive, returning them with the address of each header
+fn build_disk_tables(formats: &[&DiskFormat]) -> Result<(Vec<u8>, Vec<u16>), String> {
+    let directory_buffer = DATA_ADDRESS as usize;
+    let mut data = vec![0; SECTOR_SIZE];
+    let mut headers = vec![];
+    let address = |data: &Vec<u8>| DATA_ADDRESS as usize + data.len();
+
+    for format in formats {
+        let parameters = &format.parameters;
+
+        // Sectors of unskewed formats are used as they are
+        let translation_table = if format.skew == 1 {
+            0
+        } else {
+            let translation_table = address(&data);
+            data.extend(format.get_translation_table());
+            translation_table
+        };
+
+        let parameter_block = address(&data);
+        data.extend(parameters.to_bytes());
+
+        let check_vector = address(&data);
+        data.extend(vec![0; parameters.check_size as usize]);
+
+        let allocation_vector = address(&data);
+        data.extend(vec![0; parameters.get_allocation_vector_size()]);
+
+        headers.push(address(&data) as u16);
+
+        for word in [
+            translation_table,
+            0,
+            0,
+            0,
+            directory_buffer,
+            parameter_block,
+            check_vector,
+            allocation_vector,
+        ] {
+            data.extend((word as u16).to_le_bytes());
+        }
+    }
+
+    let available = 0x10000 - DATA_ADDRESS as usize;
+
+    if data.len() > available {
+        return Err(format!(
+            "disk tables of {} bytes do not fit in the {} bytes above {:#06x}",
+            data.len(),
+            available,
+            DATA_ADDRESS
+        ));
+    }
+
+    Ok((data, headers))
+}
+
+struct Drive {
+    image: DiskImage,
+    /// Image file which writes go through to
+    path: Option<PathBuf>,
+    header_address: u16,
+}
+
+/// CP/M 2.2 machine running a genuine CCP and BDOS, on a BIOS serviced in
+/// Rust with 8" disk images as drives
+///
+/// The CCP and BDOS are read from the system tracks of drive A: on boot,
+/// starting at the second sector, unless loaded with `load_system`. The
+/// machine powers off when console input ends.
+pub struct CpmMachine {
+    system: System,
+    console: Box<dyn Console>,
+    drives: Vec<Option<Drive>>,
+    system_image: Option<Vec<u8>>,
+    drive: usize,
+    track: usize,
+    sector: usize,
+    dma: u16,
+}
+
+impl CpmMachine {
+    pub fn new(console: impl Console + 'static) -> Self {
+        CpmMachine {
+            system: System::new(),
+            console: Box::new(console),
+            drives: (0..DRIVES).map(|_| None).collect(),
+            system_image: None,
+            drive: 0,
+            track: 0,
+            sector: 1,
+            dma: 0x0080,
+        }
+    }
+
+    /// Inserts an image in `drive`, 0 for A:, with writes kept in memory
+    pub fn insert_disk(&mut self, drive: usize, image: DiskImage) -> Result<(), String> {
+        self.insert(drive, image, None)
+    }
+
+    /// Inserts the image file at `path` in `drive`, writing sectors back to
+    /// the file as the BIOS writes them
+    pub fn open_disk(
+        &mut self,
+        drive: usize,
+        path: impl Into<PathBuf>,
+        format: DiskFormat,
+    ) -> Result<(), String> {
+        let path = path.into();
+        let image = DiskImage::load(&path, format)?;
+
+        self.insert(drive, image, Some(path))
+    }
+
+    fn insert(
+        &mut self,
+        drive: usize,
+        image: DiskImage,
+        path: Option<PathBuf>,
+    ) -> Result<(), String> {
+        if drive >= DRIVES {
+            return Err(format!("drive {} is out of range", drive));
+        }
+
+        let mut formats: Vec<_> = self
+            .drives
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| index != drive)
+            .filter_map(|(_, other)| other.as_ref().map(|other| other.image.get_format()))
+            .collect();
+        formats.push(image.get_format());
+        build_disk_tables(&formats)?;
+
+        self.drives[drive] = Some(Drive {
+            image,
+            path,
+            header_address: 0,
+        });
+
+        Ok(())
+    }
+
+    pub fn get_disk(&self, drive: usize) -> Option<&DiskImage> {
+        self.drives.get(drive)?.as_ref().map(|drive| &drive.image)
+    }
+
+    /// Uses a CCP and BDOS assembled for `CCP_ADDRESS` instead of the system
+    /// tracks of drive A:
+    pub fn load_system(&mut self, image: Vec<u8>) -> Result<(), String> {
+        if image.len() > SYSTEM_SIZE {
+            return Err(format!(
+                "system image of {} bytes is larger than {} bytes",
+                image.len(),
+                SYSTEM_SIZE
+            ));
+        }
+
+        self.system_image = Some(image);
+        Ok(())
+    }
+
+    /// Cold boots, loading the CCP and BDOS and laying out the BIOS and page
+    /// zero, then starting the CCP on drive A:
+    pub fn boot(&mut self) -> Result<(), String> {
+        let system_image = match &self.system_image {
+            Some(image) => image.clone(),
+            None => self.drives[0]
+                .as_ref()
+                .ok_or("no disk in drive A: to boot from")?
+                .image
+                .read_system(SYSTEM_SIZE)
+                .map_err(|message| format!("cannot boot from drive A: {}", message))?,
+        };
+
+        self.system_image = Some(system_image);
+        self.write_bios()?;
+
+        let state = self.system.get_state_mut();
+        state.memory.load(0x0003, vec![0x00, 0x00]);
+
+        self.warm_boot(CCP_ADDRESS);
+        self.system.power_on();
+
+        Ok(())
+    }
+
+    /// Writes the jump table, traps, and disk parameter headers along with
+    /// the tables they point to
+    fn write_bios(&mut self) -> Result<(), String> {
+        let mut jump_table = vec![];
+
+        for entry in 0..BIOS_ENTRIES as u16 {
+            let [low, high] = (TRAPS_ADDRESS + entry).to_le_bytes();
+            jump_table.extend([JMP, low, high]);
+        }
+
+        jump_table.extend([RET; BIOS_ENTRIES]);
+
+        let formats: Vec<_> = self
+            .drives
+            .iter()
+            .flatten()
+            .map(|drive| drive.image.get_format())
+            .collect();
+        let (data, headers) = build_disk_tables(&formats)?;
+
+        for (drive, header_address) in self.drives.iter_mut().flatten().zip(headers) {
+            drive.header_address = header_address;
+        }
+
+        let memory = &mut self.system.get_state_mut().memory;
+        memory.load(BIOS_ADDRESS, jump_table);
+        memory.load(DATA_ADDRESS, data);
+
+        Ok(())
+    }
+
+    /// Reloads the CCP and BDOS, restores the page zero vectors and jumps to
+    /// `entry` with the current drive in C
+    fn warm_boot(&mut self, entry: u16) {
+        let system_image = self.system_image.clone().unwrap_or_default();
+        let state = self.system.get_state_mut();
+        let [bios_low, bios_high] = (BIOS_ADDRESS + 3).to_le_bytes();
+        let [bdos_low, bdos_high] = (BDOS_ADDRESS + 6).to_le_bytes();
+
+        state.memory.load(CCP_ADDRESS, system_image);
+        state.memory.load(0x0000, vec![JMP, bios_low, bios_high]);
+        state.memory.load(0x0005, vec![JMP, bdos_low, bdos_high]);
+
+        let current_drive = state.memory.get(0x0004);
+        state.set_register(&Register::C, current_drive);
+        state.registers.stack_pointer = 0x0080;
+        state.interrupt_enabled = false;
+        state.program_counter.set(entry);
+
+        self.dma = 0x0080;
+    }
+
+    pub fn get_system_mut(&mut self) -> &mut System {
+        &mut self.system
+    }
+
+    pub fn attach_tracer(&mut self, tracer: Tracer) {
+        self.system.attach_tracer(tracer);
+    }
+
+    pub fn detach_tracer(&mut self) -> Option<Tracer> {
+        self.system.detach_tracer()
+    }
+
+    pub fn is_powered_on(&self) -> bool {
+        self.system.is_powered_on()
+    }
+
+    /// Services the BIOS entry point `entry`, returning false if the machine
+    /// cannot continue
+    fn call_bios(&mut self, entry: u16) -> bool {
+        let state = self.system.get_state_mut();
+        let parameter = state.get_register_pair(&RegisterPair::BC);
+        let byte_parameter = parameter as u8;
+
+        let result: u8 = match entry {
+            BOOT | WARM_BOOT => {
+                // The CCP entry past its command buffer setup skips any
+                // pending command
+                self.warm_boot(CCP_ADDRESS + 3);
+                return true;
+            }
+            CONSOLE_STATUS if self.console.is_ready() => 0xFF,
+            CONSOLE_STATUS => 0x00,
+            CONSOLE_INPUT => match self.console.read() {
+                Some(character) => character & 0x7F,
+                None => return false,
+            },
+            CONSOLE_OUTPUT => {
+                self.console.write(byte_parameter & 0x7F);
+                0
+            }
+            LIST | PUNCH => 0,
+            READER => END_OF_TEXT,
+            HOME => {
+                self.track = 0;
+                0
+            }
+            SELECT_DISK => {
+                let header_address = self
+                    .drives
+                    .get(byte_parameter as usize)
+                    .and_then(Option::as_ref)
+                    .map_or(0, |drive| drive.header_address);
+
+                if header_address != 0 {
+                    self.drive = byte_parameter as usize;
+                }
+
+                state.set_register_pair(&RegisterPair::HL, header_address);
+                return true;
+            }
+            SET_TRACK => {
+                self.track = parameter as usize;
+                0
+            }
+            SET_SECTOR => {
+                self.sector = parameter as usize;
+                0
+            }
+            SET_DMA => {
+                self.dma = parameter;
+                0
+            }
+            READ => self.read_sector(),
+            WRITE => self.write_sector(),
+            LIST_STATUS => 0xFF,
+            SECTOR_TRANSLATE => {
+                let table = state.get_register_pair(&RegisterPair::DE);
+                let sector = if table == 0 {
+                    parameter
+                } else {
+                    state.memory.get(table.wrapping_add(parameter)) as u16
+                };
+
+                state.set_register_pair(&RegisterPair::HL, sector);
+                return true;
+            }
+            _ => 0,
+        };
+
+        self.system
+            .get_state_mut()
+            .set_register(&Register::A, result);
+
+        true
+    }
+
+    /// Copies the selected sector, counted from 1, into the DMA buffer,
+    /// wrapping around the end of memory, returning 1 if it does not exist
+    fn read_sector(&mut self) -> u8 {
+        let Some(drive) = self.drives[self.drive].as_ref() else {
+            return 1;
+        };
+        let Some(sector) = self
+            .sector
+            .checked_sub(1)
+            .and_then(|sector| drive.image.read_sector(self.track, sector))
+        else {
+            return 1;
+        };
+
+        self.system
+            .get_state_mut()
+            .memory
+            .load(self.dma, sector.to_vec());
+        0
+    }
+
+    fn write_sector(&mut self) -> u8 {
+        let memory = &self.system.get_state().memory;
+        let buffer: Vec<u8> = (0..SECTOR_SIZE as u16)
+            .map(|offset| memory.get(self.dma.wrapping_add(offset)))
+            .collect();
+        let Some(drive) = self.drives[self.drive].as_mut() else {
+            return 1;
+        };
+        let Some(sector) = self.sector.checked_sub(1) else {
+            return 1;
+        };
+
+        if !drive.image.write_sector(self.track, sector, &buffer) {
+            return 1;
+        }
+
+        let Some(path) = &drive.path else {
+            return 0;
+        };
+        let offset = drive
+            .image
+            .get_offset(self.track, sector)
+            .expect("sector was written");
+
+        let result = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(offset as u64))?;
+                file.write_all(&buffer)
+            });
+
+        match result {
+            Ok(()) => 0,
+            Err(_) => 1,
+        }
+    }
+}
+
+impl Machine for CpmMachine {
+    fn get_state(&self) -> &State {
+        self.system.get_state()
+    }
+
+    fn get_state_mut(&mut self) -> &mut State {
+        self.system.get_state_mut()
+    }
+
+    fn step(&mut self) -> usize {
+        if !self.system.is_powered_on() {
+            return 0;
+        }
+
+        let address = self.system.get_program_counter();
+
+        if (TRAPS_ADDRESS..DATA_ADDRESS).contains(&address)
+            && !self.call_bios(address - TRAPS_ADDRESS)
+        {
+            self.system.power_off();
+            return 0;
+        }
+
+        self.system.step()
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.system.save_state()
+    }
+
+    fn restore_state(&mut self, data: &[u8]) -> Result<(), String> {
+        self.system.restore_state(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        assembler::assemble, cpm::disk::DiskParameterBlock, devices::console::BufferConsole,
+    };
+
+    /// Stands in for the CCP, exercising the console and disk entry points
+    /// of the BIOS and warm booting into a halt
+    const SYSTEM_SOURCE: &str = "
+CONOUT  EQU 0FA0CH
+CONIN   EQU 0FA09H
+SELDSK  EQU 0FA1BH
+SETTRK  EQU 0FA1EH
+SETSEC  EQU 0FA21H
+SETDMA  EQU 0FA24H
+READ    EQU 0FA27H
+WRITE   EQU 0FA2AH
+SECTRAN EQU 0FA30H
+
+        ORG 0E400H
+        JMP START
+        HLT
+START:  MVI C, 'O'
+        CALL CONOUT
+        MVI C, 0
+        CALL SELDSK
+        MOV E, M
+        INX H
+        MOV D, M
+        LXI B, 1
+        CALL SECTRAN
+        MOV B, H
+        MOV C, L
+        CALL SETSEC
+        LXI B, 2
+        CALL SETTRK
+        LXI B, 1000H
+        CALL SETDMA
+        CALL READ
+        LDA 1000H
+        MOV C, A
+        CALL CONOUT
+        MVI A, 'W'
+        STA 1000H
+        CALL WRITE
+        CALL CONIN
+        MOV C, A
+        CALL CONOUT
+        JMP 0
+";
+
+    #[test]
+    fn should_service_bios_calls() {
+        let format = DiskFormat::ibm_3740();
+        let mut image = DiskImage::new(format.clone());
+        image.write_sector(2, 6, &[b'K'; SECTOR_SIZE]);
+
+        let console = Rc::new(RefCell::new(BufferConsole::new()));
+        console.borrow_mut().push_input(b"!");
+
+        let mut machine = CpmMachine::new(console.clone());
+        machine.insert_disk(0, image).unwrap();
+        machine
+            .load_system(assemble(SYSTEM_SOURCE).unwrap().bytes)
+            .unwrap();
+        machine.boot().unwrap();
+
+        let mut steps = 0;
+
+        while machine.step() > 0 {
+            steps += 1;
+            assert!(steps < 1000);
+        }
+
+        let state = machine.get_state();
+
+        assert_eq!(console.borrow_mut().take_output(), b"OK!");
+        assert!(state.halted);
+        assert_eq!(state.program_counter.get(), CCP_ADDRESS + 4);
+        assert_eq!(
+            state.memory.get_range(0x0000, 0x0002),
+            vec![JMP, 0x03, 0xFA]
+        );
+        assert_eq!(
+            state.memory.get_range(0x0005, 0x0007),
+            vec![JMP, 0x06, 0xEC]
+        );
+        assert_eq!(
+            machine.get_disk(0).unwrap().read_sector(2, 6).unwrap()[0],
+            b'W'
+        );
+    }
+
+    #[test]
+    fn should_boot_from_system_tracks() {
+        let mut image = DiskImage::new(DiskFormat::ibm_3740());
+        let system = assemble("ORG 0E400H\nMVI C, 'A'\nCALL 0FA0CH\nCALL 0FA09H\n")
+            .unwrap()
+            .bytes;
+        let mut sector = [0; SECTOR_SIZE];
+        sector[..system.len()].copy_from_slice(&system);
+        image.write_sector(0, 1, &sector);
+
+        let console = Rc::new(RefCell::new(BufferConsole::new()));
+        let mut machine = CpmMachine::new(console.clone());
+
+        assert_eq!(
+            machine.boot(),
+            Err(String::from("no disk in drive A: to boot from"))
+        );
+
+        machine.insert_disk(0, image).unwrap();
+        machine.boot().unwrap();
+
+        while machine.step() > 0 {}
+
+        assert_eq!(console.borrow_mut().take_output(), b"A");
+        assert!(!machine.is_powered_on());
+    }
+
+    #[test]
+    fn should_lay_out_tables_within_memory() {
+        let mut machine = CpmMachine::new(BufferConsole::new());
+        machine.load_system(vec![0x76]).unwrap();
+
+        for drive in 0..DRIVES {
+            machine
+                .insert_disk(drive, DiskImage::new(DiskFormat::z80pack_hd()))
+                .unwrap();
+        }
+
+        machine.boot().unwrap();
+
+        // Unskewed disks have no translation table
+        let header = machine.drives[3].as_ref().unwrap().header_address;
+        assert_eq!(
+            machine.get_state().memory.get_range(header, header + 1),
+            [0, 0]
+        );
+
+        let format = DiskFormat {
+            name: "large",
+            tracks: 255,
+            skew: 1,
+            parameters: DiskParameterBlock::new(255, 255, 0, 2048, 1024, true).unwrap(),
+        };
+        let mut machine = CpmMachine::new(BufferConsole::new());
+        machine
+            .insert_disk(0, DiskImage::new(format.clone()))
+            .unwrap();
+
+        assert_eq!(
+            machine.insert_disk(1, DiskImage::new(format)),
+            Err(String::from(
+                "disk tables of 1718 bytes do not fit in the 1468 bytes above 0xfa44"
+            ))
+        );
+        assert!(machine.get_disk(1).is_none());
+    }
+
+    #[test]
+    fn should_wrap_dma_buffer_around_memory() {
+        let mut image = DiskImage::new(DiskFormat::ibm_3740());
+        let sector: Vec<u8> = (0..SECTOR_SIZE as u8).collect();
+        image.write_sector(2, 0, &sector);
+
+        let mut machine = CpmMachine::new(BufferConsole::new());
+        machine.insert_disk(0, image).unwrap();
+        machine.load_system(vec![0x76]).unwrap();
+        machine.boot().unwrap();
+        machine.track = 2;
+        machine.sector = 1;
+        machine.dma = 0xFFC0;
+
+        assert_eq!(machine.read_sector(), 0);
+        assert_eq!(machine.read_memory_region(0xFFC0, 0xFFFF), &sector[..0x40]);
+        assert_eq!(machine.read_memory_region(0x0000, 0x003F), &sector[0x40..]);
+
+        machine
+            .get_state_mut()
+            .memory
+            .load(0xFFC0, vec![0xEE; 0x80]);
+        machine.sector = 2;
+
+        assert_eq!(machine.write_sector(), 0);
+        assert_eq!(
+            machine.get_disk(0).unwrap().read_sector(2, 1).unwrap(),
+            [0xEE; SECTOR_SIZE]
+        );
+    }
+}
@@ -0,0 +1,6 @@
+pub mod bdos;
+pub mod disk;
+pub mod fcb;
+pub mod filesystem;
+pub mod machine;
+pub mod system;
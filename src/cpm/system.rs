@@ -0,0 +1,445 @@
+use std::path::PathBuf;
+
+use crate::{
+    devices::console::Console,
+    system::{trace::Tracer, Machine, State, System},
+};
+
+use super::{
+    bdos::{Bdos, BdosCall},
+    fcb::{self, FileName},
+};
+
+/// Address of the BDOS entry point, which also marks the top of the TPA
+pub const BDOS_ENTRY: u16 = 0xFE06;
+
+/// Address of the BIOS warm boot entry point, reached through 0x0000
+pub const WARM_BOOT: u16 = 0xFF03;
+
+/// Address programs are loaded and started at
+pub const TPA_START: u16 = 0x0100;
+
+/// Initial stack pointer, below the BDOS
+const STACK_TOP: u16 = 0xFE00;
+
+const JMP: u8 = 0xC3;
+const RET: u8 = 0xC9;
+const HLT: u8 = 0x76;
+
+/// Addresses of the default file control blocks and command tail
+const DEFAULT_FCB: u16 = 0x005C;
+const SECOND_FCB: u16 = 0x006C;
+const COMMAND_TAIL: u16 = 0x0080;
+
+/// Runs a single CP/M program with BDOS calls serviced on the host
+///
+/// Calls to 0x0005 reach a `RET` at the BDOS entry point, which performs the
+/// call in Rust before executing. The machine powers off when the program
+/// warm boots by jumping to 0x0000 or calling function 0.
+pub struct BdosSystem {
+    system: System,
+    bdos: Bdos,
+}
+
+impl BdosSystem {
+    pub fn new(console: impl Console + 'static) -> Self {
+        let mut system = System::new();
+
+        system.load_program_at(0x0000, vec![JMP, 0x03, 0xFF]);
+        system.load_program_at(0x0005, vec![JMP, 0x06, 0xFE]);
+        system.load_program_at(BDOS_ENTRY, vec![RET]);
+        system.load_program_at(WARM_BOOT, vec![HLT]);
+
+        let state = system.get_state_mut();
+        state.registers.stack_pointer = STACK_TOP;
+        state.push_word_to_stack(0x0000);
+        state.program_counter.set(TPA_START);
+
+        BdosSystem {
+            system,
+            bdos: Bdos::new(console),
+        }
+    }
+
+    /// Loads a `.COM` program into the TPA
+    pub fn load_program(&mut self, program_bytecode: Vec<u8>) {
+        self.system.load_program_at(TPA_START, program_bytecode);
+    }
+
+    /// Backs `drive`, 0 for A: to 15 for P:, with a host directory
+    pub fn set_drive(&mut self, drive: u8, directory: impl Into<PathBuf>) {
+        self.bdos.set_drive(drive, directory);
+    }
+
+    /// Fills in the command tail and the default file control blocks from
+    /// the command line arguments, as the CCP does before running a program
+    pub fn set_command_line(&mut self, arguments: &[&str]) {
+        let tail: String = arguments
+            .iter()
+            .map(|argument| format!(" {}", argument.to_ascii_uppercase()))
+            .collect();
+        // The length, tail and terminator fill at most the page below the TPA
+        let tail = &tail.as_bytes()[..tail.len().min(126)];
+
+        let memory = &mut self.system.get_state_mut().memory;
+        let mut page = vec![tail.len() as u8];
+        page.extend(tail);
+        page.push(0);
+
+        memory.load(COMMAND_TAIL, page);
+
+        for (address, argument) in [
+            (DEFAULT_FCB, arguments.first()),
+            (SECOND_FCB, arguments.get(1)),
+        ] {
+            let (drive, name) = argument
+                .and_then(|argument| fcb::parse_argument(argument))
+                .unwrap_or((0, FileName::from_bytes(&[b' '; 11])));
+
+            let mut block = vec![drive];
+            block.extend(name.to_bytes());
+            block.extend([0; 4]);
+
+            memory.load(address, block);
+        }
+    }
+
+    pub fn get_bdos(&self) -> &Bdos {
+        &self.bdos
+    }
+
+    pub fn get_bdos_mut(&mut self) -> &mut Bdos {
+        &mut self.bdos
+    }
+
+    pub fn get_system_mut(&mut self) -> &mut System {
+        &mut self.system
+    }
+
+    pub fn attach_tracer(&mut self, tracer: Tracer) {
+        self.system.attach_tracer(tracer);
+    }
+
+    pub fn detach_tracer(&mut self) -> Option<Tracer> {
+        self.system.detach_tracer()
+    }
+
+    pub fn is_powered_on(&self) -> bool {
+        self.system.is_powered_on()
+    }
+}
+
+impl Machine for BdosSystem {
+    fn get_state(&self) -> &State {
+        self.system.get_state()
+    }
+
+    fn get_state_mut(&mut self) -> &mut State {
+        self.system.get_state_mut()
+    }
+
+    fn step(&mut self) -> usize {
+        let state = self.system.get_state_mut();
+
+        if !state.enabled {
+            return 0;
+        }
+
+        match state.program_counter.get() {
+            0x0000 | WARM_BOOT => {
+                self.system.power_off();
+                return 0;
+            }
+            BDOS_ENTRY if self.bdos.call(state) == BdosCall::Exited => {
+                self.system.power_off();
+                return 0;
+            }
+            _ => {}
+        }
+
+        self.system.step()
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.system.save_state()
+    }
+
+    fn restore_state(&mut self, data: &[u8]) -> Result<(), String> {
+        self.system.restore_state(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, env, fs, process, rc::Rc};
+
+    use super::*;
+    use crate::devices::console::BufferConsole;
+
+    fn get_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("bdos-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Assembles `MVI C, function; LXI D, parameter; CALL 5`
+    fn call(function: u8, parameter: u16) -> Vec<u8> {
+        let [low, high] = parameter.to_le_bytes();
+
+        vec![0x0E, function, 0x11, low, high, 0xCD, 0x05, 0x00]
+    }
+
+    #[test]
+    fn should_print_and_read_lines() {
+        let mut program = call(9, 0x0180);
+        program.extend(call(10, 0x0200));
+        program.extend(call(1, 0));
+        program.push(0x32); // STA 0x0210
+        program.extend([0x10, 0x02]);
+        program.extend(call(12, 0));
+        program.push(0x22); // SHLD 0x0212
+        program.extend([0x12, 0x02]);
+        program.push(0xC9);
+        program.resize(0x80, 0);
+        program.extend(b"Name? $");
+
+        let directory = get_directory("console");
+        let console = Rc::new(RefCell::new(BufferConsole::new()));
+        console.borrow_mut().push_input(b"bobx\x08\rz");
+
+        let mut system = BdosSystem::new(console.clone());
+        system.set_drive(0, &directory);
+        system.load_program(program);
+        system.get_state_mut().memory.load(0x0200, vec![8]);
+
+        while system.step() > 0 {}
+
+        let memory = system.read_memory_region(0x0200, 0x0213);
+
+        assert_eq!(
+            String::from_utf8(console.borrow_mut().take_output()).unwrap(),
+            "Name? bobx\x08 \x08\rz"
+        );
+        assert_eq!(&memory[..5], &[8, 3, b'b', b'o', b'b']);
+        assert_eq!(memory[0x10], b'z');
+        assert_eq!(&memory[0x12..], &[0x22, 0x00]);
+        assert!(!system.is_powered_on());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn should_write_read_and_rename_files() {
+        let directory = get_directory("files");
+
+        let mut program = call(22, 0x005C);
+        program.extend(call(26, 0x0300));
+        program.extend(call(21, 0x005C));
+        program.extend(call(21, 0x005C));
+        program.extend(call(16, 0x005C));
+        program.extend(call(23, 0x0180));
+        program.extend(call(0, 0));
+        program.resize(0x80, 0);
+
+        // Making a file clears the allocation map, where the CCP leaves the
+        // second name, so renames use a separate block
+        program.extend(b"\0OUT     DAT\0\0\0\0\0RENAMED DAT");
+
+        let mut system = BdosSystem::new(BufferConsole::new());
+        system.set_drive(0, &directory);
+        system.load_program(program);
+        system.set_command_line(&["out.dat"]);
+        system.get_state_mut().memory.load(0x0300, vec![0xAB; 128]);
+
+        while system.step() > 0 {}
+
+        assert_eq!(
+            fs::read(directory.join("RENAMED.DAT")).unwrap(),
+            vec![0xAB; 256]
+        );
+        assert!(!directory.join("OUT.DAT").exists());
+
+        // Read the second record back at random, then hit the end of the
+        // file sequentially
+        let mut program = call(15, 0x005C);
+        program.extend(call(26, 0x0300));
+        program.extend(call(33, 0x005C));
+        program.push(0x32); // STA 0x0400
+        program.extend([0x00, 0x04]);
+        program.extend(call(20, 0x005C));
+        program.extend(call(20, 0x005C));
+        program.push(0x32); // STA 0x0401
+        program.extend([0x01, 0x04]);
+        program.extend(call(35, 0x005C));
+        program.extend(call(17, 0x005C));
+        program.push(0xC9);
+
+        fs::write(directory.join("RENAMED.DAT"), [1; 200]).unwrap();
+
+        let mut system = BdosSystem::new(BufferConsole::new());
+        system.set_drive(0, &directory);
+        system.load_program(program);
+        system.set_command_line(&["renamed.dat"]);
+        system.get_state_mut().memory.load(0x007D, vec![1, 0, 0]);
+
+        while system.step() > 0 {}
+
+        let record = system.read_memory_region(0x0300, 0x037F);
+
+        assert_eq!(system.read_memory_region(0x0400, 0x0401), vec![0, 1]);
+        assert_eq!(&system.read_memory_region(0x007D, 0x007F), &[2, 0, 0]);
+        assert_eq!(&record[..12], b"\0RENAMED DAT");
+        assert_eq!(record[15], 2);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// Assembles `STA 0x0400 + index`
+    fn store_result(index: u8) -> Vec<u8> {
+        vec![0x32, index, 0x04]
+    }
+
+    /// Builds a 36-byte file control block for the current drive
+    fn file_control_block(name: &[u8; 11], random_record: [u8; 3]) -> Vec<u8> {
+        let mut block = vec![0];
+        block.extend(name);
+        block.resize(fcb::RANDOM_RECORD, 0);
+        block.extend(random_record);
+        block
+    }
+
+    #[test]
+    fn should_search_delete_and_report_errors() {
+        let directory = get_directory("errors");
+        fs::write(directory.join("A.TXT"), [1; 130]).unwrap();
+        fs::write(directory.join("B.TXT"), [2; 1]).unwrap();
+
+        let missing = 0x0200;
+        let pattern = 0x0230;
+        let existing = 0x0260;
+        let new = 0x0290;
+        let mut program = vec![];
+
+        for (index, (function, parameter)) in [
+            (15, missing),
+            (17, pattern),
+            (18, pattern),
+            (18, pattern),
+            (15, existing),
+            (33, existing),
+            (33, missing),
+            (22, new),
+            (34, new),
+            (19, existing),
+            (15, existing),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            // Each call gets its own DMA buffer
+            program.extend(call(26, 0x0800 + 0x80 * index as u16));
+            program.extend(call(function, parameter));
+            program.extend(store_result(index as u8));
+        }
+
+        program.push(0xC9);
+
+        let mut system = BdosSystem::new(BufferConsole::new());
+        system.set_drive(0, &directory);
+        system.load_program(program);
+
+        let memory = &mut system.get_state_mut().memory;
+        memory.load(missing, file_control_block(b"MISSING TXT", [0, 0, 1]));
+        memory.load(pattern, file_control_block(b"????????TXT", [0; 3]));
+        memory.load(existing, file_control_block(b"A       TXT", [2, 0, 0]));
+        memory.load(new, file_control_block(b"NEW     DAT", [3, 0, 0]));
+        memory.load(0x0C00, vec![0x55; 128]);
+
+        while system.step() > 0 {}
+
+        assert_eq!(
+            system.read_memory_region(0x0400, 0x040A),
+            vec![0xFF, 0, 0, 0xFF, 0, 1, 6, 0, 0, 0, 0xFF]
+        );
+        assert_eq!(&system.read_memory_region(0x0881, 0x088B), b"A       TXT");
+        assert_eq!(&system.read_memory_region(0x0901, 0x090B), b"B       TXT");
+        assert!(!directory.join("A.TXT").exists());
+
+        let data = fs::read(directory.join("NEW.DAT")).unwrap();
+
+        // Writing record 3 of an empty file fills the records before it
+        assert_eq!(data.len(), 4 * 128);
+        assert_eq!(&data[..3 * 128], &[0; 3 * 128]);
+        assert_eq!(&data[3 * 128..], &[0x55; 128]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn should_wrap_control_blocks_and_buffers_around_memory() {
+        let directory = get_directory("wrap");
+        fs::write(directory.join("WRAP.TXT"), [7; 128]).unwrap();
+
+        // The FCB wraps over the page zero vectors, so the BDOS is called
+        // at its entry point
+        let direct = |function, parameter| {
+            let mut bytes = call(function, parameter);
+            bytes[6..].copy_from_slice(&BDOS_ENTRY.to_le_bytes());
+            bytes
+        };
+
+        let mut program = direct(15, 0xFFF0);
+        program.extend(store_result(0));
+        program.extend(direct(26, 0x0300));
+        program.extend(direct(20, 0xFFF0));
+        program.extend(store_result(1));
+        program.extend(direct(22, 0x005C));
+        program.extend(store_result(2));
+        program.extend(direct(26, 0xFFC0));
+        program.extend(direct(21, 0x005C));
+        program.extend(store_result(3));
+        program.push(0xC9);
+
+        let mut system = BdosSystem::new(BufferConsole::new());
+        system.set_drive(0, &directory);
+        system.load_program(program);
+        system.set_command_line(&["out.dat"]);
+
+        let memory = &mut system.get_state_mut().memory;
+        memory.load(0xFFF0, file_control_block(b"WRAP    TXT", [0; 3]));
+        memory.load(0xFFC0, vec![0xAA; 0x30]);
+
+        while system.step() > 0 {}
+
+        assert_eq!(system.read_memory_region(0x0400, 0x0403), vec![0, 0, 0, 0]);
+        assert_eq!(system.read_memory_region(0x0300, 0x037F), vec![7; 128]);
+
+        // The current record follows the 32 bytes written back across 0xFFFF
+        assert_eq!(system.read_memory_region(0x0010, 0x0010), vec![1]);
+
+        let mut expected = system.read_memory_region(0xFFC0, 0xFFFF);
+        expected.extend(system.read_memory_region(0x0000, 0x003F));
+
+        assert_eq!(fs::read(directory.join("OUT.DAT")).unwrap(), expected);
+        assert_eq!(&expected[..0x30], &[0xAA; 0x30]);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn should_keep_command_tail_below_the_tpa() {
+        let argument = "x".repeat(200);
+        let mut system = BdosSystem::new(BufferConsole::new());
+        system.load_program(vec![0xC9]);
+        system.set_command_line(&[&argument]);
+
+        let page = system.read_memory_region(COMMAND_TAIL, TPA_START);
+
+        assert_eq!(page[0], 126);
+        assert_eq!(page[1], b' ');
+        assert_eq!(&page[2..127], &[b'X'; 125]);
+        assert_eq!(page[127], 0);
+        assert_eq!(page[128], 0xC9);
+    }
+}
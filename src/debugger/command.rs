@@ -0,0 +1,203 @@
+use std::ops::RangeInclusive;
+
+use crate::{
+    symbols,
+    system::breakpoint::{BreakpointId, WatchKind},
+};
+
+/// Command entered at the debugger prompt
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Step(usize),
+    Next,
+    Continue,
+    Break(u16),
+    Watch(RangeInclusive<u16>, WatchKind),
+    WatchPort(u8, WatchKind),
+    Delete(BreakpointId),
+    ListBreakpoints,
+    Registers,
+    Memory(Option<u16>, usize),
+    Disassemble(Option<u16>, usize),
+    SetRegister(String, u16),
+    WriteMemory(u16, Vec<u8>),
+    Help,
+    Quit,
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    symbols::parse_number(text).ok_or_else(|| format!("invalid number '{}'", text))
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    u16::try_from(parse_number(text)?).map_err(|_| format!("address '{}' is out of range", text))
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+    u8::try_from(parse_number(text)?).map_err(|_| format!("byte '{}' is out of range", text))
+}
+
+fn parse_optional_address(text: Option<&&str>) -> Result<Option<u16>, String> {
+    text.map(|text| parse_address(text)).transpose()
+}
+
+fn parse_count(text: Option<&&str>, default: usize) -> Result<usize, String> {
+    text.map_or(Ok(default), |text| parse_number(text))
+}
+
+fn parse_id(text: &str) -> Result<BreakpointId, String> {
+    text.parse()
+        .map_err(|_| format!("invalid breakpoint number '{}'", text))
+}
+
+/// Splits off a leading access kind, `r`, `w` or `rw`, defaulting to `default`
+fn parse_watch_kind<'a>(words: &'a [&'a str], default: WatchKind) -> (WatchKind, &'a [&'a str]) {
+    let kind = match words.first().copied() {
+        Some("r") => WatchKind::Read,
+        Some("w") => WatchKind::Write,
+        Some("rw") => WatchKind::ReadWrite,
+        _ => return (default, words),
+    };
+
+    (kind, &words[1..])
+}
+
+fn require<'a>(text: Option<&&'a str>, name: &str) -> Result<&'a str, String> {
+    text.copied().ok_or_else(|| format!("missing {}", name))
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let name = words.first().copied().unwrap_or_default();
+
+        let command = match name {
+            "s" | "step" => Command::Step(parse_count(words.get(1), 1)?),
+            "n" | "next" => Command::Next,
+            "c" | "continue" => Command::Continue,
+            "b" | "break" => Command::Break(parse_address(require(words.get(1), "address")?)?),
+            "watch" => {
+                let (kind, words) = parse_watch_kind(&words[1..], WatchKind::Write);
+                let start = parse_address(require(words.first(), "address")?)?;
+                let length = parse_count(words.get(1), 1)?.max(1);
+                let end = start
+                    .checked_add((length - 1) as u16)
+                    .filter(|_| length <= 0x10000)
+                    .ok_or_else(|| String::from("range runs past 0xffff"))?;
+
+                Command::Watch(start..=end, kind)
+            }
+            "pwatch" => {
+                let (kind, words) = parse_watch_kind(&words[1..], WatchKind::ReadWrite);
+                let port = parse_byte(require(words.first(), "port")?)
+                    .map_err(|_| String::from("port is out of range"))?;
+
+                Command::WatchPort(port, kind)
+            }
+            "delete" => Command::Delete(parse_id(require(words.get(1), "breakpoint number")?)?),
+            "bl" | "breakpoints" => Command::ListBreakpoints,
+            "r" | "regs" => Command::Registers,
+            "x" | "mem" => Command::Memory(
+                parse_optional_address(words.get(1))?,
+                parse_count(words.get(2), 0x40)?,
+            ),
+            "d" | "disasm" => Command::Disassemble(
+                parse_optional_address(words.get(1))?,
+                parse_count(words.get(2), 10)?,
+            ),
+            "set" => Command::SetRegister(
+                require(words.get(1), "register")?.to_ascii_lowercase(),
+                u16::try_from(parse_number(require(words.get(2), "value")?)?)
+                    .map_err(|_| String::from("value is out of range"))?,
+            ),
+            "w" | "write" => Command::WriteMemory(
+                parse_address(require(words.get(1), "address")?)?,
+                words[2..]
+                    .iter()
+                    .map(|text| parse_byte(text))
+                    .collect::<Result<_, _>>()?,
+            ),
+            "h" | "help" | "?" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("unknown command '{}'", name)),
+        };
+
+        if let Command::WriteMemory(_, bytes) = &command {
+            if bytes.is_empty() {
+                return Err(String::from("missing bytes"));
+            }
+        }
+
+        Ok(command)
+    }
+}
+
+pub const HELP: &str = "\
+Numbers are decimal, or hexadecimal with a 0x prefix or H suffix.
+
+  s, step [COUNT]             Execute COUNT instructions (default 1)
+  n, next                     Step over CALL and RST instructions
+  c, continue                 Run until a breakpoint or the machine stops
+  b, break ADDRESS            Set a breakpoint
+  watch [r|w|rw] ADDRESS [LENGTH]
+                              Stop after memory is read or written (default: w, 1 byte)
+  pwatch [r|w|rw] PORT        Stop after an IN or OUT on a port (default: rw)
+  bl, breakpoints             List breakpoints and watchpoints
+  delete NUMBER               Remove a breakpoint or watchpoint by its decimal number
+  r, regs                     Show registers and flags
+  x, mem [ADDRESS] [LENGTH]   Dump memory (default: HL, 0x40 bytes)
+  d, disasm [ADDRESS] [COUNT] Disassemble instructions (default: PC, 10 instructions)
+  set NAME VALUE              Set a register (a-l, bc, de, hl, sp, pc, psw)
+                              or flag (s, z, ac, p, cy)
+  w, write ADDRESS BYTE...    Write bytes to memory
+  h, help                     Show this message
+  q, quit                     Exit the debugger
+
+An empty line repeats the previous command.";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_commands() {
+        assert_eq!(Command::parse("s"), Ok(Command::Step(1)));
+        assert_eq!(Command::parse("step 10"), Ok(Command::Step(10)));
+        assert_eq!(
+            Command::parse("x 0x100 20h"),
+            Ok(Command::Memory(Some(0x0100), 0x20))
+        );
+        assert_eq!(
+            Command::parse("set SP 0ff00h"),
+            Ok(Command::SetRegister(String::from("sp"), 0xFF00))
+        );
+        assert_eq!(
+            Command::parse("w 0x2000 1 0x02"),
+            Ok(Command::WriteMemory(0x2000, vec![0x01, 0x02]))
+        );
+        assert!(Command::parse("w 2000").is_err());
+        assert!(Command::parse("w 2000 256").is_err());
+        assert!(Command::parse("w 2000 ff").is_err());
+        assert!(Command::parse("jump").is_err());
+    }
+
+    #[test]
+    fn should_parse_watchpoints() {
+        assert_eq!(
+            Command::parse("watch 0x2000"),
+            Ok(Command::Watch(0x2000..=0x2000, WatchKind::Write))
+        );
+        assert_eq!(
+            Command::parse("watch rw 2000h 16"),
+            Ok(Command::Watch(0x2000..=0x200F, WatchKind::ReadWrite))
+        );
+        assert_eq!(
+            Command::parse("pwatch r 0x10"),
+            Ok(Command::WatchPort(0x10, WatchKind::Read))
+        );
+        assert_eq!(Command::parse("delete 12"), Ok(Command::Delete(12)));
+        assert!(Command::parse("watch 0xffff 2").is_err());
+        assert!(Command::parse("pwatch 256").is_err());
+        assert!(Command::parse("delete 1a").is_err());
+    }
+}
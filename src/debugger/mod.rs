@@ -0,0 +1,516 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    disassembler::Line,
+    internal::{
+        instructions::{Instruction, RegisterPair},
+        program_counter::decode,
+    },
+    symbols::SymbolTable,
+    system::{
+        breakpoint::{AccessKind, BreakpointId, Breakpoints, StopReason, WatchKind},
+        dump::hex_dump,
+        Machine, State,
+    },
+};
+
+use command::{Command, HELP};
+
+mod command;
+
+const PROMPT: &str = "(8080) ";
+
+/// Decodes the instruction at `address` without moving the program counter,
+/// returning it along with its encoded bytes
+fn decode_at(state: &State, address: u16) -> (Instruction, Vec<u8>) {
+    let mut bytes = vec![];
+    let instruction = decode(|| {
+        let byte = state
+            .memory
+            .inspect(address.wrapping_add(bytes.len() as u16));
+        bytes.push(byte);
+        byte
+    });
+
+    (instruction, bytes)
+}
+
+fn set_register(state: &mut State, name: &str, value: u16) -> Result<(), String> {
+    let byte = || u8::try_from(value).map_err(|_| format!("{:#x} does not fit in {}", value, name));
+    let flag = || match value {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(format!("flag {} must be 0 or 1", name)),
+    };
+
+    match name {
+        "a" => state.registers.a = byte()?,
+        "b" => state.registers.b = byte()?,
+        "c" => state.registers.c = byte()?,
+        "d" => state.registers.d = byte()?,
+        "e" => state.registers.e = byte()?,
+        "h" => state.registers.h = byte()?,
+        "l" => state.registers.l = byte()?,
+        "bc" => state.set_register_pair(&RegisterPair::BC, value),
+        "de" => state.set_register_pair(&RegisterPair::DE, value),
+        "hl" => state.set_register_pair(&RegisterPair::HL, value),
+        "sp" => state.set_register_pair(&RegisterPair::SP, value),
+        "pc" => state.program_counter.set(value),
+        "psw" => state.set_psw(value),
+        "s" => state.condition_flags.sign = flag()?,
+        "z" => state.condition_flags.zero = flag()?,
+        "ac" => state.condition_flags.aux_carry = flag()?,
+        "p" => state.condition_flags.parity = flag()?,
+        "cy" => state.condition_flags.carry = flag()?,
+        _ => return Err(format!("unknown register '{}'", name)),
+    }
+
+    Ok(())
+}
+
+fn describe_kind(kind: WatchKind) -> &'static str {
+    match kind {
+        WatchKind::Read => "read",
+        WatchKind::Write => "write",
+        WatchKind::ReadWrite => "access",
+    }
+}
+
+/// Describes why a run stopped, or `None` for the machine stopping by itself
+fn describe_stop(reason: StopReason, program_counter: u16) -> Option<String> {
+    let access = |kind| match kind {
+        AccessKind::Read => ("Read", "from"),
+        AccessKind::Write => ("Write", "to"),
+    };
+
+    match reason {
+        StopReason::Breakpoint(_) => Some(format!("Breakpoint at {:#06x}", program_counter)),
+        StopReason::MemoryWatchpoint(id, memory_access) => {
+            let (action, preposition) = access(memory_access.kind);
+
+            Some(format!(
+                "Watchpoint {}: {} of {:#04x} {} {:#06x}",
+                id, action, memory_access.value, preposition, memory_access.address
+            ))
+        }
+        StopReason::PortWatchpoint(id, port_access) => {
+            let (action, preposition) = access(port_access.kind);
+
+            Some(format!(
+                "Watchpoint {}: {} of {:#04x} {} port {:#04x}",
+                id, action, port_access.value, preposition, port_access.port
+            ))
+        }
+        StopReason::CycleLimit | StopReason::Halted | StopReason::PoweredOff => None,
+    }
+}
+
+/// Interactive debugger over any machine, reading commands line by line
+pub struct Debugger<'a, M: Machine> {
+    machine: &'a mut M,
+    breakpoints: Breakpoints,
+    descriptions: BTreeMap<BreakpointId, String>,
+    symbols: SymbolTable,
+}
+
+impl<'a, M: Machine> Debugger<'a, M> {
+    pub fn new(machine: &'a mut M) -> Self {
+        Debugger {
+            machine,
+            breakpoints: Breakpoints::default(),
+            descriptions: BTreeMap::new(),
+            symbols: SymbolTable::new(),
+        }
+    }
+
+    /// Uses `symbols` to label addresses in disassembly and register output
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// Runs the read-eval-print loop until `quit` or the end of input
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut lines = input.lines();
+        let mut previous_line = String::new();
+
+        self.show_instruction(&mut output)?;
+
+        loop {
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+
+            let line = if line.trim().is_empty() {
+                previous_line.clone()
+            } else {
+                line
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match Command::parse(&line) {
+                Ok(Command::Quit) => return Ok(()),
+                Ok(command) => self.execute(command, &mut output)?,
+                Err(message) => writeln!(output, "error: {}", message)?,
+            }
+
+            previous_line = line;
+        }
+    }
+
+    fn execute(&mut self, command: Command, output: &mut impl Write) -> io::Result<()> {
+        let state = self.machine.get_state();
+
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if !self.step(output)? {
+                        break;
+                    }
+                }
+
+                self.show_instruction(output)?;
+            }
+            Command::Next => {
+                let program_counter = state.program_counter.get();
+                let (instruction, bytes) = decode_at(state, program_counter);
+
+                match instruction {
+                    Instruction::Call(_)
+                    | Instruction::ConditionalCall(_, _)
+                    | Instruction::Restart(_) => {
+                        let return_address = program_counter.wrapping_add(bytes.len() as u16);
+                        self.run_until(Some(return_address), output)?;
+                    }
+                    _ => {
+                        self.step(output)?;
+                    }
+                }
+
+                self.show_instruction(output)?;
+            }
+            Command::Continue => {
+                self.run_until(None, output)?;
+                self.show_instruction(output)?;
+            }
+            Command::Break(address) => {
+                let id = self.breakpoints.add_address(address);
+                self.add_description(id, format!("Breakpoint {} at {:#06x}", id, address), output)?;
+            }
+            Command::Watch(addresses, kind) => {
+                let (start, end) = (*addresses.start(), *addresses.end());
+                let id = self.breakpoints.add_memory_watch(addresses, kind);
+                let description = format!(
+                    "Watchpoint {} on {} of {:#06x}..={:#06x}",
+                    id,
+                    describe_kind(kind),
+                    start,
+                    end
+                );
+                self.add_description(id, description, output)?;
+            }
+            Command::WatchPort(port, kind) => {
+                let id = self.breakpoints.add_port_watch(port, kind);
+                let description = format!(
+                    "Watchpoint {} on {} of port {:#04x}",
+                    id,
+                    describe_kind(kind),
+                    port
+                );
+                self.add_description(id, description, output)?;
+            }
+            Command::Delete(id) => {
+                if self.breakpoints.remove(id) {
+                    self.descriptions.remove(&id);
+                } else {
+                    writeln!(output, "No breakpoint number {}", id)?;
+                }
+            }
+            Command::ListBreakpoints => {
+                if self.descriptions.is_empty() {
+                    writeln!(output, "No breakpoints or watchpoints")?;
+                }
+
+                for description in self.descriptions.values() {
+                    writeln!(output, "{}", description)?;
+                }
+            }
+            Command::Registers => writeln!(output, "{}", state.display_with(&self.symbols))?,
+            Command::Memory(address, length) => {
+                let start = address.unwrap_or(state.get_register_pair(&RegisterPair::HL));
+                let length = length.clamp(1, 0x10000 - start as usize);
+                let end = start + (length - 1) as u16;
+
+                write!(
+                    output,
+                    "{}",
+                    hex_dump(start, &state.memory.inspect_range(start, end))
+                )?;
+            }
+            Command::Disassemble(address, count) => {
+                let mut address = address.unwrap_or(state.program_counter.get());
+
+                for _ in 0..count {
+                    address = self.show_instruction_at(address, output)?;
+                }
+            }
+            Command::SetRegister(name, value) => {
+                if let Err(message) = set_register(self.machine.get_state_mut(), &name, value) {
+                    writeln!(output, "error: {}", message)?;
+                }
+            }
+            Command::WriteMemory(address, bytes) => {
+                self.machine.get_state_mut().memory.load(address, bytes);
+            }
+            Command::Help => writeln!(output, "{}", HELP)?,
+            Command::Quit => {}
+        }
+
+        Ok(())
+    }
+
+    fn add_description(
+        &mut self,
+        id: BreakpointId,
+        description: String,
+        output: &mut impl Write,
+    ) -> io::Result<()> {
+        writeln!(output, "{}", description)?;
+        self.descriptions.insert(id, description);
+
+        Ok(())
+    }
+
+    /// Executes one instruction, returning whether the machine made progress
+    fn step(&mut self, output: &mut impl Write) -> io::Result<bool> {
+        if self.machine.step() == 0 {
+            let state = self.machine.get_state();
+            let reason = if !state.enabled {
+                "powered off"
+            } else {
+                "halted"
+            };
+
+            writeln!(output, "Machine is {}", reason)?;
+
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn run_until(&mut self, stop_address: Option<u16>, output: &mut impl Write) -> io::Result<()> {
+        let state = self.machine.get_state_mut();
+        state
+            .memory
+            .set_recording(self.breakpoints.has_memory_watches());
+        state
+            .ports
+            .set_recording(self.breakpoints.has_port_watches());
+
+        while self.step(output)? {
+            let state = self.machine.get_state_mut();
+            let memory_accesses = state.memory.take_accesses();
+            let port_accesses = state.ports.take_accesses();
+            let program_counter = state.program_counter.get();
+
+            let reason = self
+                .breakpoints
+                .check_accesses(&memory_accesses, &port_accesses)
+                .or_else(|| self.breakpoints.check_state(state));
+
+            if let Some(message) = reason.and_then(|reason| describe_stop(reason, program_counter))
+            {
+                writeln!(output, "{}", message)?;
+                break;
+            }
+
+            if stop_address == Some(program_counter) {
+                break;
+            }
+        }
+
+        let state = self.machine.get_state_mut();
+        state.memory.set_recording(false);
+        state.ports.set_recording(false);
+
+        Ok(())
+    }
+
+    fn show_instruction(&self, output: &mut impl Write) -> io::Result<()> {
+        let program_counter = self.machine.get_state().program_counter.get();
+
+        self.show_instruction_at(program_counter, output)?;
+
+        Ok(())
+    }
+
+    /// Prints the instruction at `address`, returning the address following it
+    fn show_instruction_at(&self, address: u16, output: &mut impl Write) -> io::Result<u16> {
+        let state = self.machine.get_state();
+        let (instruction, bytes) = decode_at(state, address);
+
+        let marker = if address == state.program_counter.get() {
+            "=>"
+        } else {
+            "  "
+        };
+        let next_address = address.wrapping_add(bytes.len() as u16);
+        let line = Line {
+            address,
+            bytes,
+            instruction: None,
+        };
+
+        if let Some(name) = self.symbols.get_name(address) {
+            writeln!(output, "{}:", name)?;
+        }
+
+        writeln!(
+            output,
+            "{} {}{}",
+            marker,
+            line.format_prefix(),
+            instruction.display_with(&self.symbols)
+        )?;
+
+        Ok(next_address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{test::TestSystem, System};
+
+    fn run_session(machine: &mut impl Machine, script: &str) -> String {
+        let mut output = vec![];
+
+        Debugger::new(machine)
+            .run(script.as_bytes(), &mut output)
+            .unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn should_step_and_show_state() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x3E, 0x12, // MVI A, 0x12
+            0x06, 0x34, // MVI B, 0x34
+            0x76, // HLT
+        ]);
+
+        let output = run_session(&mut system, "s\n\nregs\nq\n");
+
+        assert!(output.contains("=> 0000  3e 12     MVI     A, 0x12"));
+        assert!(output.contains("=> 0004  76        HLT"));
+        assert!(output.contains("A=0x12 B=0x34"));
+    }
+
+    #[test]
+    fn should_step_over_calls_and_stop_at_breakpoints() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x31, 0x00, 0x10, // LXI SP, 0x1000
+            0xCD, 0x0A, 0x00, // CALL 0x000A
+            0x3C, // INR A
+            0x3C, // INR A
+            0x76, // HLT
+            0x00, // NOP
+            0x06, 0x01, // MVI B, 0x01
+            0xC9, // RET
+        ]);
+
+        let output = run_session(&mut system, "s\nn\nb 7\nc\nregs\nc\n");
+
+        assert!(output.contains("=> 0006  3c        INR     A"));
+        assert!(output.contains("Breakpoint at 0x0007"));
+        assert!(output.contains("A=0x01 B=0x01"));
+        assert!(output.contains("Machine is halted"));
+    }
+
+    #[test]
+    fn should_show_symbols() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0xCD, 0x04, 0x00, // CALL 0x0004
+            0x76, // HLT
+            0xC9, // RET
+        ]);
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert("START", 0x0000);
+        symbols.insert("DONE", 0x0004);
+
+        let mut output = vec![];
+        let mut debugger = Debugger::new(&mut system);
+        debugger.set_symbols(symbols);
+        debugger.run("s\nregs\n".as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("START:\n=> 0000  cd 04 00  CALL    DONE"));
+        assert!(output.contains("<DONE>\nPC=0x0004"));
+    }
+
+    #[test]
+    fn should_modify_registers_and_memory() {
+        let mut system = TestSystem::new();
+
+        let output = run_session(
+            &mut system,
+            "set hl 0x200\nset cy 1\nw 200h 41h 42h\nx\nw 0xfffe 67 68\nx 0xfffe 65537\nset q 1\nq\n",
+        );
+
+        assert!(output.contains("0200  41 42"));
+        assert!(output.contains("fffe  43 44 "));
+        assert!(output.contains("error: unknown register 'q'"));
+        assert!(system.state.condition_flags.carry);
+    }
+
+    #[test]
+    fn should_stop_at_watchpoints() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x3E, 0x05, // MVI A, 0x05
+            0x32, 0x00, 0x20, // STA 0x2000
+            0xD3, 0x10, // OUT 0x10
+            0x3A, 0x00, 0x20, // LDA 0x2000
+            0x76, // HLT
+        ]);
+
+        let output = run_session(
+            &mut system,
+            "watch 0x2000
+pwatch w 0x10
+bl
+c
+c
+delete 0
+watch r 0x1fff 2
+c
+delete 5
+c
+",
+        );
+
+        assert!(output.contains("Watchpoint 0 on write of 0x2000..=0x2000"));
+        assert!(output.contains("Watchpoint 1 on write of port 0x10"));
+        assert!(output.contains("Watchpoint 0: Write of 0x05 to 0x2000\n=> 0005"));
+        assert!(output.contains("Watchpoint 1: Write of 0x05 to port 0x10\n=> 0007"));
+        assert!(output.contains("Watchpoint 2: Read of 0x05 from 0x2000\n=> 000a"));
+        assert!(output.contains("No breakpoint number 5"));
+        assert!(output.contains("Machine is halted"));
+    }
+}
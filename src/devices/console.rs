@@ -0,0 +1,420 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, BufReader, Read, Write},
+    rc::Rc,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use crate::system::PortDevice;
+
+const CARRIAGE_RETURN: u8 = b'\r';
+const LINE_FEED: u8 = b'\n';
+
+/// Control-], which leaves the emulator like the telnet escape
+pub const DEFAULT_ESCAPE: u8 = 0x1D;
+
+/// Host character stream behind a console, such as the CP/M console or a
+/// serial line
+pub trait Console {
+    /// Whether a character can be read without waiting
+    fn is_ready(&mut self) -> bool;
+
+    /// Waits for a character, returning `None` once input has ended
+    fn read(&mut self) -> Option<u8>;
+
+    fn write(&mut self, value: u8);
+
+    /// Writes a character unless the line cannot take it yet, returning
+    /// whether it was written
+    fn try_write(&mut self, value: u8) -> bool {
+        self.write(value);
+        true
+    }
+
+    /// Whether input has ended, so that no character will ever be ready
+    fn is_ended(&mut self) -> bool {
+        false
+    }
+}
+
+impl<T: Console> Console for Rc<RefCell<T>> {
+    fn is_ready(&mut self) -> bool {
+        self.borrow_mut().is_ready()
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.borrow_mut().read()
+    }
+
+    fn write(&mut self, value: u8) {
+        self.borrow_mut().write(value)
+    }
+
+    fn try_write(&mut self, value: u8) -> bool {
+        self.borrow_mut().try_write(value)
+    }
+
+    fn is_ended(&mut self) -> bool {
+        self.borrow_mut().is_ended()
+    }
+}
+
+/// Console fed from a queue of input, collecting output in memory
+#[derive(Debug, Default)]
+pub struct BufferConsole {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl BufferConsole {
+    pub fn new() -> Self {
+        BufferConsole::default()
+    }
+
+    pub fn push_input(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl Console for BufferConsole {
+    fn is_ready(&mut self) -> bool {
+        !self.input.is_empty()
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, value: u8) {
+        self.output.push(value);
+    }
+}
+
+/// Reads `input` on a background thread, sending each byte until it ends
+pub(crate) fn spawn_reader(input: impl Read + Send + 'static) -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for byte in BufReader::new(input).bytes() {
+            let Ok(byte) = byte else {
+                break;
+            };
+
+            if sender.send(byte).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Console on the standard streams of the host process
+///
+/// Standard input is read on a background thread, so that polling for a
+/// character never blocks.
+pub struct StdConsole {
+    input: Receiver<u8>,
+    pending: Option<u8>,
+}
+
+impl StdConsole {
+    pub fn new() -> Self {
+        StdConsole {
+            input: spawn_reader(io::stdin()),
+            pending: None,
+        }
+    }
+}
+
+impl Default for StdConsole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Console for StdConsole {
+    fn is_ready(&mut self) -> bool {
+        // Programs poll for input while waiting, so show what they printed
+        let _ = io::stdout().flush();
+
+        if self.pending.is_none() {
+            match self.input.try_recv() {
+                Ok(byte) => self.pending = Some(byte),
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => {}
+            }
+        }
+
+        self.pending.is_some()
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        let _ = io::stdout().flush();
+
+        self.pending.take().or_else(|| self.input.recv().ok())
+    }
+
+    fn write(&mut self, value: u8) {
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(&[value]);
+
+        if value == b'\n' {
+            let _ = stdout.flush();
+        }
+    }
+}
+
+/// Host terminal switched to raw mode, restored when dropped
+///
+/// Keys reach the program one at a time without echo, and control
+/// characters such as control-C are passed through instead of signalling
+/// the emulator.
+#[cfg(unix)]
+pub struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    /// Switches standard input to raw mode, failing if it is not a terminal
+    pub fn enable() -> io::Result<Self> {
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+
+        // SAFETY: tcgetattr fills in the termios structure on success
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            termios.assume_init()
+        };
+
+        let mut raw = original;
+
+        // SAFETY: raw is a valid termios structure
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(RawMode { original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: original was filled in by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Console terminal on a status port and a data port
+///
+/// Reading the status port reports whether a key is waiting and whether
+/// output can be written, reading the data port takes the key, and writing
+/// the data port prints a character. The status bits default to bit 0 for
+/// input ready and bit 1 for output ready, as on the MITS 88-2SIO.
+///
+/// With newline translation, line feeds typed or piped in are read as
+/// carriage returns, and line feeds printed without a carriage return get
+/// one, as raw mode no longer adds it. Typing the escape key sets a flag for
+/// the host to stop at, rather than passing the key to the program.
+pub struct ConsoleDevice {
+    console: Box<dyn Console>,
+    status_port: u8,
+    data_port: u8,
+    input_ready: u8,
+    output_ready: u8,
+    active_low: bool,
+    translate_newlines: bool,
+    escape: Option<u8>,
+    escaped: bool,
+    pending: Option<u8>,
+    last_output: u8,
+}
+
+impl ConsoleDevice {
+    pub fn new(console: impl Console + 'static, status_port: u8, data_port: u8) -> Self {
+        ConsoleDevice {
+            console: Box::new(console),
+            status_port,
+            data_port,
+            input_ready: 0x01,
+            output_ready: 0x02,
+            active_low: false,
+            translate_newlines: true,
+            escape: Some(DEFAULT_ESCAPE),
+            escaped: false,
+            pending: None,
+            last_output: 0,
+        }
+    }
+
+    /// Sets the status bits reporting a waiting key and room for output,
+    /// which read as 0 when set if `active_low`, as on the MITS 88-SIO
+    pub fn set_status_bits(&mut self, input_ready: u8, output_ready: u8, active_low: bool) {
+        self.input_ready = input_ready;
+        self.output_ready = output_ready;
+        self.active_low = active_low;
+    }
+
+    pub fn set_translate_newlines(&mut self, translate_newlines: bool) {
+        self.translate_newlines = translate_newlines;
+    }
+
+    /// Sets the key which stops the emulator, or `None` to pass every key on
+    pub fn set_escape(&mut self, escape: Option<u8>) {
+        self.escape = escape;
+    }
+
+    /// Whether the escape key has been typed
+    pub fn is_escaped(&self) -> bool {
+        self.escaped
+    }
+
+    /// Takes a waiting key into `pending`, holding back the escape key
+    ///
+    /// Port reads poll by themselves, the host polls while the program is
+    /// halted for the escape key to be seen.
+    pub fn poll(&mut self) {
+        while self.pending.is_none() && self.console.is_ready() {
+            let Some(value) = self.console.read() else {
+                break;
+            };
+
+            if Some(value) == self.escape {
+                self.escaped = true;
+            } else if self.translate_newlines && value == LINE_FEED {
+                self.pending = Some(CARRIAGE_RETURN);
+            } else {
+                self.pending = Some(value);
+            }
+        }
+    }
+
+    fn get_status(&mut self) -> u8 {
+        self.poll();
+
+        let mut status = self.output_ready;
+
+        if self.pending.is_some() {
+            status |= self.input_ready;
+        }
+
+        if self.active_low {
+            status ^= self.input_ready | self.output_ready;
+        }
+
+        status
+    }
+
+    fn print(&mut self, value: u8) {
+        if self.translate_newlines && value == LINE_FEED && self.last_output != CARRIAGE_RETURN {
+            self.console.write(CARRIAGE_RETURN);
+        }
+
+        self.console.write(value);
+        self.last_output = value;
+    }
+}
+
+impl PortDevice for ConsoleDevice {
+    fn read(&mut self, port: u8, _cycles: usize) -> u8 {
+        if port == self.status_port {
+            return self.get_status();
+        }
+
+        if port == self.data_port {
+            self.poll();
+            return self.pending.take().unwrap_or(0);
+        }
+
+        0xFF
+    }
+
+    fn write(&mut self, port: u8, value: u8, _cycles: usize) {
+        if port == self.data_port {
+            self.print(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::system::System;
+
+    #[test]
+    fn should_poll_read_and_translate() {
+        let console = Rc::new(RefCell::new(BufferConsole::new()));
+        let mut device = ConsoleDevice::new(console.clone(), 0x10, 0x11);
+
+        assert_eq!(device.read(0x10, 0), 0x02);
+        assert_eq!(device.read(0x11, 0), 0x00);
+
+        console.borrow_mut().push_input(b"a\n\x1Db");
+
+        assert_eq!(device.read(0x10, 0), 0x03);
+        assert_eq!(device.read(0x11, 0), b'a');
+        assert_eq!(device.read(0x11, 0), b'\r');
+        assert!(!device.is_escaped());
+        assert_eq!(device.read(0x11, 0), b'b');
+        assert!(device.is_escaped());
+
+        device.write(0x11, b'x', 0);
+        device.write(0x11, b'\n', 0);
+        device.write(0x11, b'\r', 0);
+        device.write(0x11, b'\n', 0);
+        device.write(0x10, b'y', 0);
+
+        assert_eq!(console.borrow_mut().take_output(), b"x\r\n\r\n");
+
+        device.set_status_bits(0x20, 0x80, true);
+        device.set_translate_newlines(false);
+        device.set_escape(None);
+        console.borrow_mut().push_input(b"\n\x1D");
+
+        assert_eq!(device.read(0x10, 0), 0x00);
+        assert_eq!(device.read(0x11, 0), b'\n');
+        assert_eq!(device.read(0x11, 0), 0x1D);
+        assert_eq!(device.read(0x10, 0), 0x20);
+    }
+
+    #[test]
+    fn should_echo_through_ports() {
+        let console = Rc::new(RefCell::new(BufferConsole::new()));
+        console.borrow_mut().push_input(b"hi");
+
+        let mut system = System::new();
+        system.register_port_range_device(
+            0x10..=0x11,
+            ConsoleDevice::new(console.clone(), 0x10, 0x11),
+        );
+
+        system.load_program(vec![
+            0xDB, 0x10, // IN 0x10
+            0xE6, 0x01, // ANI 0x01
+            0xCA, 0x00, 0x00, // JZ 0x0000
+            0xDB, 0x11, // IN 0x11
+            0xD3, 0x11, // OUT 0x11
+            0xC3, 0x00, 0x00, // JMP 0x0000
+        ]);
+        system.run(1000);
+
+        assert_eq!(console.borrow_mut().take_output(), b"hi");
+    }
+}
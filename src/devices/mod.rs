@@ -0,0 +1,4 @@
+pub mod console;
+pub mod pic;
+pub mod serial;
+pub mod usart;
@@ -0,0 +1,497 @@
+use crate::system::{InterruptController, PortDevice};
+
+const CALL_OPCODE: u8 = 0xCD;
+
+/// Level reported when an acknowledge happens without a pending request
+const SPURIOUS_LEVEL: u8 = 7;
+
+#[derive(Debug, PartialEq, Eq)]
+enum InitializationStep {
+    Ready,
+    AwaitingIcw2,
+    AwaitingIcw3,
+    AwaitingIcw4,
+}
+
+/// Intel 8259A programmable interrupt controller in 8080 (MCS-80) mode
+///
+/// The controller occupies two consecutive ports, selected by bit 0 of the
+/// port number (the A0 pin). It should be registered on those ports and
+/// attached as the system's interrupt controller, after which the host
+/// drives the eight IRQ lines with `set_irq_line`.
+#[derive(Debug)]
+pub struct Pic8259 {
+    initialization_step: InitializationStep,
+    requires_icw4: bool,
+    single: bool,
+    level_triggered: bool,
+    address_interval_4: bool,
+    vector_low_byte: u8,
+    vector_high_byte: u8,
+    cascade: u8,
+    auto_eoi: bool,
+    rotate_on_auto_eoi: bool,
+    special_mask: bool,
+    read_in_service: bool,
+    poll: bool,
+    lowest_priority: u8,
+    irq_lines: u8,
+    interrupt_request: u8,
+    in_service: u8,
+    interrupt_mask: u8,
+}
+
+impl Pic8259 {
+    pub fn new() -> Self {
+        Pic8259 {
+            initialization_step: InitializationStep::Ready,
+            requires_icw4: false,
+            single: true,
+            level_triggered: false,
+            address_interval_4: false,
+            vector_low_byte: 0,
+            vector_high_byte: 0,
+            cascade: 0,
+            auto_eoi: false,
+            rotate_on_auto_eoi: false,
+            special_mask: false,
+            read_in_service: false,
+            poll: false,
+            lowest_priority: 7,
+            irq_lines: 0,
+            interrupt_request: 0,
+            in_service: 0,
+            // All levels stay masked until the controller is programmed
+            interrupt_mask: 0xFF,
+        }
+    }
+
+    /// Sets the state of an IRQ line
+    ///
+    /// In edge triggered mode a request is latched on the rising edge, in
+    /// level triggered mode the request follows the line.
+    pub fn set_irq_line(&mut self, line: u8, asserted: bool) {
+        let bit = 1 << (line & 0x07);
+        let was_asserted = self.irq_lines & bit != 0;
+
+        if asserted {
+            self.irq_lines |= bit;
+
+            if self.level_triggered || !was_asserted {
+                self.interrupt_request |= bit;
+            }
+        } else {
+            self.irq_lines &= !bit;
+
+            if self.level_triggered {
+                self.interrupt_request &= !bit;
+            }
+        }
+    }
+
+    /// Raises and lowers an IRQ line, which latches a request in edge
+    /// triggered mode
+    pub fn pulse_irq_line(&mut self, line: u8) {
+        self.set_irq_line(line, true);
+        self.set_irq_line(line, false);
+    }
+
+    pub fn get_interrupt_request_register(&self) -> u8 {
+        self.interrupt_request
+    }
+
+    pub fn get_in_service_register(&self) -> u8 {
+        self.in_service
+    }
+
+    pub fn get_interrupt_mask_register(&self) -> u8 {
+        self.interrupt_mask
+    }
+
+    /// Levels ordered from highest to lowest priority
+    fn get_priority_order(&self) -> impl Iterator<Item = u8> {
+        let highest_priority = (self.lowest_priority + 1) & 0x07;
+
+        (0..8).map(move |offset| (highest_priority + offset) & 0x07)
+    }
+
+    fn get_highest_pending_level(&self) -> Option<u8> {
+        let pending = self.interrupt_request & !self.interrupt_mask;
+
+        for level in self.get_priority_order() {
+            let bit = 1 << level;
+
+            // Fully nested mode: nothing at or below an in-service level is
+            // granted, special mask mode only inhibits the level itself
+            if self.in_service & bit != 0 {
+                if !self.special_mask {
+                    return None;
+                }
+
+                continue;
+            }
+
+            if pending & bit != 0 {
+                return Some(level);
+            }
+        }
+
+        None
+    }
+
+    fn get_highest_in_service_level(&self) -> Option<u8> {
+        self.get_priority_order()
+            .find(|level| self.in_service & (1 << level) != 0)
+    }
+
+    /// Moves the highest priority request into service, returning its level
+    fn accept_request(&mut self) -> Option<u8> {
+        let level = self.get_highest_pending_level()?;
+        let bit = 1 << level;
+
+        self.interrupt_request &= !bit;
+
+        if self.level_triggered && self.irq_lines & bit != 0 {
+            self.interrupt_request |= bit;
+        }
+
+        if self.auto_eoi {
+            if self.rotate_on_auto_eoi {
+                self.lowest_priority = level;
+            }
+        } else {
+            self.in_service |= bit;
+        }
+
+        Some(level)
+    }
+
+    fn get_vector_address(&self, level: u8) -> u16 {
+        let low_byte = if self.address_interval_4 {
+            (self.vector_low_byte & 0xE0) | (level << 2)
+        } else {
+            (self.vector_low_byte & 0xC0) | (level << 3)
+        };
+
+        u16::from_be_bytes([self.vector_high_byte, low_byte])
+    }
+
+    fn write_icw1(&mut self, value: u8) {
+        self.initialization_step = InitializationStep::AwaitingIcw2;
+        self.requires_icw4 = value & 0x01 != 0;
+        self.single = value & 0x02 != 0;
+        self.address_interval_4 = value & 0x04 != 0;
+        self.level_triggered = value & 0x08 != 0;
+        self.vector_low_byte = value & 0xE0;
+
+        self.auto_eoi = false;
+        self.rotate_on_auto_eoi = false;
+        self.special_mask = false;
+        self.read_in_service = false;
+        self.poll = false;
+        self.lowest_priority = 7;
+        self.interrupt_request = 0;
+        self.in_service = 0;
+        self.interrupt_mask = 0;
+    }
+
+    fn end_of_interrupt(&mut self, level: Option<u8>, rotate: bool) {
+        if let Some(level) = level {
+            self.in_service &= !(1 << level);
+
+            if rotate {
+                self.lowest_priority = level;
+            }
+        }
+    }
+
+    fn write_ocw2(&mut self, value: u8) {
+        let level = value & 0x07;
+
+        match value >> 5 {
+            0b001 => self.end_of_interrupt(self.get_highest_in_service_level(), false),
+            0b011 => self.end_of_interrupt(Some(level), false),
+            0b101 => self.end_of_interrupt(self.get_highest_in_service_level(), true),
+            0b111 => self.end_of_interrupt(Some(level), true),
+            0b100 => self.rotate_on_auto_eoi = true,
+            0b000 => self.rotate_on_auto_eoi = false,
+            0b110 => self.lowest_priority = level,
+            _ => {}
+        }
+    }
+
+    fn write_ocw3(&mut self, value: u8) {
+        if value & 0x40 != 0 {
+            self.special_mask = value & 0x20 != 0;
+        }
+
+        self.poll = value & 0x04 != 0;
+
+        if value & 0x02 != 0 {
+            self.read_in_service = value & 0x01 != 0;
+        }
+    }
+
+    fn write_data(&mut self, value: u8) {
+        match self.initialization_step {
+            InitializationStep::AwaitingIcw2 => {
+                self.vector_high_byte = value;
+                self.initialization_step = if !self.single {
+                    InitializationStep::AwaitingIcw3
+                } else if self.requires_icw4 {
+                    InitializationStep::AwaitingIcw4
+                } else {
+                    InitializationStep::Ready
+                };
+            }
+            InitializationStep::AwaitingIcw3 => {
+                self.cascade = value;
+                self.initialization_step = if self.requires_icw4 {
+                    InitializationStep::AwaitingIcw4
+                } else {
+                    InitializationStep::Ready
+                };
+            }
+            InitializationStep::AwaitingIcw4 => {
+                // Only 8080 mode is emulated, so the uPM bit is ignored
+                self.auto_eoi = value & 0x02 != 0;
+                self.initialization_step = InitializationStep::Ready;
+            }
+            InitializationStep::Ready => self.interrupt_mask = value,
+        }
+    }
+}
+
+impl Default for Pic8259 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PortDevice for Pic8259 {
+    fn read(&mut self, port: u8, _cycles: usize) -> u8 {
+        if port & 0x01 != 0 {
+            return self.interrupt_mask;
+        }
+
+        if self.poll {
+            self.poll = false;
+
+            return match self.accept_request() {
+                Some(level) => 0x80 | level,
+                None => 0x00,
+            };
+        }
+
+        if self.read_in_service {
+            self.in_service
+        } else {
+            self.interrupt_request
+        }
+    }
+
+    fn write(&mut self, port: u8, value: u8, _cycles: usize) {
+        if port & 0x01 != 0 {
+            self.write_data(value);
+        } else if value & 0x10 != 0 {
+            self.write_icw1(value);
+        } else if value & 0x08 != 0 {
+            self.write_ocw3(value);
+        } else {
+            self.write_ocw2(value);
+        }
+    }
+}
+
+impl InterruptController for Pic8259 {
+    fn is_requesting(&self) -> bool {
+        self.initialization_step == InitializationStep::Ready
+            && self.get_highest_pending_level().is_some()
+    }
+
+    fn acknowledge(&mut self) -> Vec<u8> {
+        let level = self.accept_request().unwrap_or(SPURIOUS_LEVEL);
+        let [high_byte, low_byte] = self.get_vector_address(level).to_be_bytes();
+
+        vec![CALL_OPCODE, low_byte, high_byte]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::system::System;
+
+    fn create_pic(icw1: u8, icw2: u8) -> Pic8259 {
+        let mut pic = Pic8259::new();
+
+        pic.write(0x20, icw1, 0);
+        pic.write(0x21, icw2, 0);
+
+        pic
+    }
+
+    #[test]
+    fn should_generate_call_vectors() {
+        let mut pic = create_pic(0x16, 0x10);
+
+        pic.pulse_irq_line(3);
+        assert_eq!(pic.acknowledge(), vec![0xCD, 0x0C, 0x10]);
+
+        let mut pic = create_pic(0xB2, 0x20);
+
+        pic.pulse_irq_line(3);
+        assert_eq!(pic.acknowledge(), vec![0xCD, 0x98, 0x20]);
+    }
+
+    #[test]
+    fn should_prioritize_and_nest_requests() {
+        let mut pic = create_pic(0x16, 0x10);
+
+        pic.pulse_irq_line(5);
+        pic.pulse_irq_line(2);
+
+        assert_eq!(pic.acknowledge()[1], 2 << 2);
+        assert_eq!(pic.get_in_service_register(), 0x04);
+
+        // IRQ 5 is blocked by the in-service IRQ 2 until EOI
+        assert!(!pic.is_requesting());
+
+        pic.pulse_irq_line(1);
+        assert!(pic.is_requesting());
+        assert_eq!(pic.acknowledge()[1], 1 << 2);
+
+        pic.write(0x20, 0x20, 0);
+        assert_eq!(pic.get_in_service_register(), 0x04);
+        pic.write(0x20, 0x20, 0);
+        assert_eq!(pic.get_in_service_register(), 0x00);
+
+        assert_eq!(pic.acknowledge()[1], 5 << 2);
+    }
+
+    #[test]
+    fn should_not_nest_request_at_in_service_level() {
+        let mut pic = create_pic(0x16, 0x10);
+
+        pic.pulse_irq_line(3);
+        assert_eq!(pic.acknowledge()[1], 3 << 2);
+
+        // A new request on the in-service IR waits for its EOI
+        pic.pulse_irq_line(3);
+        assert!(!pic.is_requesting());
+        assert_eq!(pic.get_interrupt_request_register(), 0x08);
+
+        pic.write(0x20, 0x20, 0);
+        assert!(pic.is_requesting());
+        assert_eq!(pic.acknowledge()[1], 3 << 2);
+        assert_eq!(pic.get_in_service_register(), 0x08);
+    }
+
+    #[test]
+    fn should_enable_lower_levels_in_special_mask_mode() {
+        let mut pic = create_pic(0x16, 0x10);
+
+        pic.pulse_irq_line(3);
+        assert_eq!(pic.acknowledge()[1], 3 << 2);
+
+        // Set special mask mode
+        pic.write(0x20, 0x68, 0);
+        pic.pulse_irq_line(3);
+        assert!(!pic.is_requesting());
+
+        pic.pulse_irq_line(5);
+        assert_eq!(pic.acknowledge()[1], 5 << 2);
+        assert_eq!(pic.get_in_service_register(), 0x28);
+
+        // Reset special mask mode
+        pic.write(0x20, 0x48, 0);
+        pic.write(0x20, 0x63, 0);
+        pic.write(0x20, 0x65, 0);
+        assert_eq!(pic.acknowledge()[1], 3 << 2);
+    }
+
+    #[test]
+    fn should_mask_requests() {
+        let mut pic = create_pic(0x16, 0x10);
+
+        pic.write(0x21, 0x08, 0);
+        pic.pulse_irq_line(3);
+
+        assert!(!pic.is_requesting());
+        assert_eq!(pic.read(0x21, 0), 0x08);
+
+        pic.write(0x21, 0x00, 0);
+        assert!(pic.is_requesting());
+    }
+
+    #[test]
+    fn should_rotate_priorities() {
+        let mut pic = create_pic(0x16, 0x10);
+
+        // Set IRQ 4 as lowest priority
+        pic.write(0x20, 0xC4, 0);
+        pic.pulse_irq_line(2);
+        pic.pulse_irq_line(6);
+
+        assert_eq!(pic.acknowledge()[1], 6 << 2);
+
+        // Rotate on non-specific EOI, making IRQ 6 the lowest priority
+        pic.write(0x20, 0xA0, 0);
+        pic.pulse_irq_line(6);
+
+        assert_eq!(pic.acknowledge()[1], 2 << 2);
+    }
+
+    #[test]
+    fn should_read_registers_and_poll() {
+        let mut pic = create_pic(0x16, 0x10);
+
+        pic.pulse_irq_line(4);
+        assert_eq!(pic.read(0x20, 0), 0x10);
+
+        pic.write(0x20, 0x0C, 0);
+        assert_eq!(pic.read(0x20, 0), 0x84);
+
+        pic.write(0x20, 0x0B, 0);
+        assert_eq!(pic.read(0x20, 0), 0x10);
+    }
+
+    #[test]
+    fn should_interrupt_system() {
+        let pic = Rc::new(RefCell::new(Pic8259::new()));
+
+        let mut system = System::new();
+        system.register_port_range_device(0x20..=0x21, Rc::clone(&pic));
+        system.attach_interrupt_controller(Rc::clone(&pic));
+
+        let mut program = vec![
+            0x3E, 0x16, // MVI A, 0x16
+            0xD3, 0x20, // OUT 0x20
+            0x3E, 0x10, // MVI A, 0x10
+            0xD3, 0x21, // OUT 0x21
+            0xAF, // XRA A
+            0xD3, 0x21, // OUT 0x21
+            0xFB, // EI
+            0x76, // HLT
+        ];
+        program.resize(0x1008, 0x00);
+        program.extend([
+            0x3E, 0x42, // MVI A, 0x42
+            0xD3, 0x01, // OUT 0x01
+            0x76, // HLT
+        ]);
+
+        system.load_program(program);
+        system.run(1000);
+
+        assert!(system.is_halted());
+
+        pic.borrow_mut().pulse_irq_line(2);
+        system.run(1000);
+
+        assert_eq!(system.get_output(0x01), 0x42);
+        assert_eq!(pic.borrow().get_in_service_register(), 0x04);
+    }
+}
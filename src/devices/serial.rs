@@ -0,0 +1,384 @@
+#[cfg(unix)]
+use std::{
+    ffi::CStr,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::PathBuf,
+};
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::mpsc::{Receiver, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use super::console::{spawn_reader, Console};
+
+/// Time between checks while waiting for a character
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Serial line on a pair of host streams, such as files or pipes
+///
+/// The input is read on a background thread, so that waiting on a pipe never
+/// blocks the emulator, and output is written as it comes.
+pub struct StreamConsole {
+    input: Receiver<u8>,
+    pending: Option<u8>,
+    ended: bool,
+    output: Box<dyn Write>,
+}
+
+impl StreamConsole {
+    pub fn new(input: impl Read + Send + 'static, output: impl Write + 'static) -> Self {
+        StreamConsole {
+            input: spawn_reader(input),
+            pending: None,
+            ended: false,
+            output: Box::new(output),
+        }
+    }
+
+    /// Reads from the file or named pipe at `input_path` and writes to a
+    /// file created at `output_path`
+    ///
+    /// Opening a named pipe waits until something opens it for writing.
+    pub fn open(input_path: impl AsRef<Path>, output_path: impl AsRef<Path>) -> io::Result<Self> {
+        let input = File::open(input_path)?;
+        let output = File::create(output_path)?;
+
+        Ok(StreamConsole::new(input, output))
+    }
+}
+
+impl Console for StreamConsole {
+    fn is_ready(&mut self) -> bool {
+        if self.pending.is_none() {
+            match self.input.try_recv() {
+                Ok(byte) => self.pending = Some(byte),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.ended = true,
+            }
+        }
+
+        self.pending.is_some()
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.pending.take().or_else(|| self.input.recv().ok())
+    }
+
+    fn write(&mut self, value: u8) {
+        let _ = self.output.write_all(&[value]);
+        let _ = self.output.flush();
+    }
+
+    fn is_ended(&mut self) -> bool {
+        !self.is_ready() && self.ended
+    }
+}
+
+/// Serial line served to one client at a time on a localhost TCP port
+///
+/// A client is accepted whenever the line is polled with none connected,
+/// and output written while nobody is connected is dropped, as on an
+/// unplugged line. `try_write` refuses output while the client is not
+/// keeping up, for the USART to hold it until there is room.
+pub struct TcpConsole {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    pending: Option<u8>,
+}
+
+impl TcpConsole {
+    /// Listens on `port` of 127.0.0.1, or on any free port if it is 0
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(TcpConsole {
+            listener,
+            stream: None,
+            pending: None,
+        })
+    }
+
+    pub fn get_port(&self) -> u16 {
+        self.listener
+            .local_addr()
+            .map_or(0, |address| address.port())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn accept(&mut self) {
+        if self.stream.is_some() {
+            return;
+        }
+
+        if let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                let _ = stream.set_nodelay(true);
+                self.stream = Some(stream);
+            }
+        }
+    }
+}
+
+impl Console for TcpConsole {
+    fn is_ready(&mut self) -> bool {
+        self.accept();
+
+        if let (None, Some(stream)) = (self.pending, self.stream.as_mut()) {
+            let mut buffer = [0];
+
+            match stream.read(&mut buffer) {
+                Ok(1) => self.pending = Some(buffer[0]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                Ok(_) | Err(_) => self.stream = None,
+            }
+        }
+
+        self.pending.is_some()
+    }
+
+    /// Waits for a character, from the next client if this one disconnects
+    fn read(&mut self) -> Option<u8> {
+        while !self.is_ready() {
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        self.pending.take()
+    }
+
+    fn write(&mut self, value: u8) {
+        self.try_write(value);
+    }
+
+    fn try_write(&mut self, value: u8) -> bool {
+        self.accept();
+
+        if let Some(stream) = self.stream.as_mut() {
+            match stream.write(&[value]) {
+                Ok(1) => {}
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return false,
+                Ok(_) | Err(_) => self.stream = None,
+            }
+        }
+
+        true
+    }
+}
+
+/// Serial line on a pseudo-terminal, for terminal programs such as
+/// `screen` or `minicom` to open by its path
+///
+/// The terminal side is kept open and in raw mode, so that characters pass
+/// through unchanged and the line stays up between programs opening it.
+/// Output is dropped once the terminal's buffer fills with nobody reading.
+#[cfg(unix)]
+pub struct PtyConsole {
+    master: File,
+    _slave: OwnedFd,
+    path: PathBuf,
+    pending: Option<u8>,
+}
+
+#[cfg(unix)]
+impl PtyConsole {
+    pub fn open() -> io::Result<Self> {
+        // SAFETY: the file descriptors are checked before they are owned,
+        // and the name buffer is large enough for any terminal path
+        unsafe {
+            let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK);
+
+            if master < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let master = File::from(OwnedFd::from_raw_fd(master));
+            let mut name = [0 as libc::c_char; 128];
+
+            if libc::grantpt(master.as_raw_fd()) != 0
+                || libc::unlockpt(master.as_raw_fd()) != 0
+                || libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+
+            let path = PathBuf::from(CStr::from_ptr(name.as_ptr()).to_string_lossy().as_ref());
+            let slave = libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY);
+
+            if slave < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let slave = OwnedFd::from_raw_fd(slave);
+            let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+
+            if libc::tcgetattr(slave.as_raw_fd(), termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut termios = termios.assume_init();
+            libc::cfmakeraw(&mut termios);
+
+            if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(PtyConsole {
+                master,
+                _slave: slave,
+                path,
+                pending: None,
+            })
+        }
+    }
+
+    /// Path of the terminal side, such as /dev/pts/3
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(unix)]
+impl Console for PtyConsole {
+    fn is_ready(&mut self) -> bool {
+        if self.pending.is_none() {
+            let mut buffer = [0];
+
+            if let Ok(1) = self.master.read(&mut buffer) {
+                self.pending = Some(buffer[0]);
+            }
+        }
+
+        self.pending.is_some()
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        while !self.is_ready() {
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        self.pending.take()
+    }
+
+    fn write(&mut self, value: u8) {
+        let _ = self.master.write(&[value]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::Cursor, process};
+
+    use super::*;
+
+    fn wait_for_input(console: &mut impl Console) -> Option<u8> {
+        for _ in 0..1000 {
+            if console.is_ready() {
+                return console.read();
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        None
+    }
+
+    #[test]
+    fn should_read_and_write_streams() {
+        let directory = env::temp_dir().join(format!("serial-test-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let input_path = directory.join("input");
+        let output_path = directory.join("output");
+        fs::write(&input_path, b"ok").unwrap();
+
+        let mut console = StreamConsole::open(&input_path, &output_path).unwrap();
+
+        assert_eq!(wait_for_input(&mut console), Some(b'o'));
+        assert_eq!(console.read(), Some(b'k'));
+        assert_eq!(console.read(), None);
+        assert!(console.is_ended());
+
+        console.write(b'!');
+        assert_eq!(fs::read(&output_path).unwrap(), b"!");
+
+        let mut console = StreamConsole::new(Cursor::new(vec![]), io::sink());
+        assert_eq!(console.read(), None);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn should_serve_tcp_client() {
+        let mut console = TcpConsole::bind(0).unwrap();
+        console.write(b'-');
+
+        let mut client = TcpStream::connect(("127.0.0.1", console.get_port())).unwrap();
+        client.write_all(b"hi").unwrap();
+
+        assert_eq!(wait_for_input(&mut console), Some(b'h'));
+        assert!(console.is_connected());
+        assert_eq!(console.read(), Some(b'i'));
+
+        console.write(b'!');
+
+        let mut buffer = [0];
+        client.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"!");
+
+        drop(client);
+        assert_eq!(wait_for_input(&mut console), None);
+        assert!(!console.is_connected());
+    }
+
+    #[test]
+    fn should_refuse_output_while_client_is_not_reading() {
+        let mut console = TcpConsole::bind(0).unwrap();
+        let _client = TcpStream::connect(("127.0.0.1", console.get_port())).unwrap();
+
+        while !console.is_connected() {
+            console.is_ready();
+        }
+
+        // Fill the socket buffers on both ends
+        let stream = console.stream.as_mut().unwrap();
+        let block = vec![0; 1 << 16];
+
+        while stream.write(&block).is_ok() {}
+
+        assert!(!console.try_write(b'x'));
+
+        assert!(console.is_connected());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn should_pass_through_pseudo_terminal() {
+        let Ok(mut console) = PtyConsole::open() else {
+            // Some sandboxes have no pseudo-terminals
+            return;
+        };
+
+        let mut terminal = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(console.get_path())
+            .unwrap();
+        terminal.write_all(b"\r\n").unwrap();
+
+        assert_eq!(wait_for_input(&mut console), Some(b'\r'));
+        assert_eq!(wait_for_input(&mut console), Some(b'\n'));
+
+        console.write(b'\n');
+
+        let mut buffer = [0];
+        terminal.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"\n");
+    }
+}
@@ -0,0 +1,399 @@
+use crate::system::{InterruptController, PortDevice};
+
+use super::console::Console;
+
+const TRANSMIT_READY: u8 = 0x01;
+const RECEIVE_READY: u8 = 0x02;
+const TRANSMITTER_EMPTY: u8 = 0x04;
+const DATA_SET_READY: u8 = 0x80;
+
+const TRANSMIT_ENABLE: u8 = 0x01;
+const RECEIVE_ENABLE: u8 = 0x04;
+const INTERNAL_RESET: u8 = 0x40;
+
+const RST_OPCODE: u8 = 0xC7;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Expecting {
+    Mode,
+    SyncCharacters(u8),
+    Command,
+}
+
+/// Intel 8251 USART with its serial line connected to a host backend
+///
+/// The USART occupies two consecutive ports, the data register when bit 0
+/// of the port number (the C/D pin) is clear and the mode, command and
+/// status registers when it is set. After a reset it expects a mode word,
+/// followed by one or two sync characters in synchronous mode, and then
+/// command words until the command resets it.
+///
+/// Characters are transmitted as soon as they are written with the
+/// transmitter enabled, holding TxRDY low while the line cannot take them
+/// yet, and received once the program has read the previous one, so the
+/// parity, overrun and framing errors never occur. Receiving can request an
+/// RST instruction, with the USART attached as the system's interrupt
+/// controller, or through a `Pic8259` line driven by the host from
+/// `is_receive_ready`.
+pub struct Usart8251 {
+    backend: Box<dyn Console>,
+    expecting: Expecting,
+    mode: u8,
+    sync_characters: Vec<u8>,
+    command: u8,
+    transmit: Option<u8>,
+    receive: Option<u8>,
+    receive_interrupt: Option<u8>,
+    data_set_ready: bool,
+}
+
+impl Usart8251 {
+    pub fn new(backend: impl Console + 'static) -> Self {
+        Usart8251 {
+            backend: Box::new(backend),
+            expecting: Expecting::Mode,
+            mode: 0,
+            sync_characters: vec![],
+            command: 0,
+            transmit: None,
+            receive: None,
+            receive_interrupt: None,
+            data_set_ready: true,
+        }
+    }
+
+    /// Sets the restart, from 0 to 7, requested while a received character
+    /// is waiting, or `None` to leave the receiver polled
+    pub fn set_receive_interrupt(&mut self, restart: Option<u8>) {
+        self.receive_interrupt = restart.map(|restart| restart & 0x07);
+    }
+
+    /// Sets the DSR input, reported in bit 7 of the status
+    pub fn set_data_set_ready(&mut self, data_set_ready: bool) {
+        self.data_set_ready = data_set_ready;
+    }
+
+    pub fn get_mode(&self) -> u8 {
+        self.mode
+    }
+
+    pub fn get_command(&self) -> u8 {
+        self.command
+    }
+
+    pub fn get_sync_characters(&self) -> &[u8] {
+        &self.sync_characters
+    }
+
+    /// Whether the RxRDY pin is asserted
+    pub fn is_receive_ready(&self) -> bool {
+        self.receive.is_some()
+    }
+
+    /// Whether nothing is left to receive, with the backend's input ended
+    pub fn is_input_ended(&mut self) -> bool {
+        self.receive.is_none() && self.backend.is_ended()
+    }
+
+    /// Sends a held character if the backend takes it now, and takes a
+    /// waiting character from the backend if the receiver is enabled and
+    /// empty
+    ///
+    /// Port accesses poll by themselves, the host polls between
+    /// instructions for the receive interrupt to wake a halted program.
+    pub fn poll(&mut self) {
+        self.flush_transmitter();
+
+        if self.command & RECEIVE_ENABLE == 0 || self.receive.is_some() {
+            return;
+        }
+
+        if self.backend.is_ready() {
+            self.receive = self
+                .backend
+                .read()
+                .map(|value| value & self.get_data_mask());
+        }
+    }
+
+    /// Mask of the 5 to 8 data bits in a character
+    fn get_data_mask(&self) -> u8 {
+        let bits = 5 + ((self.mode >> 2) & 0x03);
+        (0xFFu16 >> (8 - bits)) as u8
+    }
+
+    fn is_synchronous(&self) -> bool {
+        self.mode & 0x03 == 0
+    }
+
+    fn get_status(&mut self) -> u8 {
+        self.poll();
+
+        let mut status = 0;
+
+        if self.transmit.is_none() {
+            status |= TRANSMIT_READY | TRANSMITTER_EMPTY;
+        }
+
+        if self.receive.is_some() {
+            status |= RECEIVE_READY;
+        }
+
+        if self.data_set_ready {
+            status |= DATA_SET_READY;
+        }
+
+        status
+    }
+
+    fn flush_transmitter(&mut self) {
+        if self.command & TRANSMIT_ENABLE == 0 {
+            return;
+        }
+
+        if let Some(value) = self.transmit {
+            if self.backend.try_write(value) {
+                self.transmit = None;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.expecting = Expecting::Mode;
+        self.command = 0;
+        self.transmit = None;
+        self.receive = None;
+    }
+
+    fn write_control(&mut self, value: u8) {
+        match self.expecting {
+            Expecting::Mode => {
+                self.mode = value;
+                self.sync_characters.clear();
+
+                // Bit 7 selects a single sync character in synchronous mode
+                self.expecting = if !self.is_synchronous() {
+                    Expecting::Command
+                } else if value & 0x80 != 0 {
+                    Expecting::SyncCharacters(1)
+                } else {
+                    Expecting::SyncCharacters(2)
+                };
+            }
+            Expecting::SyncCharacters(remaining) => {
+                self.sync_characters.push(value);
+
+                self.expecting = if remaining > 1 {
+                    Expecting::SyncCharacters(remaining - 1)
+                } else {
+                    Expecting::Command
+                };
+            }
+            Expecting::Command if value & INTERNAL_RESET != 0 => self.reset(),
+            Expecting::Command => {
+                self.command = value;
+                self.flush_transmitter();
+            }
+        }
+    }
+}
+
+impl PortDevice for Usart8251 {
+    fn read(&mut self, port: u8, _cycles: usize) -> u8 {
+        if port & 0x01 != 0 {
+            return self.get_status();
+        }
+
+        self.poll();
+        self.receive.take().unwrap_or(0)
+    }
+
+    fn write(&mut self, port: u8, value: u8, _cycles: usize) {
+        if port & 0x01 != 0 {
+            self.write_control(value);
+            return;
+        }
+
+        self.transmit = Some(value & self.get_data_mask());
+        self.flush_transmitter();
+    }
+}
+
+impl InterruptController for Usart8251 {
+    fn is_requesting(&self) -> bool {
+        self.receive_interrupt.is_some() && self.receive.is_some()
+    }
+
+    fn acknowledge(&mut self) -> Vec<u8> {
+        let restart = self.receive_interrupt.unwrap_or(0);
+        vec![RST_OPCODE | (restart << 3)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{devices::console::BufferConsole, system::System};
+
+    #[test]
+    fn should_program_mode_and_command() {
+        let console = Rc::new(RefCell::new(BufferConsole::new()));
+        let mut usart = Usart8251::new(console.clone());
+        console.borrow_mut().push_input(b"\xC1");
+
+        // Commands are taken as the mode until the USART is programmed
+        usart.write(0x01, 0x4E, 0);
+        assert_eq!(usart.get_mode(), 0x4E);
+        assert_eq!(usart.read(0x01, 0), 0x85);
+
+        usart.write(0x00, b'x', 0);
+        assert_eq!(usart.read(0x01, 0), 0x80);
+
+        usart.write(0x01, 0x37, 0);
+        assert_eq!(usart.get_command(), 0x37);
+        assert_eq!(usart.read(0x01, 0), 0x87);
+        assert_eq!(usart.read(0x00, 0), 0xC1);
+        assert_eq!(usart.read(0x01, 0), 0x85);
+
+        usart.write(0x00, b'y', 0);
+        assert_eq!(console.borrow_mut().take_output(), b"xy");
+
+        // Seven data bits
+        usart.write(0x01, 0x40, 0);
+        usart.write(0x01, 0x4A, 0);
+        usart.write(0x01, 0x05, 0);
+        console.borrow_mut().push_input(b"\xC1");
+        usart.write(0x00, 0xFF, 0);
+
+        assert_eq!(usart.read(0x00, 0), 0x41);
+        assert_eq!(console.borrow_mut().take_output(), b"\x7F");
+    }
+
+    /// Line taking characters only while `accepting`
+    #[derive(Default)]
+    struct Line {
+        accepting: bool,
+        output: Vec<u8>,
+    }
+
+    impl Console for Line {
+        fn is_ready(&mut self) -> bool {
+            false
+        }
+
+        fn read(&mut self) -> Option<u8> {
+            None
+        }
+
+        fn write(&mut self, value: u8) {
+            self.output.push(value);
+        }
+
+        fn try_write(&mut self, value: u8) -> bool {
+            if self.accepting {
+                self.output.push(value);
+            }
+
+            self.accepting
+        }
+    }
+
+    #[test]
+    fn should_hold_characters_until_the_line_takes_them() {
+        let line = Rc::new(RefCell::new(Line::default()));
+        let mut usart = Usart8251::new(line.clone());
+
+        usart.write(0x01, 0x4E, 0);
+        usart.write(0x01, 0x01, 0);
+        usart.write(0x00, b'x', 0);
+
+        assert_eq!(usart.read(0x01, 0), 0x80);
+        assert!(line.borrow().output.is_empty());
+
+        line.borrow_mut().accepting = true;
+
+        assert_eq!(usart.read(0x01, 0), 0x85);
+        assert_eq!(line.borrow().output, b"x");
+    }
+
+    #[test]
+    fn should_take_sync_characters() {
+        let mut usart = Usart8251::new(BufferConsole::new());
+
+        // The reset sequence of three zeros then an internal reset
+        for value in [0x00, 0x00, 0x00, 0x40] {
+            usart.write(0x01, value, 0);
+        }
+
+        assert_eq!(usart.get_command(), 0x00);
+
+        usart.write(0x01, 0x0C, 0);
+        usart.write(0x01, 0x16, 0);
+        usart.write(0x01, 0x17, 0);
+        usart.write(0x01, 0x15, 0);
+
+        assert_eq!(usart.get_sync_characters(), [0x16, 0x17]);
+        assert_eq!(usart.get_command(), 0x15);
+
+        usart.write(0x01, 0x40, 0);
+        usart.write(0x01, 0x8C, 0);
+        usart.write(0x01, 0x16, 0);
+        usart.write(0x01, 0x15, 0);
+
+        assert_eq!(usart.get_sync_characters(), [0x16]);
+        assert_eq!(usart.get_command(), 0x15);
+    }
+
+    #[test]
+    fn should_interrupt_on_receive() {
+        let console = Rc::new(RefCell::new(BufferConsole::new()));
+        let mut usart = Usart8251::new(console.clone());
+        usart.set_receive_interrupt(Some(7));
+
+        let usart = Rc::new(RefCell::new(usart));
+        let mut system = System::new();
+        system.register_port_range_device(0x10..=0x11, Rc::clone(&usart));
+        system.attach_interrupt_controller(Rc::clone(&usart));
+
+        let mut program = vec![
+            0x31, 0x00, 0x10, // LXI SP, 0x1000
+            0x3E, 0x4E, // MVI A, 0x4E
+            0xD3, 0x11, // OUT 0x11
+            0x3E, 0x05, // MVI A, 0x05
+            0xD3, 0x11, // OUT 0x11
+            0xFB, // EI
+            0x76, // HLT
+            0xC3, 0x0C, 0x00, // JMP 0x000C
+        ];
+        program.resize(0x38, 0x00);
+        program.extend([
+            0xDB, 0x10, // IN 0x10
+            0xC6, 0x01, // ADI 0x01
+            0xD3, 0x10, // OUT 0x10
+            0xFB, // EI
+            0xC9, // RET
+        ]);
+
+        system.load_program(program);
+        system.run(1000);
+
+        assert!(system.is_halted());
+
+        console.borrow_mut().push_input(b"HAL");
+        usart.borrow_mut().poll();
+        system.run(1000);
+
+        assert!(system.is_halted());
+        assert!(!usart.borrow().is_receive_ready());
+        assert_eq!(console.borrow_mut().take_output(), b"I");
+
+        usart.borrow_mut().poll();
+        system.run(1000);
+        usart.borrow_mut().poll();
+        system.run(1000);
+
+        assert_eq!(console.borrow_mut().take_output(), b"BM");
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use super::decode_instruction;
+use crate::instructions::{Instruction, RegisterPair};
+
+/// Addresses of the reset vector and the `RST 1` to `RST 7` vectors
+pub const RESTART_VECTORS: [u16; 8] = [0x00, 0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38];
+
+/// Maximum number of bytes in a single `DB` line
+const BYTES_PER_LINE: usize = 8;
+
+/// Branch target of an instruction, and whether execution may continue with
+/// the instruction following it
+fn control_flow(instruction: &Instruction) -> (Option<u16>, bool) {
+    match instruction {
+        Instruction::Jump(address) => (Some(*address), false),
+        Instruction::ConditionalJump(_, address)
+        | Instruction::Call(address)
+        | Instruction::ConditionalCall(_, address) => (Some(*address), true),
+        Instruction::Restart(n) => (Some(*n as u16 * 8), true),
+        Instruction::Return | Instruction::JumpHLIndirect => (None, false),
+        _ => (None, true),
+    }
+}
+
+/// Address referenced by an instruction that is worth labelling when it
+/// falls inside the image
+fn referenced_address(instruction: &Instruction) -> Option<u16> {
+    match instruction {
+        Instruction::LoadRegisterPairImmediate(RegisterPair::SP, _) => None,
+        _ => instruction.get_address_operand(),
+    }
+}
+
+fn label(address: u16) -> String {
+    format!("L{:04X}", address)
+}
+
+/// Disassembly of an image that follows control flow from a set of entry
+/// points, separating reachable code from data
+///
+/// Its `Display` output is assembler source that reproduces the image.
+#[derive(Debug)]
+pub struct FlowDisassembly {
+    base_address: u16,
+    bytes: Vec<u8>,
+    /// Decoded instructions and their lengths, keyed by offset into `bytes`
+    instructions: BTreeMap<usize, (Instruction, usize)>,
+    /// Offsets of data words that point at code
+    words: BTreeSet<usize>,
+    labels: BTreeSet<u16>,
+}
+
+impl FlowDisassembly {
+    /// Traces all code reachable from `entry_points` in `bytes`, the first of
+    /// which is located at `base_address`
+    ///
+    /// Entry points outside of the image are ignored.
+    pub fn trace(bytes: &[u8], base_address: u16, entry_points: &[u16]) -> Self {
+        let mut disassembly = FlowDisassembly {
+            base_address,
+            bytes: bytes.to_vec(),
+            instructions: BTreeMap::new(),
+            words: BTreeSet::new(),
+            labels: BTreeSet::new(),
+        };
+        let mut covered = vec![false; bytes.len()];
+        let mut pending: Vec<u16> = entry_points.iter().rev().copied().collect();
+        let mut targets: BTreeSet<u16> = entry_points.iter().copied().collect();
+
+        while let Some(address) = pending.pop() {
+            let Some(mut offset) = disassembly.offset_of(address) else {
+                continue;
+            };
+
+            while offset < bytes.len() && !covered[offset] {
+                let Some((instruction, length)) = decode_instruction(&bytes[offset..]) else {
+                    break;
+                };
+
+                if covered[offset..offset + length].iter().any(|&byte| byte) {
+                    break;
+                }
+
+                covered[offset..offset + length].fill(true);
+
+                let (target, falls_through) = control_flow(&instruction);
+
+                if let Some(target) = target {
+                    if targets.insert(target) {
+                        pending.push(target);
+                    }
+                }
+
+                disassembly
+                    .instructions
+                    .insert(offset, (instruction, length));
+
+                if !falls_through {
+                    break;
+                }
+
+                offset += length;
+            }
+        }
+
+        for (instruction, _) in disassembly.instructions.values() {
+            targets.extend(referenced_address(instruction));
+        }
+
+        let mut offset = 0;
+
+        while offset + 1 < bytes.len() {
+            if covered[offset] || covered[offset + 1] {
+                offset += 1;
+                continue;
+            }
+
+            let word = u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+
+            // Null words are far more likely to be padding than pointers
+            match disassembly.offset_of(word) {
+                Some(target) if word != 0 && disassembly.instructions.contains_key(&target) => {
+                    disassembly.words.insert(offset);
+                    targets.insert(word);
+                    offset += 2;
+                }
+                _ => offset += 1,
+            }
+        }
+
+        disassembly.labels = targets
+            .into_iter()
+            .filter(|&address| disassembly.is_line_start(address))
+            .collect();
+
+        disassembly
+    }
+
+    fn offset_of(&self, address: u16) -> Option<usize> {
+        let offset = address.wrapping_sub(self.base_address) as usize;
+
+        (offset < self.bytes.len()).then_some(offset)
+    }
+
+    fn address_of(&self, offset: usize) -> u16 {
+        self.base_address.wrapping_add(offset as u16)
+    }
+
+    /// Whether `address` starts an instruction or falls in data, so that a
+    /// label can be placed in front of it
+    fn is_line_start(&self, address: u16) -> bool {
+        let Some(offset) = self.offset_of(address) else {
+            return false;
+        };
+
+        if self.instructions.contains_key(&offset) {
+            return true;
+        }
+
+        let inside_instruction = self
+            .instructions
+            .range(..offset)
+            .next_back()
+            .is_some_and(|(start, (_, length))| start + length > offset);
+        let inside_word = offset > 0 && self.words.contains(&(offset - 1));
+
+        !inside_instruction && !inside_word
+    }
+
+    /// Whether an instruction was decoded starting at `address`
+    pub fn is_code(&self, address: u16) -> bool {
+        self.offset_of(address)
+            .is_some_and(|offset| self.instructions.contains_key(&offset))
+    }
+
+    /// Addresses that are given a label in the output
+    pub fn get_labels(&self) -> &BTreeSet<u16> {
+        &self.labels
+    }
+
+    fn operand(&self, address: u16) -> String {
+        if self.labels.contains(&address) {
+            label(address)
+        } else {
+            format!("{:#06x}", address)
+        }
+    }
+
+    fn format_instruction(&self, instruction: &Instruction) -> String {
+        let text = instruction.to_string();
+
+        match referenced_address(instruction) {
+            Some(address) if self.labels.contains(&address) => {
+                text.replacen(&format!("{:#06x}", address), &label(address), 1)
+            }
+            _ => text,
+        }
+    }
+}
+
+impl Display for FlowDisassembly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "        ORG     {:#06x}", self.base_address)?;
+
+        let mut offset = 0;
+
+        while offset < self.bytes.len() {
+            let address = self.address_of(offset);
+
+            if self.labels.contains(&address) {
+                writeln!(f, "{}:", label(address))?;
+            }
+
+            if let Some((instruction, length)) = self.instructions.get(&offset) {
+                let bytes = &self.bytes[offset..offset + length];
+
+                if instruction.encode() == bytes {
+                    writeln!(f, "        {}", self.format_instruction(instruction))?;
+                } else {
+                    // Undocumented opcodes would assemble to their documented
+                    // equivalents
+                    writeln!(
+                        f,
+                        "        DB      {}  ; {}",
+                        bytes
+                            .iter()
+                            .map(|byte| format!("{:#04x}", byte))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        instruction
+                    )?;
+                }
+
+                offset += length;
+            } else if self.words.contains(&offset) {
+                let word = u16::from_le_bytes([self.bytes[offset], self.bytes[offset + 1]]);
+
+                writeln!(f, "        DW      {}", self.operand(word))?;
+                offset += 2;
+            } else {
+                let mut data = vec![format!("{:#04x}", self.bytes[offset])];
+                offset += 1;
+
+                while offset < self.bytes.len()
+                    && data.len() < BYTES_PER_LINE
+                    && !self.instructions.contains_key(&offset)
+                    && !self.words.contains(&offset)
+                    && !self.labels.contains(&self.address_of(offset))
+                {
+                    data.push(format!("{:#04x}", self.bytes[offset]));
+                    offset += 1;
+                }
+
+                writeln!(f, "        DB      {}", data.join(", "))?;
+            }
+        }
+
+        writeln!(f, "        END")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn should_separate_code_from_data() {
+        let bytes = [
+            0xC3, 0x08, 0x00, // JMP L0008
+            0x48, 0x49, // DB 'HI'
+            0x0E, 0x00, 0x00, // DW L000E, DB 0x00
+            0x21, 0x03, 0x00, // LXI HL, L0003
+            0xCD, 0x0F, 0x00, // CALL L000F
+            0x76, // HLT
+            0xC9, // RET
+            0xFF, // DB 0xff
+        ];
+
+        let disassembly = FlowDisassembly::trace(&bytes, 0x0000, &[0x0000]);
+
+        assert!(disassembly.is_code(0x0008));
+        assert!(!disassembly.is_code(0x0003));
+        assert_eq!(
+            disassembly.to_string(),
+            "        ORG     0x0000\n\
+             L0000:\n\
+             \x20       JMP     L0008\n\
+             L0003:\n\
+             \x20       DB      0x48, 0x49\n\
+             \x20       DW      L000E\n\
+             \x20       DB      0x00\n\
+             L0008:\n\
+             \x20       LXI     HL, L0003\n\
+             \x20       CALL    L000F\n\
+             L000E:\n\
+             \x20       HLT\n\
+             L000F:\n\
+             \x20       RET\n\
+             \x20       DB      0xff\n\
+             \x20       END\n"
+        );
+    }
+
+    #[test]
+    fn should_emit_undocumented_opcodes_as_bytes() {
+        let bytes = [
+            0xCD, 0x06, 0x00, // CALL L0006
+            0xCB, 0x00, 0x00, // JMP 0x0000 (undocumented)
+            0xD9, // RET (undocumented)
+        ];
+
+        let disassembly = FlowDisassembly::trace(&bytes, 0x0000, &[0x0000]);
+        let source = disassembly.to_string();
+
+        assert!(source.contains("        DB      0xcb, 0x00, 0x00  ; JMP     0x0000\n"));
+        assert!(source.contains("        DB      0xd9  ; RET\n"));
+        assert_eq!(assemble(&source).unwrap().bytes, bytes);
+    }
+
+    #[test]
+    fn should_follow_restarts_and_ignore_targets_outside_the_image() {
+        let bytes = [
+            0xCF, // RST 1
+            0xC3, 0x00, 0x80, // JMP 0x8000
+            0x00, 0x00, 0x00, 0x00, // DB
+            0xC9, // RET
+        ];
+
+        let disassembly = FlowDisassembly::trace(&bytes, 0x0000, &[0x0000, 0x1234]);
+        let source = disassembly.to_string();
+
+        assert!(disassembly.is_code(0x0008));
+        assert!(source.contains("JMP     0x8000"));
+        assert!(source.contains("L0008:\n        RET\n"));
+        assert_eq!(
+            disassembly.get_labels().iter().copied().collect::<Vec<_>>(),
+            vec![0x0000, 0x0008]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt::Display;
+
+pub use flow::{FlowDisassembly, RESTART_VECTORS};
+
+pub use crate::internal::program_counter::decode_instruction;
+
+use crate::{
+    instructions::Instruction,
+    symbols::{SymbolTable, Symbolic},
+};
+
+mod flow;
+
+/// Single line of a disassembly listing
+#[derive(Debug)]
+pub struct Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Decoded instruction, or `None` for bytes rendered as data
+    pub instruction: Option<Instruction>,
+}
+
+impl Line {
+    /// Decodes the instruction at the start of `bytes`, falling back to a
+    /// data line holding the remaining bytes if the instruction is truncated
+    pub fn decode(address: u16, bytes: &[u8]) -> Line {
+        match decode_instruction(bytes) {
+            Some((instruction, length)) => Line {
+                address,
+                bytes: bytes[..length].to_vec(),
+                instruction: Some(instruction),
+            },
+            None => Line {
+                address,
+                bytes: bytes.to_vec(),
+                instruction: None,
+            },
+        }
+    }
+
+    /// Address and raw bytes columns, padded to where the mnemonic starts
+    pub fn format_prefix(&self) -> String {
+        let hex: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        format!("{:04x}  {:<8}  ", self.address, hex.join(" "))
+    }
+
+    /// Displays the line with a label and operands taken from `symbols`
+    pub fn display_with<'a>(&'a self, symbols: &'a SymbolTable) -> Symbolic<'a, Line> {
+        Symbolic::new(self, symbols)
+    }
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.format_prefix())?;
+
+        match &self.instruction {
+            Some(instruction) => write!(f, "{}", instruction),
+            None => {
+                let data: Vec<String> = self
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:#04x}", byte))
+                    .collect();
+
+                write!(f, "DB      {}", data.join(", "))
+            }
+        }
+    }
+}
+
+/// Decodes `bytes` as consecutive instructions, the first located at
+/// `base_address`
+pub fn disassemble(bytes: &[u8], base_address: u16) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = base_address.wrapping_add(offset as u16);
+        let line = Line::decode(address, &bytes[offset..]);
+
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Formats a full listing of `bytes`, one instruction per line
+pub fn format_listing(bytes: &[u8], base_address: u16) -> String {
+    disassemble(bytes, base_address)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::{Condition, Register};
+
+    #[test]
+    fn should_decode_instruction_lengths() {
+        assert_eq!(
+            decode_instruction(&[0x3E, 0x12, 0x00]),
+            Some((Instruction::MoveImmediate(Register::A, 0x12), 2))
+        );
+        assert_eq!(
+            decode_instruction(&[0xC8]),
+            Some((Instruction::ConditionalReturn(Condition::Zero), 1))
+        );
+        assert_eq!(decode_instruction(&[0xCD, 0x00]), None);
+        assert_eq!(decode_instruction(&[]), None);
+    }
+
+    #[test]
+    fn should_format_listing() {
+        let bytes = [0x31, 0x00, 0x10, 0xC8, 0x76, 0xC3, 0x00];
+
+        assert_eq!(
+            format_listing(&bytes, 0x0100),
+            "0100  31 00 10  LXI     SP, 0x1000\n\
+             0103  c8        RZ\n\
+             0104  76        HLT\n\
+             0105  c3 00     DB      0xc3, 0x00\n"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+const INTERRUPT: u8 = 0x03;
+
+/// Message received from the debugger
+pub enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Framing layer of the remote serial protocol over a TCP stream
+pub struct Connection {
+    stream: TcpStream,
+    pending_byte: Option<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Connection {
+            stream,
+            pending_byte: None,
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending_byte.take() {
+            return Ok(Some(byte));
+        }
+
+        let mut buffer = [0u8];
+
+        match self.stream.read(&mut buffer)? {
+            0 => Ok(None),
+            _ => Ok(Some(buffer[0])),
+        }
+    }
+
+    fn read_hex_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut digits = String::new();
+
+        for _ in 0..2 {
+            match self.read_byte()? {
+                Some(byte) => digits.push(char::from(byte)),
+                None => return Ok(None),
+            }
+        }
+
+        Ok(u8::from_str_radix(&digits, 16).ok())
+    }
+
+    /// Reads the next packet or interrupt request, acknowledging packets and
+    /// skipping acknowledgements, returning `None` once the client
+    /// disconnects
+    pub fn read(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(Incoming::Interrupt)),
+                Some(b'$') => {}
+                Some(_) => continue,
+            }
+
+            let mut data = vec![];
+
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+
+            if self.read_hex_byte()? == Some(checksum(&data)) {
+                self.stream.write_all(b"+")?;
+
+                return Ok(Some(Incoming::Packet(
+                    String::from_utf8_lossy(&data).into_owned(),
+                )));
+            }
+
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    pub fn write(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data.as_bytes()));
+
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+
+    /// Checks without blocking whether the client requested an interrupt
+    pub fn poll_interrupt(&mut self) -> io::Result<bool> {
+        if self.pending_byte.is_some() {
+            return Ok(false);
+        }
+
+        let mut buffer = [0u8];
+
+        self.stream.set_nonblocking(true)?;
+        let result = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Ok(false),
+            Ok(_) if buffer[0] == INTERRUPT => Ok(true),
+            Ok(_) => {
+                self.pending_byte = Some(buffer[0]);
+                Ok(false)
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
@@ -0,0 +1,638 @@
+use std::{collections::HashMap, io, net::TcpListener};
+
+use crate::{
+    internal::instructions::RegisterPair,
+    system::{
+        breakpoint::{BreakpointId, StopReason, WatchKind},
+        Machine, State, System,
+    },
+};
+
+use connection::{Connection, Incoming};
+
+mod connection;
+
+/// Clock cycles executed between checks for an interrupt from the debugger
+const CONTINUE_SLICE_CYCLES: usize = 100_000;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// Register numbers in `g`/`G`/`p`/`P` packets
+const REGISTER_NAMES: [&str; 10] = ["a", "b", "c", "d", "e", "h", "l", "sp", "pc", "psw"];
+
+const TARGET_DESCRIPTION: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.emulator8080.cpu">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="b" bitsize="8" type="uint8"/>
+    <reg name="c" bitsize="8" type="uint8"/>
+    <reg name="d" bitsize="8" type="uint8"/>
+    <reg name="e" bitsize="8" type="uint8"/>
+    <reg name="h" bitsize="8" type="uint8"/>
+    <reg name="l" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="psw" bitsize="16" type="uint16"/>
+  </feature>
+</target>
+"#;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// Register value in target byte order
+fn get_register_bytes(state: &State, register: usize) -> Option<Vec<u8>> {
+    let registers = &state.registers;
+
+    let bytes = match register {
+        0 => vec![registers.a],
+        1 => vec![registers.b],
+        2 => vec![registers.c],
+        3 => vec![registers.d],
+        4 => vec![registers.e],
+        5 => vec![registers.h],
+        6 => vec![registers.l],
+        7 => registers.stack_pointer.to_le_bytes().to_vec(),
+        8 => state.program_counter.get().to_le_bytes().to_vec(),
+        9 => state.get_psw().to_le_bytes().to_vec(),
+        _ => return None,
+    };
+
+    Some(bytes)
+}
+
+fn set_register_bytes(state: &mut State, register: usize, bytes: &[u8]) -> Option<()> {
+    let byte = *bytes.first()?;
+    let word = || Some(u16::from_le_bytes([byte, *bytes.get(1)?]));
+
+    match register {
+        0 => state.registers.a = byte,
+        1 => state.registers.b = byte,
+        2 => state.registers.c = byte,
+        3 => state.registers.d = byte,
+        4 => state.registers.e = byte,
+        5 => state.registers.h = byte,
+        6 => state.registers.l = byte,
+        7 => state.set_register_pair(&RegisterPair::SP, word()?),
+        8 => state.program_counter.set(word()?),
+        9 => state.set_psw(word()?),
+        _ => return None,
+    }
+
+    Some(())
+}
+
+fn get_register_size(register: usize) -> usize {
+    if register < 7 {
+        1
+    } else {
+        2
+    }
+}
+
+/// Stub exposing a system to debuggers speaking the GDB remote serial
+/// protocol
+///
+/// Registers are numbered A, B, C, D, E, H, L, SP, PC, PSW, as described by
+/// the target description served through `qXfer:features:read`.
+pub struct GdbStub<'a> {
+    system: &'a mut System,
+    breakpoints: HashMap<(u8, u16, usize), BreakpointId>,
+    watch_kinds: HashMap<BreakpointId, WatchKind>,
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(system: &'a mut System) -> Self {
+        GdbStub {
+            system,
+            breakpoints: HashMap::new(),
+            watch_kinds: HashMap::new(),
+        }
+    }
+
+    /// Accepts a single debugger connection and serves it until the debugger
+    /// detaches, kills the target or disconnects
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        let mut connection = Connection::new(stream);
+
+        while let Some(incoming) = connection.read()? {
+            let packet = match incoming {
+                Incoming::Packet(packet) => packet,
+                Incoming::Interrupt => continue,
+            };
+
+            match packet.as_str() {
+                "D" => {
+                    connection.write("OK")?;
+                    return Ok(());
+                }
+                "k" => return Ok(()),
+                _ => {
+                    let response = self.handle_packet(&packet, &mut connection)?;
+                    connection.write(&response)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, packet: &str, connection: &mut Connection) -> io::Result<String> {
+        // Packets from the lossy UTF-8 conversion may start with a wider
+        // replacement character, which no command matches
+        let command_length = packet.chars().next().map_or(0, char::len_utf8);
+
+        let response = match packet.split_at(command_length) {
+            ("?", _) => format!("S{:02x}", SIGTRAP),
+            ("g", _) => self.read_registers(),
+            ("G", data) => self.write_registers(data),
+            ("p", data) => self.read_register(data),
+            ("P", data) => self.write_register(data),
+            ("m", data) => self.read_memory(data),
+            ("M", data) => self.write_memory(data),
+            ("s", address) => {
+                self.resume_at(address);
+                self.step()
+            }
+            ("c", address) => {
+                self.resume_at(address);
+                self.continue_execution(connection)?
+            }
+            ("b", "s") => self.reverse_step(),
+            ("b", "c") => self.reverse_continue(),
+            ("Z", data) => self.insert_breakpoint(data),
+            ("z", data) => self.remove_breakpoint(data),
+            ("q", _) => self.handle_query(packet),
+            _ => String::new(),
+        };
+
+        Ok(response)
+    }
+
+    fn handle_query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return String::from(
+                "PacketSize=4000;qXfer:features:read+;ReverseStep+;ReverseContinue+",
+            );
+        }
+
+        if packet == "qAttached" {
+            return String::from("1");
+        }
+
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',') else {
+                return String::from("E01");
+            };
+            let (Some(offset), Some(length)) = (parse_hex(offset), parse_hex(length)) else {
+                return String::from("E01");
+            };
+
+            let start = offset.min(TARGET_DESCRIPTION.len());
+            let end = offset.saturating_add(length).min(TARGET_DESCRIPTION.len());
+            let marker = if end == TARGET_DESCRIPTION.len() {
+                'l'
+            } else {
+                'm'
+            };
+
+            return format!("{}{}", marker, &TARGET_DESCRIPTION[start..end]);
+        }
+
+        String::new()
+    }
+
+    fn read_registers(&self) -> String {
+        let state = self.system.get_state();
+
+        (0..REGISTER_NAMES.len())
+            .filter_map(|register| get_register_bytes(state, register))
+            .map(|bytes| to_hex(&bytes))
+            .collect()
+    }
+
+    fn write_registers(&mut self, data: &str) -> String {
+        let Some(bytes) = from_hex(data) else {
+            return String::from("E01");
+        };
+
+        let state = self.system.get_state_mut();
+        let mut offset = 0;
+
+        for register in 0..REGISTER_NAMES.len() {
+            let size = get_register_size(register);
+
+            let Some(register_bytes) = bytes.get(offset..offset + size) else {
+                return String::from("E01");
+            };
+
+            // A is written last, as PSW includes the accumulator
+            if register != 0 {
+                set_register_bytes(state, register, register_bytes);
+            }
+
+            offset += size;
+        }
+
+        state.registers.a = bytes[0];
+
+        String::from("OK")
+    }
+
+    fn read_register(&self, data: &str) -> String {
+        parse_hex(data)
+            .and_then(|register| get_register_bytes(self.system.get_state(), register))
+            .map_or(String::from("E01"), |bytes| to_hex(&bytes))
+    }
+
+    fn write_register(&mut self, data: &str) -> String {
+        let result = data.split_once('=').and_then(|(register, value)| {
+            let register = parse_hex(register)?;
+            let bytes = from_hex(value)?;
+
+            set_register_bytes(self.system.get_state_mut(), register, &bytes)
+        });
+
+        match result {
+            Some(()) => String::from("OK"),
+            None => String::from("E01"),
+        }
+    }
+
+    fn parse_memory_range(data: &str) -> Option<(u16, usize)> {
+        let (address, length) = data.split_once(',')?;
+
+        Some((u16::try_from(parse_hex(address)?).ok()?, parse_hex(length)?))
+    }
+
+    fn read_memory(&self, data: &str) -> String {
+        let Some((address, length)) = Self::parse_memory_range(data) else {
+            return String::from("E01");
+        };
+
+        if length == 0 {
+            return String::new();
+        }
+
+        let length = length.min(0x10000 - address as usize);
+        let end = address + (length - 1) as u16;
+
+        to_hex(&self.system.read_memory_region(address, end))
+    }
+
+    fn write_memory(&mut self, data: &str) -> String {
+        let result = data.split_once(':').and_then(|(range, value)| {
+            let (address, length) = Self::parse_memory_range(range)?;
+            let bytes = from_hex(value)?;
+
+            (bytes.len() == length).then_some((address, bytes))
+        });
+
+        match result {
+            Some((address, bytes)) => {
+                self.system.get_state_mut().memory.load(address, bytes);
+                String::from("OK")
+            }
+            None => String::from("E01"),
+        }
+    }
+
+    fn resume_at(&mut self, address: &str) {
+        if let Some(address) = parse_hex(address).and_then(|address| u16::try_from(address).ok()) {
+            self.system.get_state_mut().program_counter.set(address);
+        }
+    }
+
+    fn step(&mut self) -> String {
+        if self.system.step() == 0 && !self.system.is_powered_on() {
+            return String::from("W00");
+        }
+
+        format!("S{:02x}", SIGTRAP)
+    }
+
+    fn continue_execution(&mut self, connection: &mut Connection) -> io::Result<String> {
+        loop {
+            let reason = self.system.run(CONTINUE_SLICE_CYCLES);
+
+            if reason == StopReason::CycleLimit {
+                if !connection.poll_interrupt()? {
+                    continue;
+                }
+
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+
+            return Ok(self.stop_reply(reason));
+        }
+    }
+
+    fn stop_reply(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::CycleLimit => format!("S{:02x}", SIGINT),
+            StopReason::PoweredOff => String::from("W00"),
+            StopReason::Halted | StopReason::Breakpoint(_) => format!("S{:02x}", SIGTRAP),
+            StopReason::MemoryWatchpoint(id, access) => {
+                let kind = match self.watch_kinds.get(&id) {
+                    Some(WatchKind::Write) => "watch",
+                    Some(WatchKind::Read) => "rwatch",
+                    _ => "awatch",
+                };
+
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, access.address)
+            }
+            StopReason::PortWatchpoint(_, _) => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    fn reverse_step(&mut self) -> String {
+        if self.system.get_rewind_position().is_none() {
+            return String::from("E01");
+        }
+
+        if !self.system.reverse_step() {
+            return format!("T{:02x}replaylog:begin;", SIGTRAP);
+        }
+
+        format!("S{:02x}", SIGTRAP)
+    }
+
+    fn reverse_continue(&mut self) -> String {
+        if self.system.get_rewind_position().is_none() {
+            return String::from("E01");
+        }
+
+        match self.system.reverse_continue() {
+            Some(reason) => self.stop_reply(reason),
+            None => format!("T{:02x}replaylog:begin;", SIGTRAP),
+        }
+    }
+
+    fn parse_breakpoint(data: &str) -> Option<(u8, u16, usize)> {
+        let mut fields = data.split(',');
+
+        let kind = fields.next()?.parse().ok()?;
+        let address = u16::try_from(parse_hex(fields.next()?)?).ok()?;
+        let length = parse_hex(fields.next()?)?;
+
+        Some((kind, address, length))
+    }
+
+    fn insert_breakpoint(&mut self, data: &str) -> String {
+        let Some(key @ (kind, address, length)) = Self::parse_breakpoint(data) else {
+            return String::from("E01");
+        };
+
+        if self.breakpoints.contains_key(&key) {
+            return String::from("OK");
+        }
+
+        let length = length.clamp(1, 0x10000 - address as usize);
+        let end = address + (length - 1) as u16;
+        let watch_kind = match kind {
+            0 | 1 => None,
+            2 => Some(WatchKind::Write),
+            3 => Some(WatchKind::Read),
+            4 => Some(WatchKind::ReadWrite),
+            _ => return String::new(),
+        };
+
+        let id = match watch_kind {
+            Some(watch_kind) => {
+                let id = self.system.add_memory_watchpoint(address..=end, watch_kind);
+                self.watch_kinds.insert(id, watch_kind);
+                id
+            }
+            None => self.system.add_breakpoint(address),
+        };
+
+        self.breakpoints.insert(key, id);
+
+        String::from("OK")
+    }
+
+    fn remove_breakpoint(&mut self, data: &str) -> String {
+        let Some(key) = Self::parse_breakpoint(data) else {
+            return String::from("E01");
+        };
+
+        if let Some(id) = self.breakpoints.remove(&key) {
+            self.system.remove_breakpoint(id);
+            self.watch_kinds.remove(&id);
+        }
+
+        String::from("OK")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        thread,
+    };
+
+    use super::*;
+
+    fn checksum(data: &str) -> u8 {
+        data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+    }
+
+    /// Sends each packet and collects the response, skipping acknowledgements
+    fn run_client(port: u16, packets: Vec<&'static str>) -> Vec<String> {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut responses = vec![];
+
+        for packet in packets {
+            write!(stream, "${}#{:02x}", packet, checksum(packet)).unwrap();
+
+            if packet == "k" {
+                break;
+            }
+
+            let mut response = vec![];
+            let mut byte = [0u8];
+
+            loop {
+                stream.read_exact(&mut byte).unwrap();
+
+                match byte[0] {
+                    b'+' if response.is_empty() => continue,
+                    b'#' => break,
+                    value => response.push(value),
+                }
+            }
+
+            let mut received_checksum = [0u8; 2];
+            stream.read_exact(&mut received_checksum).unwrap();
+
+            let response = String::from_utf8(response[1..].to_vec()).unwrap();
+            assert_eq!(
+                std::str::from_utf8(&received_checksum).unwrap(),
+                format!("{:02x}", checksum(&response))
+            );
+
+            responses.push(response);
+        }
+
+        responses
+    }
+
+    fn run_session(system: &mut System, packets: Vec<&'static str>) -> Vec<String> {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = thread::spawn(move || run_client(port, packets));
+
+        GdbStub::new(system).serve(&listener).unwrap();
+
+        client.join().unwrap()
+    }
+
+    #[test]
+    fn should_serve_registers_memory_and_execution() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x3E, 0x12, // MVI A, 0x12
+            0x21, 0x00, 0x20, // LXI H, 0x2000
+            0x77, // MOV M, A
+            0x76, // HLT
+        ]);
+
+        let responses = run_session(
+            &mut system,
+            vec![
+                "qSupported:multiprocess+",
+                "qXfer:features:read:target.xml:0,20",
+                "?",
+                "s",
+                "g",
+                "Z0,5,1",
+                "c",
+                "p8",
+                "z0,5,1",
+                "Z2,2000,1",
+                "c",
+                "m2000,2",
+                "M2001,1:ab",
+                "m2000,2",
+                "P0=55",
+                "p0",
+                "c",
+                "mfffe,10001",
+                "D",
+            ],
+        );
+
+        assert_eq!(
+            responses,
+            vec![
+                "PacketSize=4000;qXfer:features:read+;ReverseStep+;ReverseContinue+",
+                "m<?xml version=\"1.0\"?>\n<!DOCTYPE ",
+                "S05",
+                "S05",
+                "12000000000000000002000212",
+                "OK",
+                "S05",
+                "0500",
+                "OK",
+                "OK",
+                "T05watch:2000;",
+                "1200",
+                "OK",
+                "12ab",
+                "OK",
+                "55",
+                "S05",
+                "0000",
+                "OK",
+            ]
+        );
+        assert!(system.is_halted());
+    }
+
+    #[test]
+    fn should_step_and_continue_backwards() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x3E, 0x12, // MVI A, 0x12
+            0x21, 0x00, 0x20, // LXI H, 0x2000
+            0x77, // MOV M, A
+            0x76, // HLT
+        ]);
+
+        assert_eq!(run_session(&mut system, vec!["bs", "D"]), vec!["E01", "OK"]);
+
+        system.enable_rewind(2, 8);
+
+        let responses = run_session(
+            &mut system,
+            vec!["Z2,2000,1", "c", "bs", "p8", "bc", "p8", "bs", "D"],
+        );
+
+        assert_eq!(
+            responses,
+            vec![
+                "OK",
+                "T05watch:2000;",
+                "S05",
+                "0500",
+                "T05replaylog:begin;",
+                "0000",
+                "T05replaylog:begin;",
+                "OK",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_ignore_packets_starting_with_invalid_utf8() {
+        let mut system = System::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.write_all(b"$\xff#ff").unwrap();
+
+            let mut response = [0u8; 5];
+            stream.read_exact(&mut response).unwrap();
+
+            stream.write_all(b"$D#44").unwrap();
+
+            let mut detach = [0u8; 7];
+            stream.read_exact(&mut detach).unwrap();
+
+            (response, detach)
+        });
+
+        GdbStub::new(&mut system).serve(&listener).unwrap();
+
+        let (response, detach) = client.join().unwrap();
+        assert_eq!(&response, b"+$#00");
+        assert_eq!(&detach, b"+$OK#9a");
+    }
+}
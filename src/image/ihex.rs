@@ -0,0 +1,263 @@
+use super::{decode_hex, ImageError, LoadedImage};
+
+/// Number of data bytes per record written by `write`
+const BYTES_PER_RECORD: usize = 16;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+        .wrapping_neg()
+}
+
+fn parse_record(line: &str) -> Result<(u8, u16, Vec<u8>), String> {
+    let record = line
+        .strip_prefix(':')
+        .ok_or("record does not start with ':'")?;
+    let bytes = decode_hex(record)?;
+
+    if bytes.len() < 5 {
+        return Err(String::from("record is too short"));
+    }
+
+    let length = bytes[0] as usize;
+
+    if bytes.len() != length + 5 {
+        return Err(format!(
+            "record declares {} data bytes but holds {}",
+            length,
+            bytes.len() - 5
+        ));
+    }
+
+    let (contents, found) = bytes.split_at(bytes.len() - 1);
+    let expected = checksum(contents);
+
+    if found[0] != expected {
+        return Err(format!(
+            "checksum mismatch: expected {:#04x}, found {:#04x}",
+            expected, found[0]
+        ));
+    }
+
+    let address = u16::from_be_bytes([bytes[1], bytes[2]]);
+
+    Ok((bytes[3], address, bytes[4..4 + length].to_vec()))
+}
+
+/// Parses Intel HEX text into an image, taking the entry address from a
+/// start address record
+///
+/// Records must be followed by an end of file record, and all addresses
+/// must fall within the 64KiB address space of the 8080.
+pub fn parse(text: &str) -> Result<LoadedImage, ImageError> {
+    let mut image = LoadedImage::new();
+    let mut base: u32 = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| ImageError::new(line_number, message);
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (kind, address, data) = parse_record(line).map_err(error)?;
+
+        match (kind, data.len()) {
+            (DATA, _) => {
+                let start = base + address as u32;
+
+                if start + data.len() as u32 > 0x10000 {
+                    return Err(error(format!("data at {:#x} extends beyond 64KiB", start)));
+                }
+
+                image.add(start as u16, &data);
+            }
+            (END_OF_FILE, 0) => return Ok(image),
+            (EXTENDED_SEGMENT_ADDRESS, 2) => {
+                base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4;
+            }
+            (EXTENDED_LINEAR_ADDRESS, 2) => {
+                base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16;
+            }
+            (START_SEGMENT_ADDRESS, 4) | (START_LINEAR_ADDRESS, 4) => {
+                let high = u16::from_be_bytes([data[0], data[1]]) as u32;
+                let low = u16::from_be_bytes([data[2], data[3]]) as u32;
+                let entry = if kind == START_SEGMENT_ADDRESS {
+                    (high << 4) + low
+                } else {
+                    high << 16 | low
+                };
+
+                image.entry =
+                    Some(u16::try_from(entry).map_err(|_| {
+                        error(format!("start address {:#x} is beyond 64KiB", entry))
+                    })?);
+            }
+            (END_OF_FILE..=START_LINEAR_ADDRESS, length) => {
+                return Err(error(format!(
+                    "record type {:#04x} cannot hold {} data bytes",
+                    kind, length
+                )));
+            }
+            _ => return Err(error(format!("unknown record type {:#04x}", kind))),
+        }
+    }
+
+    Err(ImageError::new(
+        text.lines().count(),
+        String::from("missing end of file record"),
+    ))
+}
+
+fn write_record(output: &mut String, kind: u8, address: u16, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    bytes.push(checksum(&bytes));
+
+    output.push(':');
+
+    for byte in bytes {
+        output.push_str(&format!("{:02X}", byte));
+    }
+
+    output.push('\n');
+}
+
+/// Formats an image as Intel HEX, with a start linear address record for
+/// its entry address
+pub fn write(image: &LoadedImage) -> String {
+    let mut output = String::new();
+
+    for segment in &image.segments {
+        for (index, data) in segment.bytes.chunks(BYTES_PER_RECORD).enumerate() {
+            let address = segment
+                .address
+                .wrapping_add((index * BYTES_PER_RECORD) as u16);
+
+            write_record(&mut output, DATA, address, data);
+        }
+    }
+
+    if let Some(entry) = image.entry {
+        write_record(
+            &mut output,
+            START_LINEAR_ADDRESS,
+            0,
+            &(entry as u32).to_be_bytes(),
+        );
+    }
+
+    write_record(&mut output, END_OF_FILE, 0, &[]);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{image::Segment, system::System};
+
+    #[test]
+    fn should_load_records_and_start_address() {
+        let image = parse(
+            ":0300000031FF2F9E\n\
+             \n\
+             :02000300C30038\n\
+             :02000600007682\n\
+             :0400000500000100F6\n\
+             :00000001FF\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            image.segments,
+            vec![
+                Segment {
+                    address: 0x0000,
+                    bytes: vec![0x31, 0xFF, 0x2F, 0xC3, 0x00],
+                },
+                Segment {
+                    address: 0x0006,
+                    bytes: vec![0x00, 0x76],
+                },
+            ]
+        );
+        assert_eq!(image.entry, Some(0x0100));
+
+        let mut system = System::new();
+        image.load_into(&mut system);
+
+        assert_eq!(
+            system.read_memory_region(0x0000, 0x0007),
+            vec![0x31, 0xFF, 0x2F, 0xC3, 0x00, 0x00, 0x00, 0x76]
+        );
+        assert_eq!(system.get_program_counter(), 0x0100);
+    }
+
+    #[test]
+    fn should_report_invalid_records() {
+        let error = |text: &str| parse(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error(":0300000031FF2FA3\n"),
+            "line 1: checksum mismatch: expected 0x9e, found 0xa3"
+        );
+        assert_eq!(
+            error("0300000031FF2F9E\n"),
+            "line 1: record does not start with ':'"
+        );
+        assert_eq!(
+            error(":0400000031FF2F9D\n"),
+            "line 1: record declares 4 data bytes but holds 3"
+        );
+        assert_eq!(
+            error(":0300000031FG2F9E\n"),
+            "line 1: invalid hex digits 'FG'"
+        );
+        assert_eq!(
+            error(":02FFFF000102FD\n:00000001FF\n"),
+            "line 1: data at 0xffff extends beyond 64KiB"
+        );
+        assert_eq!(
+            error(":020000040001F9\n:0100000000FF\n:00000001FF\n"),
+            "line 2: data at 0x10000 extends beyond 64KiB"
+        );
+        assert_eq!(
+            error(":0300000031FF2F9E\n"),
+            "line 1: missing end of file record"
+        );
+    }
+
+    #[test]
+    fn should_write_memory_back_out() {
+        let mut system = System::new();
+        system.load_program((0..20).collect());
+
+        let mut image = LoadedImage::from_memory(&system, &[0x0000..=0x0013, 0x0100..=0x0101]);
+        image.entry = Some(0x0000);
+
+        let text = write(&image);
+
+        assert_eq!(
+            text,
+            ":10000000000102030405060708090A0B0C0D0E0F78\n\
+             :0400100010111213A6\n\
+             :020100000000FD\n\
+             :0400000500000000F7\n\
+             :00000001FF\n"
+        );
+        assert_eq!(parse(&text).unwrap(), image);
+    }
+}
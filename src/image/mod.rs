@@ -0,0 +1,225 @@
+use std::{fmt::Display, fs, ops::RangeInclusive, path::Path};
+
+use crate::system::Machine;
+
+pub mod ihex;
+pub mod srec;
+
+/// File formats that images can be read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    IntelHex,
+    SRecord,
+    /// Flat binary without any addresses, placed at a given origin
+    Binary,
+}
+
+impl ImageFormat {
+    /// Guesses the format from the file extension, treating unknown
+    /// extensions as flat binaries
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("hex" | "ihx") => ImageFormat::IntelHex,
+            Some("s19" | "s28" | "s37" | "srec" | "mot") => ImageFormat::SRecord,
+            _ => ImageFormat::Binary,
+        }
+    }
+
+    /// Parses a format name as given on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "hex" | "ihex" => Some(ImageFormat::IntelHex),
+            "srec" | "s19" => Some(ImageFormat::SRecord),
+            "bin" | "binary" => Some(ImageFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// Reads an image file, placing flat binaries at `origin`
+pub fn read_file(
+    path: impl AsRef<Path>,
+    format: ImageFormat,
+    origin: u16,
+) -> Result<LoadedImage, String> {
+    let path = path.as_ref();
+    let read_error = |error| format!("cannot read '{}': {}", path.display(), error);
+    let parse_error = |error: ImageError| format!("{}: {}", path.display(), error);
+
+    match format {
+        ImageFormat::IntelHex => {
+            ihex::parse(&fs::read_to_string(path).map_err(read_error)?).map_err(parse_error)
+        }
+        ImageFormat::SRecord => {
+            srec::parse(&fs::read_to_string(path).map_err(read_error)?).map_err(parse_error)
+        }
+        ImageFormat::Binary => {
+            let bytes = fs::read(path).map_err(read_error)?;
+
+            LoadedImage::from_binary(origin, &bytes)
+                .map_err(|message| format!("{}: {}", path.display(), message))
+        }
+    }
+}
+
+/// Error in an image file, with the 1-based line it was found on
+#[derive(Debug, PartialEq, Eq)]
+pub struct ImageError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ImageError {
+    fn new(line: usize, message: String) -> Self {
+        ImageError { line, message }
+    }
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Decodes pairs of hexadecimal digits into bytes
+fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.is_ascii() {
+        return Err(String::from("record contains non-ASCII characters"));
+    }
+
+    if text.len() % 2 != 0 {
+        return Err(String::from("odd number of hex digits"));
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| {
+            let digits = &text[index..index + 2];
+
+            u8::from_str_radix(digits, 16).map_err(|_| format!("invalid hex digits '{}'", digits))
+        })
+        .collect()
+}
+
+/// Contiguous block of bytes to place in memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+/// Program image made of segments at arbitrary addresses, with an optional
+/// address to start executing at
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadedImage {
+    pub segments: Vec<Segment>,
+    pub entry: Option<u16>,
+}
+
+impl LoadedImage {
+    pub fn new() -> Self {
+        LoadedImage::default()
+    }
+
+    /// Describes a flat binary placed at `origin`, without an entry address
+    pub fn from_binary(origin: u16, bytes: &[u8]) -> Result<Self, String> {
+        if origin as usize + bytes.len() > 0x10000 {
+            return Err(format!(
+                "{} bytes do not fit in memory at {:#06x}",
+                bytes.len(),
+                origin
+            ));
+        }
+
+        let mut image = LoadedImage::new();
+        image.add(origin, bytes);
+
+        Ok(image)
+    }
+
+    /// Adds `bytes` at `address`, extending the last segment when they
+    /// directly follow it
+    pub fn add(&mut self, address: u16, bytes: &[u8]) {
+        match self.segments.last_mut() {
+            Some(segment) if segment.address as usize + segment.bytes.len() == address as usize => {
+                segment.bytes.extend_from_slice(bytes);
+            }
+            _ => self.segments.push(Segment {
+                address,
+                bytes: bytes.to_vec(),
+            }),
+        }
+    }
+
+    /// Captures the contents of the given memory ranges of `machine`
+    pub fn from_memory(machine: &impl Machine, ranges: &[RangeInclusive<u16>]) -> Self {
+        let mut image = LoadedImage::new();
+
+        for range in ranges {
+            image.add(
+                *range.start(),
+                &machine.read_memory_region(*range.start(), *range.end()),
+            );
+        }
+
+        image
+    }
+
+    /// Writes every segment into the memory of `machine`, bypassing ROM
+    /// protection, and moves the program counter to the entry address
+    pub fn load_into(&self, machine: &mut impl Machine) {
+        let state = machine.get_state_mut();
+
+        for segment in self
+            .segments
+            .iter()
+            .filter(|segment| !segment.bytes.is_empty())
+        {
+            state.memory.load(segment.address, segment.bytes.clone());
+        }
+
+        if let Some(entry) = self.entry {
+            state.program_counter.set(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+
+    #[test]
+    fn should_place_binaries_at_origin() {
+        let mut image = LoadedImage::from_binary(0xE000, &[0x3E, 0x01, 0x76]).unwrap();
+        image.entry = Some(0xE000);
+
+        let mut system = System::new();
+        system.load_image(&image);
+
+        assert_eq!(
+            system.read_memory_region(0xE000, 0xE002),
+            vec![0x3E, 0x01, 0x76]
+        );
+        assert_eq!(system.get_program_counter(), 0xE000);
+
+        assert_eq!(
+            LoadedImage::from_binary(0xFFFF, &[0x00, 0x00]).unwrap_err(),
+            "2 bytes do not fit in memory at 0xffff"
+        );
+    }
+
+    #[test]
+    fn should_guess_formats() {
+        assert_eq!(ImageFormat::from_path("rom.HEX"), ImageFormat::IntelHex);
+        assert_eq!(ImageFormat::from_path("rom.s19"), ImageFormat::SRecord);
+        assert_eq!(ImageFormat::from_path("rom.com"), ImageFormat::Binary);
+        assert_eq!(ImageFormat::from_name("srec"), Some(ImageFormat::SRecord));
+        assert_eq!(ImageFormat::from_name("elf"), None);
+    }
+}
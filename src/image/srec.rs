@@ -0,0 +1,213 @@
+use super::{decode_hex, ImageError, LoadedImage};
+
+/// Number of data bytes per record written by `write`
+const BYTES_PER_RECORD: usize = 16;
+
+fn checksum(bytes: &[u8]) -> u8 {
+    !bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Splits a record into its type digit, address and data
+fn parse_record(line: &str) -> Result<(u8, u32, Vec<u8>), String> {
+    let record = line
+        .strip_prefix('S')
+        .ok_or("record does not start with 'S'")?;
+    let kind = record
+        .chars()
+        .next()
+        .and_then(|c| c.to_digit(10))
+        .ok_or("missing record type")? as u8;
+    let bytes = decode_hex(&record[1..])?;
+
+    let address_length = match kind {
+        0 | 1 | 5 | 9 => 2,
+        2 | 6 | 8 => 3,
+        3 | 7 => 4,
+        _ => return Err(format!("unknown record type S{}", kind)),
+    };
+
+    let Some((&count, rest)) = bytes.split_first() else {
+        return Err(String::from("record is too short"));
+    };
+
+    if rest.len() != count as usize {
+        return Err(format!(
+            "record declares {} bytes but holds {}",
+            count,
+            rest.len()
+        ));
+    }
+
+    if rest.len() < address_length + 1 {
+        return Err(String::from("record is too short"));
+    }
+
+    let (contents, found) = bytes.split_at(bytes.len() - 1);
+    let expected = checksum(contents);
+
+    if found[0] != expected {
+        return Err(format!(
+            "checksum mismatch: expected {:#04x}, found {:#04x}",
+            expected, found[0]
+        ));
+    }
+
+    let address = rest[..address_length]
+        .iter()
+        .fold(0u32, |address, byte| address << 8 | *byte as u32);
+
+    Ok((kind, address, rest[address_length..rest.len() - 1].to_vec()))
+}
+
+/// Parses Motorola S-records (S19, S28 or S37) into an image, taking the
+/// entry address from the termination record
+///
+/// Header records are skipped, and record counts are checked against the
+/// number of data records read so far.
+pub fn parse(text: &str) -> Result<LoadedImage, ImageError> {
+    let mut image = LoadedImage::new();
+    let mut data_records: u32 = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| ImageError::new(index + 1, message);
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let (kind, address, data) = parse_record(line).map_err(error)?;
+
+        match kind {
+            0 => {}
+            1..=3 => {
+                if address as usize + data.len() > 0x10000 {
+                    return Err(error(format!(
+                        "data at {:#x} extends beyond 64KiB",
+                        address
+                    )));
+                }
+
+                image.add(address as u16, &data);
+                data_records += 1;
+            }
+            5 | 6 => {
+                if address != data_records {
+                    return Err(error(format!(
+                        "record count is {} but {} data records were read",
+                        address, data_records
+                    )));
+                }
+            }
+            _ => {
+                image.entry =
+                    Some(u16::try_from(address).map_err(|_| {
+                        error(format!("start address {:#x} is beyond 64KiB", address))
+                    })?);
+
+                return Ok(image);
+            }
+        }
+    }
+
+    Err(ImageError::new(
+        text.lines().count(),
+        String::from("missing termination record"),
+    ))
+}
+
+fn write_record(output: &mut String, kind: u8, address: u16, data: &[u8]) {
+    let mut bytes = vec![(data.len() + 3) as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.extend(data);
+    bytes.push(checksum(&bytes));
+
+    output.push_str(&format!("S{}", kind));
+
+    for byte in bytes {
+        output.push_str(&format!("{:02X}", byte));
+    }
+
+    output.push('\n');
+}
+
+/// Formats an image as S19 records, ending with an S9 record holding the
+/// entry address, or 0 if it has none
+pub fn write(image: &LoadedImage) -> String {
+    let mut output = String::new();
+
+    for segment in &image.segments {
+        for (index, data) in segment.bytes.chunks(BYTES_PER_RECORD).enumerate() {
+            let address = segment
+                .address
+                .wrapping_add((index * BYTES_PER_RECORD) as u16);
+
+            write_record(&mut output, 1, address, data);
+        }
+    }
+
+    write_record(&mut output, 9, image.entry.unwrap_or(0), &[]);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Segment;
+
+    #[test]
+    fn should_load_records_and_entry() {
+        let image = parse(
+            "S00600004844521B\n\
+             S1060100C3000134\n\
+             S2070002003E427600\n\
+             S5030002FA\n\
+             S9030100FB\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            image.segments,
+            vec![
+                Segment {
+                    address: 0x0100,
+                    bytes: vec![0xC3, 0x00, 0x01],
+                },
+                Segment {
+                    address: 0x0200,
+                    bytes: vec![0x3E, 0x42, 0x76],
+                },
+            ]
+        );
+        assert_eq!(image.entry, Some(0x0100));
+        assert_eq!(parse(&write(&image)).unwrap(), image);
+    }
+
+    #[test]
+    fn should_report_invalid_records() {
+        let error = |text: &str| parse(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("S1060100C3000135\n"),
+            "line 1: checksum mismatch: expected 0x34, found 0x35"
+        );
+        assert_eq!(error("S4030000FC\n"), "line 1: unknown record type S4");
+        assert_eq!(
+            error("S1070100C3000134\n"),
+            "line 1: record declares 7 bytes but holds 6"
+        );
+        assert_eq!(
+            error("S1060100C3000134\nS5030002FA\n"),
+            "line 2: record count is 2 but 1 data records were read"
+        );
+        assert_eq!(
+            error("S30800010000C3000132\n"),
+            "line 1: data at 0x10000 extends beyond 64KiB"
+        );
+        assert_eq!(
+            error("S1060100C3000134\n"),
+            "line 1: missing termination record"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use super::instructions::Condition;
 
 #[derive(Debug, Default)]
@@ -61,3 +63,17 @@ impl ConditionFlags {
         }
     }
 }
+
+impl Display for ConditionFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "S={} Z={} AC={} P={} C={}",
+            self.sign as u8,
+            self.zero as u8,
+            self.aux_carry as u8,
+            self.parity as u8,
+            self.carry as u8
+        )
+    }
+}
@@ -94,7 +94,7 @@ pub fn execute_decrement_reg_pair(state: &mut State, register_pair: &RegisterPai
 
 pub fn execute_add_reg_pair_to_hl(state: &mut State, register_pair: &RegisterPair) {
     let hl_value = state.get_register_pair(&RegisterPair::HL);
-    let value = state.get_register_pair(&register_pair);
+    let value = state.get_register_pair(register_pair);
 
     let (result, carry) = hl_value.overflowing_add(value);
 
@@ -45,7 +45,7 @@ pub fn execute_move_hl_to_sp(state: &mut State) {
 }
 
 pub fn execute_input(state: &mut State, port: u8) {
-    let input_value = state.inputs.get(port);
+    let input_value = state.ports.read(port, state.cycles);
 
     state.set_register(&Register::A, input_value);
 }
@@ -53,9 +53,16 @@ pub fn execute_input(state: &mut State, port: u8) {
 pub fn execute_output(state: &mut State, port: u8) {
     let output_value = state.get_register(&Register::A);
 
-    state.outputs.set(port, output_value);
+    state.ports.write(port, output_value, state.cycles);
 }
 
 pub fn execute_set_interrupt(state: &mut State, value: bool) {
-    state.interrupt_enabled = value
+    state.interrupt_enabled = value;
+
+    // Interrupts are only accepted after the instruction following EI
+    state.interrupt_delayed = value;
+}
+
+pub fn execute_halt(state: &mut State) {
+    state.halted = true;
 }
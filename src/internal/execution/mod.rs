@@ -15,8 +15,9 @@ use logical::{
     execute_rotate_right_through_carry, execute_set_carry, execute_xor,
 };
 use machine_control::{
-    execute_exchange_stack_top_with_hl, execute_input, execute_move_hl_to_sp, execute_output,
-    execute_pop_psw, execute_pop_reg_pair, execute_push_stack, execute_set_interrupt,
+    execute_exchange_stack_top_with_hl, execute_halt, execute_input, execute_move_hl_to_sp,
+    execute_output, execute_pop_psw, execute_pop_reg_pair, execute_push_stack,
+    execute_set_interrupt,
 };
 
 use super::{
@@ -135,7 +136,7 @@ pub fn execute_instruction(state: &mut State, instruction: &Instruction) {
         Instruction::Output(port) => execute_output(state, *port),
         Instruction::EnableInterrupts => execute_set_interrupt(state, true),
         Instruction::DisableInterrupts => execute_set_interrupt(state, false),
-        Instruction::Halt => state.enabled = false,
+        Instruction::Halt => execute_halt(state),
         Instruction::NoOp => {}
     }
 }
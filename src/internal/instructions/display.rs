@@ -1,6 +1,7 @@
 use std::fmt::Display;
 
 use super::{Condition, Instruction, Register, RegisterPair};
+use crate::symbols::{SymbolTable, Symbolic};
 
 impl Display for Register {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -92,7 +93,7 @@ impl Display for Instruction {
             Instruction::Call(addr) => write!(f, "CALL    {:#06x}", addr),
             Instruction::ConditionalCall(c, addr) => write!(f, "C{}     {:#06x}", c, addr),
             Instruction::Return => write!(f, "RET"),
-            Instruction::ConditionalReturn(c) => write!(f, "J{}", c),
+            Instruction::ConditionalReturn(c) => write!(f, "R{}", c.to_string().trim_end()),
             Instruction::Restart(n) => write!(f, "RST     {:#04x}", n),
             Instruction::JumpHLIndirect => write!(f, "PCHL"),
             Instruction::PushRegPair(rp) => write!(f, "PUSH    {}", rp),
@@ -110,3 +111,11 @@ impl Display for Instruction {
         }
     }
 }
+
+impl Instruction {
+    /// Displays the instruction with its address operand replaced by the
+    /// symbol defined there, if any
+    pub fn display_with<'a>(&'a self, symbols: &'a SymbolTable) -> Symbolic<'a, Instruction> {
+        Symbolic::new(self, symbols)
+    }
+}
@@ -0,0 +1,150 @@
+use super::{Condition, Instruction, Register, RegisterPair};
+
+impl Register {
+    /// Register field as encoded in bits 0-2 or 3-5 of an opcode
+    fn code(&self) -> u8 {
+        match self {
+            Register::B => 0,
+            Register::C => 1,
+            Register::D => 2,
+            Register::E => 3,
+            Register::H => 4,
+            Register::L => 5,
+            Register::Memory => 6,
+            Register::A => 7,
+        }
+    }
+}
+
+impl RegisterPair {
+    /// Register pair field as encoded in bits 4-5 of an opcode
+    fn code(&self) -> u8 {
+        match self {
+            RegisterPair::BC => 0,
+            RegisterPair::DE => 1,
+            RegisterPair::HL => 2,
+            RegisterPair::SP => 3,
+        }
+    }
+}
+
+impl Condition {
+    /// Condition field as encoded in bits 3-5 of an opcode
+    fn code(&self) -> u8 {
+        match self {
+            Condition::NotZero => 0,
+            Condition::Zero => 1,
+            Condition::NoCarry => 2,
+            Condition::Carry => 3,
+            Condition::OddParity => 4,
+            Condition::EvenParity => 5,
+            Condition::Plus => 6,
+            Condition::Minus => 7,
+        }
+    }
+}
+
+impl Instruction {
+    /// Encodes the instruction into its opcode followed by any operand bytes,
+    /// using the documented opcode where several decode to the same
+    /// instruction
+    pub fn encode(&self) -> Vec<u8> {
+        let register = |base: u8, r: &Register| base | r.code();
+        let register_high = |base: u8, r: &Register| base | r.code() << 3;
+        let pair = |base: u8, rp: &RegisterPair| base | rp.code() << 4;
+        let condition = |base: u8, c: &Condition| base | c.code() << 3;
+        let with_byte = |opcode: u8, data: u8| vec![opcode, data];
+        let with_word = |opcode: u8, data: u16| {
+            let [low, high] = data.to_le_bytes();
+            vec![opcode, low, high]
+        };
+
+        match self {
+            Instruction::Move(source, destination) => {
+                vec![0x40 | destination.code() << 3 | source.code()]
+            }
+            Instruction::MoveImmediate(r, data) => with_byte(register_high(0x06, r), *data),
+            Instruction::LoadRegisterPairImmediate(rp, data) => with_word(pair(0x01, rp), *data),
+            Instruction::LoadAccumDirect(address) => with_word(0x3A, *address),
+            Instruction::StoreAccumDirect(address) => with_word(0x32, *address),
+            Instruction::LoadHLDirect(address) => with_word(0x2A, *address),
+            Instruction::StoreHLDirect(address) => with_word(0x22, *address),
+            Instruction::LoadAccumIndirect(rp) => vec![pair(0x0A, rp)],
+            Instruction::StoreAccumIndirect(rp) => vec![pair(0x02, rp)],
+            Instruction::ExchangeHLWithDE => vec![0xEB],
+            Instruction::Add(r) => vec![register(0x80, r)],
+            Instruction::AddImmediate(data) => with_byte(0xC6, *data),
+            Instruction::AddWithCarry(r) => vec![register(0x88, r)],
+            Instruction::AddImmediateWithCarry(data) => with_byte(0xCE, *data),
+            Instruction::Subtract(r) => vec![register(0x90, r)],
+            Instruction::SubtractImmediate(data) => with_byte(0xD6, *data),
+            Instruction::SubtractWithBorrow(r) => vec![register(0x98, r)],
+            Instruction::SubtractImmediateWithBorrow(data) => with_byte(0xDE, *data),
+            Instruction::Increment(r) => vec![register_high(0x04, r)],
+            Instruction::Decrement(r) => vec![register_high(0x05, r)],
+            Instruction::IncrementRegPair(rp) => vec![pair(0x03, rp)],
+            Instruction::DecrementRegPair(rp) => vec![pair(0x0B, rp)],
+            Instruction::AddRegPairToHL(rp) => vec![pair(0x09, rp)],
+            Instruction::DecimalAdjustAccum => vec![0x27],
+            Instruction::And(r) => vec![register(0xA0, r)],
+            Instruction::AndImmediate(data) => with_byte(0xE6, *data),
+            Instruction::Xor(r) => vec![register(0xA8, r)],
+            Instruction::XorImmediate(data) => with_byte(0xEE, *data),
+            Instruction::Or(r) => vec![register(0xB0, r)],
+            Instruction::OrImmediate(data) => with_byte(0xF6, *data),
+            Instruction::Compare(r) => vec![register(0xB8, r)],
+            Instruction::CompareImmediate(data) => with_byte(0xFE, *data),
+            Instruction::RotateLeft => vec![0x07],
+            Instruction::RotateRight => vec![0x0F],
+            Instruction::RotateLeftThroughCarry => vec![0x17],
+            Instruction::RotateRightThroughCarry => vec![0x1F],
+            Instruction::ComplementAccum => vec![0x2F],
+            Instruction::ComplementCarry => vec![0x3F],
+            Instruction::SetCarry => vec![0x37],
+            Instruction::Jump(address) => with_word(0xC3, *address),
+            Instruction::ConditionalJump(c, address) => with_word(condition(0xC2, c), *address),
+            Instruction::Call(address) => with_word(0xCD, *address),
+            Instruction::ConditionalCall(c, address) => with_word(condition(0xC4, c), *address),
+            Instruction::Return => vec![0xC9],
+            Instruction::ConditionalReturn(c) => vec![condition(0xC0, c)],
+            Instruction::Restart(n) => vec![0xC7 | (n & 0x07) << 3],
+            Instruction::JumpHLIndirect => vec![0xE9],
+            Instruction::PushRegPair(rp) => vec![pair(0xC5, rp)],
+            Instruction::PushPSW => vec![0xF5],
+            Instruction::PopRegPair(rp) => vec![pair(0xC1, rp)],
+            Instruction::PopPSW => vec![0xF1],
+            Instruction::ExchangeStackTopWithHL => vec![0xE3],
+            Instruction::MoveHLToSP => vec![0xF9],
+            Instruction::Input(port) => with_byte(0xDB, *port),
+            Instruction::Output(port) => with_byte(0xD3, *port),
+            Instruction::EnableInterrupts => vec![0xFB],
+            Instruction::DisableInterrupts => vec![0xF3],
+            Instruction::Halt => vec![0x76],
+            Instruction::NoOp => vec![0x00],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::internal::program_counter::decode_instruction;
+
+    /// Opcodes that decode to the same instruction as a documented opcode
+    const ALIASES: [u8; 12] = [
+        0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0xCB, 0xD9, 0xDD, 0xED, 0xFD,
+    ];
+
+    #[test]
+    fn should_encode_every_documented_opcode() {
+        for opcode in 0..=0xFF {
+            if ALIASES.contains(&opcode) {
+                continue;
+            }
+
+            let bytes = [opcode, 0x34, 0x12];
+            let (instruction, length) = decode_instruction(&bytes).unwrap();
+
+            assert_eq!(instruction.encode(), bytes[..length], "{}", instruction);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub mod display;
+pub mod encode;
 pub mod timing;
 
 /// Possible registers for the 8080 processor
@@ -119,3 +120,21 @@ pub enum Instruction {
     Halt,
     NoOp,
 }
+
+impl Instruction {
+    /// Address or 16-bit immediate operand of the instruction, if it has one
+    pub fn get_address_operand(&self) -> Option<u16> {
+        match self {
+            Instruction::LoadRegisterPairImmediate(_, address)
+            | Instruction::LoadAccumDirect(address)
+            | Instruction::StoreAccumDirect(address)
+            | Instruction::LoadHLDirect(address)
+            | Instruction::StoreHLDirect(address)
+            | Instruction::Jump(address)
+            | Instruction::ConditionalJump(_, address)
+            | Instruction::Call(address)
+            | Instruction::ConditionalCall(_, address) => Some(*address),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use std::{cell::RefCell, rc::Rc};
+
+/// Device driving the processor's INT line
+///
+/// The controller is polled at every instruction boundary while interrupts
+/// are enabled. Once a request is accepted, the controller supplies the
+/// instruction bytes placed on the data bus during the acknowledge cycles.
+pub trait InterruptController {
+    fn is_requesting(&self) -> bool;
+    fn acknowledge(&mut self) -> Vec<u8>;
+}
+
+impl<T: InterruptController> InterruptController for Rc<RefCell<T>> {
+    fn is_requesting(&self) -> bool {
+        self.borrow().is_requesting()
+    }
+
+    fn acknowledge(&mut self) -> Vec<u8> {
+        self.borrow_mut().acknowledge()
+    }
+}
@@ -0,0 +1,296 @@
+use std::{cell::RefCell, fmt, ops::RangeInclusive, rc::Rc};
+
+use super::{internal::InternalMemory, AccessKind, AddressableMemory, OPEN_BUS_VALUE};
+
+/// Peripheral mapped into the memory address space
+///
+/// Devices receive the absolute address of every access within their region.
+pub trait MemoryDevice {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+
+    /// Value shown when the host inspects `address`, without the side
+    /// effects of a read, the open bus value unless overridden
+    fn peek(&self, _address: u16) -> u8 {
+        OPEN_BUS_VALUE
+    }
+}
+
+impl<T: MemoryDevice> MemoryDevice for Rc<RefCell<T>> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.borrow_mut().read(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.borrow_mut().write(address, value)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.borrow().peek(address)
+    }
+}
+
+/// Data access made by the processor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: u16,
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+enum RegionKind {
+    Ram,
+    Rom,
+    Unmapped,
+    Device(RefCell<Box<dyn MemoryDevice>>),
+}
+
+struct MappedRegion {
+    addresses: RangeInclusive<u16>,
+    kind: RegionKind,
+}
+
+/// Address space seen by the processor
+///
+/// The whole address space is RAM until regions are mapped. Regions mapped
+/// later take precedence over earlier ones, and RAM/ROM regions share a
+/// single backing store indexed by absolute address.
+pub struct MemoryBus {
+    storage: InternalMemory,
+    regions: Vec<MappedRegion>,
+    recording: bool,
+    accesses: RefCell<Vec<MemoryAccess>>,
+}
+
+impl MemoryBus {
+    pub fn new() -> Self {
+        MemoryBus {
+            storage: InternalMemory::new(),
+            regions: vec![],
+            recording: false,
+            accesses: RefCell::new(vec![]),
+        }
+    }
+
+    fn map(&mut self, addresses: RangeInclusive<u16>, kind: RegionKind) {
+        self.regions.push(MappedRegion { addresses, kind });
+    }
+
+    pub fn map_ram(&mut self, addresses: RangeInclusive<u16>) {
+        self.map(addresses, RegionKind::Ram);
+    }
+
+    pub fn map_rom(&mut self, addresses: RangeInclusive<u16>) {
+        self.map(addresses, RegionKind::Rom);
+    }
+
+    pub fn map_unmapped(&mut self, addresses: RangeInclusive<u16>) {
+        self.map(addresses, RegionKind::Unmapped);
+    }
+
+    pub fn map_device(&mut self, addresses: RangeInclusive<u16>, device: Box<dyn MemoryDevice>) {
+        self.map(addresses, RegionKind::Device(RefCell::new(device)));
+    }
+
+    fn get_region_kind(&self, address: u16) -> &RegionKind {
+        self.regions
+            .iter()
+            .rev()
+            .find(|region| region.addresses.contains(&address))
+            .map_or(&RegionKind::Ram, |region| &region.kind)
+    }
+
+    /// Writes bytes into the backing store, ignoring write protection of ROM
+    /// regions, and wrapping around from 0xFFFF to 0x0000
+    pub fn load(&mut self, start: u16, bytes: Vec<u8>) {
+        for (offset, byte) in bytes.into_iter().enumerate() {
+            self.storage.set(start.wrapping_add(offset as u16), byte);
+        }
+    }
+
+    /// Enables recording of data accesses, which are collected with
+    /// `take_accesses`
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+        self.accesses.get_mut().clear();
+    }
+
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(self.accesses.get_mut())
+    }
+
+    fn record(&self, address: u16, value: u8, kind: AccessKind) {
+        if self.recording {
+            self.accesses.borrow_mut().push(MemoryAccess {
+                address,
+                value,
+                kind,
+            });
+        }
+    }
+
+    fn read(&self, address: u16) -> u8 {
+        match self.get_region_kind(address) {
+            RegionKind::Ram | RegionKind::Rom => self.storage.get(address),
+            RegionKind::Unmapped => OPEN_BUS_VALUE,
+            RegionKind::Device(device) => device.borrow_mut().read(address),
+        }
+    }
+
+    /// Reads an instruction byte, which is not recorded as a data access
+    pub fn fetch(&self, address: u16) -> u8 {
+        self.read(address)
+    }
+
+    /// Reads bytes from the backing store without side effects on devices
+    pub fn peek_range(&self, start: u16, end: u16) -> Vec<u8> {
+        self.storage.get_range(start, end)
+    }
+
+    /// Reads a byte as the processor would see it, without device side
+    /// effects or a recorded access, for debuggers and dumps
+    pub fn inspect(&self, address: u16) -> u8 {
+        match self.get_region_kind(address) {
+            RegionKind::Ram | RegionKind::Rom => self.storage.get(address),
+            RegionKind::Unmapped => OPEN_BUS_VALUE,
+            RegionKind::Device(device) => device.borrow().peek(address),
+        }
+    }
+
+    pub fn inspect_range(&self, start: u16, end: u16) -> Vec<u8> {
+        (start..=end).map(|address| self.inspect(address)).collect()
+    }
+}
+
+impl AddressableMemory<u16> for MemoryBus {
+    fn get_range(&self, start: u16, end: u16) -> Vec<u8> {
+        (start..=end).map(|address| self.get(address)).collect()
+    }
+
+    fn set_range(&mut self, start: u16, end: u16, bytes: Vec<u8>) {
+        for (byte, address) in bytes.into_iter().zip(start..=end) {
+            self.set(address, byte)
+        }
+    }
+
+    fn get(&self, address: u16) -> u8 {
+        let value = self.read(address);
+
+        self.record(address, value, AccessKind::Read);
+
+        value
+    }
+
+    fn set(&mut self, address: u16, value: u8) {
+        self.record(address, value, AccessKind::Write);
+
+        match self.get_region_kind(address) {
+            RegionKind::Ram => self.storage.set(address, value),
+            RegionKind::Rom | RegionKind::Unmapped => {}
+            RegionKind::Device(device) => device.borrow_mut().write(address, value),
+        }
+    }
+}
+
+impl fmt::Debug for MemoryBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let regions: Vec<String> = self
+            .regions
+            .iter()
+            .map(|region| {
+                let kind = match region.kind {
+                    RegionKind::Ram => "RAM",
+                    RegionKind::Rom => "ROM",
+                    RegionKind::Unmapped => "unmapped",
+                    RegionKind::Device(_) => "device",
+                };
+
+                format!(
+                    "{:#06x}..={:#06x} {}",
+                    region.addresses.start(),
+                    region.addresses.end(),
+                    kind
+                )
+            })
+            .collect();
+
+        f.debug_struct("MemoryBus")
+            .field("regions", &regions)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Register(u8);
+
+    impl MemoryDevice for Register {
+        fn read(&mut self, _address: u16) -> u8 {
+            let value = self.0;
+            self.0 = 0;
+            value
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.0 = value ^ address.to_be_bytes()[1];
+        }
+
+        fn peek(&self, _address: u16) -> u8 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn should_route_accesses_by_region() {
+        let mut bus = MemoryBus::new();
+
+        bus.load(0x0000, vec![0x11, 0x22]);
+        bus.map_rom(0x0000..=0x0FFF);
+        bus.map_unmapped(0x8000..=0xFFFF);
+        bus.map_device(0x9000..=0x9000, Box::new(Register(0x42)));
+
+        bus.set(0x0001, 0x99);
+        assert_eq!(bus.get_range(0x0000, 0x0001), vec![0x11, 0x22]);
+
+        bus.set(0x1000, 0x33);
+        assert_eq!(bus.get(0x1000), 0x33);
+
+        bus.set(0x8000, 0x44);
+        assert_eq!(bus.get(0x8000), 0xFF);
+
+        assert_eq!(bus.get(0x9000), 0x42);
+        assert_eq!(bus.get(0x9000), 0x00);
+        bus.set(0x9000, 0x0F);
+        assert_eq!(bus.get(0x9000), 0x0F);
+    }
+
+    #[test]
+    fn should_inspect_without_side_effects() {
+        let mut bus = MemoryBus::new();
+
+        bus.load(0x0000, vec![0x11]);
+        bus.map_unmapped(0x8000..=0x8FFF);
+        bus.map_device(0x9000..=0x9000, Box::new(Register(0x42)));
+        bus.set_recording(true);
+
+        assert_eq!(bus.inspect_range(0x0000, 0x0000), vec![0x11]);
+        assert_eq!(bus.inspect(0x8000), 0xFF);
+        assert_eq!(bus.inspect(0x9000), 0x42);
+        assert_eq!(bus.inspect(0x9000), 0x42);
+        assert!(bus.take_accesses().is_empty());
+        assert_eq!(bus.get(0x9000), 0x42);
+        assert_eq!(bus.inspect(0x9000), 0x00);
+    }
+
+    #[test]
+    fn should_wrap_loads_around_the_address_space() {
+        let mut bus = MemoryBus::new();
+
+        bus.load(0xFFFE, vec![0x01, 0x02, 0x03, 0x04]);
+
+        assert_eq!(bus.get_range(0xFFFE, 0xFFFF), vec![0x01, 0x02]);
+        assert_eq!(bus.get_range(0x0000, 0x0001), vec![0x03, 0x04]);
+    }
+}
@@ -19,4 +19,12 @@ impl AddressableMemory<u16> for InternalMemory {
             self.0[address] = byte
         }
     }
+
+    fn get(&self, address: u16) -> u8 {
+        self.0[address.to_usize()]
+    }
+
+    fn set(&mut self, address: u16, value: u8) {
+        self.0[address.to_usize()] = value
+    }
 }
@@ -1,6 +1,16 @@
+pub mod bus;
 pub mod internal;
 pub mod io;
 
+/// Value read from the data bus when nothing drives it
+pub const OPEN_BUS_VALUE: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
 pub trait Address {
     fn to_usize(self) -> usize;
 }
@@ -1,7 +1,9 @@
 pub mod condition_flags;
 pub mod execution;
 pub mod instructions;
+pub mod interrupt;
 pub mod memory;
+pub mod ports;
 pub mod program_counter;
 pub mod register;
 pub mod state;
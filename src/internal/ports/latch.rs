@@ -0,0 +1,48 @@
+use crate::internal::memory::{io::IOMemory, AddressableMemory};
+
+use super::PortDevice;
+
+/// Default device which latches values on every port
+///
+/// Inputs are set by the host and read by `IN`, outputs are written by `OUT`
+/// and read back by the host.
+#[derive(Debug)]
+pub struct LatchDevice {
+    inputs: IOMemory,
+    outputs: IOMemory,
+}
+
+impl LatchDevice {
+    pub fn new() -> Self {
+        LatchDevice {
+            inputs: IOMemory::new(),
+            outputs: IOMemory::new(),
+        }
+    }
+
+    pub fn set_input(&mut self, port: u8, value: u8) {
+        self.inputs.set(port, value);
+    }
+
+    pub fn get_input(&self, port: u8) -> u8 {
+        self.inputs.get(port)
+    }
+
+    pub fn get_output(&self, port: u8) -> u8 {
+        self.outputs.get(port)
+    }
+
+    pub fn set_output(&mut self, port: u8, value: u8) {
+        self.outputs.set(port, value);
+    }
+}
+
+impl PortDevice for LatchDevice {
+    fn read(&mut self, port: u8, _cycles: usize) -> u8 {
+        self.inputs.get(port)
+    }
+
+    fn write(&mut self, port: u8, value: u8, _cycles: usize) {
+        self.outputs.set(port, value);
+    }
+}
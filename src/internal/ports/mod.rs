@@ -0,0 +1,186 @@
+pub mod latch;
+
+use std::{cell::RefCell, fmt, ops::RangeInclusive, rc::Rc};
+
+use latch::LatchDevice;
+
+use super::memory::AccessKind;
+
+/// Peripheral attached to one or more I/O ports
+///
+/// Devices are notified of every `IN`/`OUT` access to the ports they are
+/// registered on, along with the total number of clock cycles elapsed when
+/// the instruction started.
+pub trait PortDevice {
+    fn read(&mut self, port: u8, cycles: usize) -> u8;
+    fn write(&mut self, port: u8, value: u8, cycles: usize);
+}
+
+impl<T: PortDevice> PortDevice for Rc<RefCell<T>> {
+    fn read(&mut self, port: u8, cycles: usize) -> u8 {
+        self.borrow_mut().read(port, cycles)
+    }
+
+    fn write(&mut self, port: u8, value: u8, cycles: usize) {
+        self.borrow_mut().write(port, value, cycles)
+    }
+}
+
+/// Port access made by an `IN` or `OUT` instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortAccess {
+    pub port: u8,
+    pub value: u8,
+    pub kind: AccessKind,
+}
+
+struct MappedDevice {
+    ports: RangeInclusive<u8>,
+    device: Box<dyn PortDevice>,
+}
+
+/// Routes port accesses to registered devices
+///
+/// Ports without a registered device fall back to a latch, which returns the
+/// last value set by the host on input and stores the last value written on
+/// output.
+pub struct PortBus {
+    devices: Vec<MappedDevice>,
+    pub latch: LatchDevice,
+    recording: bool,
+    accesses: Vec<PortAccess>,
+}
+
+impl PortBus {
+    pub fn new() -> Self {
+        PortBus {
+            devices: vec![],
+            latch: LatchDevice::new(),
+            recording: false,
+            accesses: vec![],
+        }
+    }
+
+    /// Registers a device on a range of ports, taking precedence over any
+    /// device previously registered on the same ports
+    pub fn register(&mut self, ports: RangeInclusive<u8>, device: Box<dyn PortDevice>) {
+        self.devices.push(MappedDevice { ports, device });
+    }
+
+    fn get_device(&mut self, port: u8) -> &mut dyn PortDevice {
+        match self
+            .devices
+            .iter_mut()
+            .rev()
+            .find(|mapped| mapped.ports.contains(&port))
+        {
+            Some(mapped) => mapped.device.as_mut(),
+            None => &mut self.latch,
+        }
+    }
+
+    /// Enables recording of port accesses, which are collected with
+    /// `take_accesses`
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+        self.accesses.clear();
+    }
+
+    pub fn take_accesses(&mut self) -> Vec<PortAccess> {
+        std::mem::take(&mut self.accesses)
+    }
+
+    fn record(&mut self, port: u8, value: u8, kind: AccessKind) {
+        if self.recording {
+            self.accesses.push(PortAccess { port, value, kind });
+        }
+    }
+
+    pub fn read(&mut self, port: u8, cycles: usize) -> u8 {
+        let value = self.get_device(port).read(port, cycles);
+
+        self.record(port, value, AccessKind::Read);
+
+        value
+    }
+
+    pub fn write(&mut self, port: u8, value: u8, cycles: usize) {
+        self.record(port, value, AccessKind::Write);
+
+        self.get_device(port).write(port, value, cycles)
+    }
+}
+
+impl fmt::Debug for PortBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PortBus")
+            .field(
+                "devices",
+                &self
+                    .devices
+                    .iter()
+                    .map(|mapped| &mapped.ports)
+                    .collect::<Vec<_>>(),
+            )
+            .field("latch", &self.latch)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        reads: u8,
+        last_write: Option<(u8, u8, usize)>,
+    }
+
+    impl PortDevice for Counter {
+        fn read(&mut self, _port: u8, _cycles: usize) -> u8 {
+            self.reads += 1;
+            self.reads
+        }
+
+        fn write(&mut self, port: u8, value: u8, cycles: usize) {
+            self.last_write = Some((port, value, cycles));
+        }
+    }
+
+    #[test]
+    fn should_route_accesses_to_registered_device() {
+        let counter = Rc::new(RefCell::new(Counter {
+            reads: 0,
+            last_write: None,
+        }));
+
+        let mut bus = PortBus::new();
+        bus.register(0x10..=0x11, Box::new(Rc::clone(&counter)));
+
+        assert_eq!(bus.read(0x10, 0), 1);
+        assert_eq!(bus.read(0x11, 0), 2);
+
+        bus.write(0x11, 0xAB, 42);
+        assert_eq!(counter.borrow().last_write, Some((0x11, 0xAB, 42)));
+    }
+
+    #[test]
+    fn should_fall_back_to_latch() {
+        let mut bus = PortBus::new();
+        bus.register(
+            0x10..=0x10,
+            Box::new(Counter {
+                reads: 0,
+                last_write: None,
+            }),
+        );
+
+        bus.latch.set_input(0x20, 0x55);
+        assert_eq!(bus.read(0x20, 0), 0x55);
+
+        bus.write(0x20, 0x66, 0);
+        bus.write(0x10, 0x77, 0);
+        assert_eq!(bus.latch.get_output(0x20), 0x66);
+        assert_eq!(bus.latch.get_output(0x10), 0x00);
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 #[derive(Debug, Default)]
 pub struct Registers {
     pub a: u8,
@@ -9,3 +11,13 @@ pub struct Registers {
     pub l: u8,
     pub stack_pointer: u16,
 }
+
+impl Display for Registers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "A={:#04x} B={:#04x} C={:#04x} D={:#04x} E={:#04x} H={:#04x} L={:#04x} SP={:#06x}",
+            self.a, self.b, self.c, self.d, self.e, self.h, self.l, self.stack_pointer
+        )
+    }
+}
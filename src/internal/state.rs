@@ -1,8 +1,12 @@
+use std::fmt::Display;
+
 use crate::internal::instructions::{Register, RegisterPair};
+use crate::symbols::{SymbolTable, Symbolic};
 
 use super::{
     condition_flags::ConditionFlags,
-    memory::{internal::InternalMemory, io::IOMemory, AddressableMemory},
+    memory::{bus::MemoryBus, AddressableMemory},
+    ports::PortBus,
     program_counter::ProgramCounter,
     register::Registers,
 };
@@ -10,26 +14,30 @@ use super::{
 #[derive(Debug)]
 pub struct State {
     pub enabled: bool,
+    pub halted: bool,
     pub interrupt_enabled: bool,
+    pub interrupt_delayed: bool,
     pub program_counter: ProgramCounter,
     pub registers: Registers,
     pub condition_flags: ConditionFlags,
-    pub memory: InternalMemory,
-    pub inputs: IOMemory,
-    pub outputs: IOMemory,
+    pub memory: MemoryBus,
+    pub ports: PortBus,
+    pub cycles: usize,
 }
 
 impl State {
     pub fn new() -> Self {
         Self {
             enabled: true,
-            interrupt_enabled: true,
+            halted: false,
+            interrupt_enabled: false,
+            interrupt_delayed: false,
             program_counter: ProgramCounter::new(),
             registers: Registers::default(),
             condition_flags: ConditionFlags::default(),
-            memory: InternalMemory::new(),
-            inputs: IOMemory::new(),
-            outputs: IOMemory::new(),
+            memory: MemoryBus::new(),
+            ports: PortBus::new(),
+            cycles: 0,
         }
     }
 
@@ -99,7 +107,7 @@ impl State {
     }
 
     pub fn get_psw(&self) -> u16 {
-        return u16::from_be_bytes([self.registers.a, self.condition_flags.get_byte()]);
+        u16::from_be_bytes([self.registers.a, self.condition_flags.get_byte()])
     }
 
     pub fn set_psw(&mut self, psw: u16) {
@@ -132,3 +140,33 @@ impl State {
         u16::from_be_bytes([high_byte, low_byte])
     }
 }
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    /// Displays the state preceded by the location of the program counter
+    /// relative to the nearest symbol
+    pub fn display_with<'a>(&'a self, symbols: &'a SymbolTable) -> Symbolic<'a, State> {
+        Symbolic::new(self, symbols)
+    }
+}
+
+impl Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "PC={:#06x} {}",
+            self.program_counter.get(),
+            self.registers
+        )?;
+        write!(
+            f,
+            "{} INTE={} HALT={}",
+            self.condition_flags, self.interrupt_enabled as u8, self.halted as u8
+        )
+    }
+}
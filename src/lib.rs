@@ -1,3 +1,13 @@
+pub mod assembler;
+pub mod cpm;
+pub mod debugger;
+pub mod devices;
+pub mod disassembler;
+pub mod gdb;
+pub mod image;
+pub mod symbols;
 pub mod system;
 
 mod internal;
+
+pub use internal::instructions;
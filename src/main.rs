@@ -1,3 +1,907 @@
+use std::{
+    cell::RefCell,
+    env, fs,
+    io::{self, BufWriter},
+    net::TcpListener,
+    process,
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use emulator_8080::devices::{console::RawMode, serial::PtyConsole};
+use emulator_8080::{
+    cpm::{
+        disk::{DiskFormat, FORMAT_NAMES},
+        machine::{CpmMachine, DRIVES},
+        system::BdosSystem,
+    },
+    debugger::Debugger,
+    devices::{
+        console::{ConsoleDevice, StdConsole, DEFAULT_ESCAPE},
+        serial::{StreamConsole, TcpConsole},
+        usart::Usart8251,
+    },
+    disassembler::{disassemble as disassemble_lines, FlowDisassembly, RESTART_VECTORS},
+    gdb::GdbStub,
+    image::{self, ihex, ImageFormat, LoadedImage},
+    symbols::{self, SymbolTable},
+    system::{
+        dump::hex_dump,
+        recording::Recording,
+        trace::{TraceFormat, Tracer},
+        Machine, System,
+    },
+};
+
+const USAGE: &str = "\
+Usage: emulator-8080 [OPTIONS] <IMAGE> [ARGUMENTS...]
+
+Images ending in .hex or .ihx are read as Intel HEX, .s19, .s28, .s37, .srec or .mot
+as Motorola S-records, and anything else as a flat binary.
+
+Options:
+  -f, --format <FORMAT>     Image format: hex, srec or bin (default: from the extension)
+  -l, --load <ADDRESS>      Address to load a flat binary at (default 0x0000, 0x0100 with --cpm)
+  -e, --entry <ADDRESS>     Initial program counter (default: from the image, else load address)
+      --cpm                 Run a CP/M program with BDOS calls serviced on the host, passing
+                            ARGUMENTS as its command line and exiting on warm boot
+      --drive <D=DIR>       With --cpm, back drive D: with a host directory, may be repeated
+                            (default: A: is the current directory)
+      --boot                Boot CP/M 2.2 from the system tracks of the disk image IMAGE, with
+                            ARGUMENTS as the disk images for B: to D:, saving written sectors
+                            to the image files and stopping once standard input ends
+      --disk-format <FORMAT>
+                            With --boot, ibm-3740 (8\" SSSD, default) or z80pack-hd
+      --system <FILE>       With --boot, load the CCP and BDOS from FILE, assembled for
+                            0xE400, instead of the system tracks
+  -c, --cycles <COUNT>      Stop after at least COUNT clock cycles
+  -i, --instructions <COUNT>
+                            Stop after COUNT instructions
+  -d, --dump <START:END>    Dump a memory range at exit, may be repeated
+      --save-hex <FILE>     Write the --dump ranges to FILE as Intel HEX at exit
+      --restore-state <FILE>
+                            Resume from a snapshot written by --save-state
+      --save-state <FILE>   Write a snapshot of the whole machine to FILE at exit
+      --replay <FILE>       Start from the snapshot in a recording and reapply its input
+                            and interrupt events at the recorded cycle counts
+      --record <FILE>       Write a recording of the run to FILE at exit, including interrupts
+                            from --usart-interrupt but not other input from attached devices
+      --trace <FILE>        Write a line per instruction executed to FILE, or - for stdout
+      --trace-format <FORMAT>
+                            text (default), pairs, binary, or a template such as
+                            '{pc} {bytes:8} {mnemonic:16} A={a} F={f} SP={sp} CYC={cycles}'
+      --console <STATUS[,DATA]>
+                            Attach the host terminal, in raw mode, to a status port and a
+                            data port (default: the port after STATUS)
+      --escape <KEY>        Key which stops the program with --console, such as ^] (default)
+                            or 'none'
+      --usart <PORT>        Attach an 8251 USART with its data register at the even PORT and
+                            its control and status registers at PORT+1
+      --serial <BACKEND>    Line for --usart: pty (default, printing the terminal to open),
+                            tcp:PORT for a client on localhost, or file:INPUT[,OUTPUT] to
+                            read INPUT and write OUTPUT (default: standard output)
+      --usart-interrupt <RST>
+                            Request RST 0 to 7 while a received character is waiting.
+                            Halting to wait for one ends the run once input has ended,
+                            or straight away with --cycles or --instructions
+      --debug               Start the interactive debugger instead of running
+      --gdb <PORT>          Wait for a GDB remote protocol connection on localhost
+      --rewind              With --gdb, keep execution history for reverse-step and
+                            reverse-continue, not with --usart
+  -s, --symbols <FILE>      Load symbols (name = address, .SYM or .PRN) for listings and dumps
+      --disassemble         Print a disassembly listing of the image instead of running
+      --recursive           With --disassemble, follow control flow from the load address,
+                            entry address and RST vectors, printing assembler source
+  -h, --help                Print this message
+
+Numbers may be decimal, or hexadecimal with a 0x prefix or H suffix.";
+
+/// Instructions between rewind checkpoints, bounding the work of a reverse
+/// step
+const REWIND_INTERVAL: usize = 10_000;
+
+/// Rewind checkpoints kept, of about 64KiB each
+const REWIND_CHECKPOINTS: usize = 256;
+
+/// Instructions between checks for a character to interrupt on
+const USART_POLL_INTERVAL: usize = 1024;
+
+/// Host end of the USART's serial line
+enum SerialBackend {
+    Pty,
+    Tcp(u16),
+    File(String, Option<String>),
+}
+
+struct Options {
+    image_path: String,
+    format: Option<ImageFormat>,
+    load_address: Option<u16>,
+    entry_address: Option<u16>,
+    cpm: bool,
+    drives: Vec<(u8, String)>,
+    boot: bool,
+    disk_format: Option<DiskFormat>,
+    system_path: Option<String>,
+    program_arguments: Vec<String>,
+    max_cycles: Option<usize>,
+    max_instructions: Option<usize>,
+    dump_ranges: Vec<(u16, u16)>,
+    debug: bool,
+    gdb_port: Option<u16>,
+    rewind: bool,
+    disassemble: bool,
+    recursive: bool,
+    symbols_path: Option<String>,
+    save_hex_path: Option<String>,
+    restore_state_path: Option<String>,
+    save_state_path: Option<String>,
+    replay_path: Option<String>,
+    record_path: Option<String>,
+    trace_path: Option<String>,
+    trace_format: TraceFormat,
+    console_ports: Option<(u8, u8)>,
+    escape: Option<u8>,
+    usart_port: Option<u8>,
+    serial_backend: Option<SerialBackend>,
+    usart_interrupt: Option<u8>,
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    symbols::parse_number(text).ok_or_else(|| format!("invalid number '{}'", text))
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let value = parse_number(text)?;
+
+    u16::try_from(value).map_err(|_| format!("address '{}' is out of range", text))
+}
+
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+    let (start, end) = text
+        .split_once(':')
+        .ok_or_else(|| format!("invalid range '{}', expected START:END", text))?;
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+
+    if start > end {
+        return Err(format!("range '{}' ends before it starts", text));
+    }
+
+    Ok((start, end))
+}
+
+fn parse_drive(text: &str) -> Result<(u8, String), String> {
+    match text.split_once('=') {
+        Some((letter, directory)) if letter.len() == 1 && !directory.is_empty() => {
+            let drive = letter.as_bytes()[0].to_ascii_uppercase().wrapping_sub(b'A');
+
+            if drive < 16 {
+                return Ok((drive, directory.to_string()));
+            }
+
+            Err(format!("invalid drive '{}', expected A to P", letter))
+        }
+        _ => Err(format!("invalid drive '{}', expected D=DIR", text)),
+    }
+}
+
+fn parse_port(text: &str) -> Result<u8, String> {
+    let value = parse_number(text)?;
+
+    u8::try_from(value).map_err(|_| format!("port '{}' is out of range", text))
+}
+
+fn parse_console_ports(text: &str) -> Result<(u8, u8), String> {
+    match text.split_once(',') {
+        Some((status, data)) => Ok((parse_port(status)?, parse_port(data)?)),
+        None => {
+            let status = parse_port(text)?;
+            Ok((status, status.wrapping_add(1)))
+        }
+    }
+}
+
+fn parse_usart_port(text: &str) -> Result<u8, String> {
+    let port = parse_port(text)?;
+
+    if port & 0x01 != 0 {
+        return Err(format!("USART port '{}' must be even", text));
+    }
+
+    Ok(port)
+}
+
+/// Parses a backend such as `pty`, `tcp:2323` or `file:in.txt,out.txt`
+fn parse_serial_backend(text: &str) -> Result<SerialBackend, String> {
+    match text.split_once(':') {
+        None if text == "pty" => Ok(SerialBackend::Pty),
+        Some(("tcp", port)) => port
+            .parse()
+            .map(SerialBackend::Tcp)
+            .map_err(|_| format!("invalid port '{}'", port)),
+        Some(("file", paths)) if !paths.is_empty() => Ok(match paths.split_once(',') {
+            Some((input, output)) => {
+                SerialBackend::File(input.to_string(), Some(output.to_string()))
+            }
+            None => SerialBackend::File(paths.to_string(), None),
+        }),
+        _ => Err(format!(
+            "invalid serial backend '{}', expected pty, tcp:PORT or file:INPUT[,OUTPUT]",
+            text
+        )),
+    }
+}
+
+fn parse_restart(text: &str) -> Result<u8, String> {
+    parse_number(text)
+        .ok()
+        .and_then(|value| u8::try_from(value).ok())
+        .filter(|restart| *restart <= 7)
+        .ok_or_else(|| format!("invalid restart '{}', expected 0 to 7", text))
+}
+
+/// Parses a key such as `^]`, a number, or `none`
+fn parse_escape(text: &str) -> Result<Option<u8>, String> {
+    match text.as_bytes() {
+        b"none" => Ok(None),
+        [b'^', key @ b'@'..=b'_'] => Ok(Some(key & 0x1F)),
+        [b'^', key @ b'a'..=b'z'] => Ok(Some(key & 0x1F)),
+        _ => {
+            let value = parse_number(text).map_err(|_| format!("invalid escape key '{}'", text))?;
+            u8::try_from(value)
+                .map(Some)
+                .map_err(|_| format!("invalid escape key '{}'", text))
+        }
+    }
+}
+
+fn parse_options(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options {
+        image_path: String::new(),
+        format: None,
+        load_address: None,
+        entry_address: None,
+        cpm: false,
+        drives: vec![],
+        boot: false,
+        disk_format: None,
+        system_path: None,
+        program_arguments: vec![],
+        max_cycles: None,
+        max_instructions: None,
+        dump_ranges: vec![],
+        debug: false,
+        gdb_port: None,
+        rewind: false,
+        disassemble: false,
+        recursive: false,
+        symbols_path: None,
+        save_hex_path: None,
+        restore_state_path: None,
+        save_state_path: None,
+        replay_path: None,
+        record_path: None,
+        trace_path: None,
+        trace_format: TraceFormat::default(),
+        console_ports: None,
+        escape: Some(DEFAULT_ESCAPE),
+        usart_port: None,
+        serial_backend: None,
+        usart_interrupt: None,
+    };
+    let mut image_path = None;
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for '{}'", arg))
+        };
+
+        match arg.as_str() {
+            "-f" | "--format" => {
+                let name = value()?;
+                options.format = Some(
+                    ImageFormat::from_name(&name)
+                        .ok_or_else(|| format!("unknown image format '{}'", name))?,
+                );
+            }
+            "-l" | "--load" => options.load_address = Some(parse_address(&value()?)?),
+            "-e" | "--entry" => options.entry_address = Some(parse_address(&value()?)?),
+            "--cpm" => options.cpm = true,
+            "--drive" => options.drives.push(parse_drive(&value()?)?),
+            "--boot" => options.boot = true,
+            "--disk-format" => {
+                let name = value()?;
+                options.disk_format = Some(DiskFormat::from_name(&name).ok_or_else(|| {
+                    format!(
+                        "unknown disk format '{}', expected one of {}",
+                        name,
+                        FORMAT_NAMES.join(", ")
+                    )
+                })?);
+            }
+            "--system" => options.system_path = Some(value()?),
+            "-c" | "--cycles" => options.max_cycles = Some(parse_number(&value()?)?),
+            "-i" | "--instructions" => options.max_instructions = Some(parse_number(&value()?)?),
+            "-d" | "--dump" => options.dump_ranges.push(parse_range(&value()?)?),
+            "--debug" => options.debug = true,
+            "--gdb" => {
+                let port = value()?;
+                options.gdb_port = Some(
+                    port.parse()
+                        .map_err(|_| format!("invalid port '{}'", port))?,
+                );
+            }
+            "--rewind" => options.rewind = true,
+            "--console" => options.console_ports = Some(parse_console_ports(&value()?)?),
+            "--escape" => options.escape = parse_escape(&value()?)?,
+            "--usart" => options.usart_port = Some(parse_usart_port(&value()?)?),
+            "--serial" => options.serial_backend = Some(parse_serial_backend(&value()?)?),
+            "--usart-interrupt" => options.usart_interrupt = Some(parse_restart(&value()?)?),
+            "-s" | "--symbols" => options.symbols_path = Some(value()?),
+            "--save-hex" => options.save_hex_path = Some(value()?),
+            "--restore-state" => options.restore_state_path = Some(value()?),
+            "--save-state" => options.save_state_path = Some(value()?),
+            "--replay" => options.replay_path = Some(value()?),
+            "--record" => options.record_path = Some(value()?),
+            "--trace" => options.trace_path = Some(value()?),
+            "--trace-format" => options.trace_format = TraceFormat::from_name(&value()?)?,
+            "--disassemble" => options.disassemble = true,
+            "--recursive" => options.recursive = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ if image_path.is_none() => image_path = Some(arg),
+            _ => options.program_arguments.push(arg),
+        }
+    }
+
+    options.image_path = image_path.ok_or("missing image path")?;
+
+    if options.boot {
+        if let Some(arg) = options.program_arguments.get(DRIVES - 1) {
+            return Err(format!("unexpected argument '{}'", arg));
+        }
+
+        if options.cpm
+            || options.disassemble
+            || options.gdb_port.is_some()
+            || options.console_ports.is_some()
+            || options.usart_port.is_some()
+            || options.replay_path.is_some()
+            || options.record_path.is_some()
+        {
+            return Err(String::from(
+                "--boot cannot be used with --cpm, --disassemble, --gdb, --console, --usart, --replay or --record",
+            ));
+        }
+    } else if options.disk_format.is_some() || options.system_path.is_some() {
+        return Err(String::from("--disk-format and --system require --boot"));
+    }
+
+    if !options.cpm {
+        if let Some(arg) = options.program_arguments.first().filter(|_| !options.boot) {
+            return Err(format!("unexpected argument '{}'", arg));
+        }
+
+        if !options.drives.is_empty() {
+            return Err(String::from("--drive requires --cpm"));
+        }
+    }
+
+    if options.cpm && options.gdb_port.is_some() {
+        return Err(String::from("--gdb cannot be used with --cpm"));
+    }
+
+    if options.rewind && options.gdb_port.is_none() {
+        return Err(String::from("--rewind requires --gdb"));
+    }
+
+    if options.console_ports.is_some()
+        && (options.cpm || options.debug || options.gdb_port.is_some())
+    {
+        return Err(String::from(
+            "--console cannot be used with --cpm, --debug or --gdb",
+        ));
+    }
+
+    if options.usart_port.is_none()
+        && (options.serial_backend.is_some() || options.usart_interrupt.is_some())
+    {
+        return Err(String::from(
+            "--serial and --usart-interrupt require --usart",
+        ));
+    }
+
+    if options.cpm && options.usart_port.is_some() {
+        return Err(String::from("--usart cannot be used with --cpm"));
+    }
+
+    if options.rewind && options.usart_port.is_some() {
+        return Err(String::from("--rewind cannot be used with --usart"));
+    }
+
+    if options.cpm && options.replay_path.is_some() {
+        return Err(String::from("--replay cannot be used with --cpm"));
+    }
+
+    if options.replay_path.is_some() && options.usart_interrupt.is_some() {
+        return Err(String::from(
+            "--replay cannot be used with --usart-interrupt",
+        ));
+    }
+
+    if options.cpm && options.record_path.is_some() {
+        return Err(String::from("--record cannot be used with --cpm"));
+    }
+
+    if options.restore_state_path.is_some() && options.replay_path.is_some() {
+        return Err(String::from("--replay cannot be used with --restore-state"));
+    }
+
+    Ok(options)
+}
+
+/// Reads the image file, placing flat binaries at the load address and
+/// taking the entry address from the options, the file or the first segment
+fn read_image(options: &Options) -> Result<LoadedImage, String> {
+    let format = options
+        .format
+        .unwrap_or_else(|| ImageFormat::from_path(&options.image_path));
+    let default_load_address = if options.cpm { 0x0100 } else { 0x0000 };
+    let mut image = image::read_file(
+        &options.image_path,
+        format,
+        options.load_address.unwrap_or(default_load_address),
+    )?;
+
+    image.entry = options
+        .entry_address
+        .or(image.entry)
+        .or(image.segments.first().map(|segment| segment.address));
+
+    Ok(image)
+}
+
+/// Opens the disk images in drives A: to D: and cold boots CP/M from them
+fn boot(options: &Options) -> Result<CpmMachine, String> {
+    let mut machine = CpmMachine::new(StdConsole::new());
+    let format = options.disk_format.clone().unwrap_or_default();
+    let paths = [&options.image_path]
+        .into_iter()
+        .chain(&options.program_arguments);
+
+    for (drive, path) in paths.enumerate() {
+        machine.open_disk(drive, path, format.clone())?;
+    }
+
+    if let Some(path) = &options.system_path {
+        let image = fs::read(path).map_err(|error| format!("cannot read '{}': {}", path, error))?;
+        machine.load_system(image)?;
+    }
+
+    machine.boot()?;
+
+    Ok(machine)
+}
+
+fn save_hex(machine: &impl Machine, options: &Options, path: &str) {
+    let ranges: Vec<_> = options
+        .dump_ranges
+        .iter()
+        .map(|&(start, end)| start..=end)
+        .collect();
+    let image = LoadedImage::from_memory(machine, &ranges);
+
+    if let Err(error) = fs::write(path, ihex::write(&image)) {
+        eprintln!("error: cannot write '{}': {}", path, error);
+        process::exit(1);
+    }
+}
+
+fn restore_state(machine: &mut impl Machine, path: &str) {
+    let result = fs::read(path)
+        .map_err(|error| format!("cannot read '{}': {}", path, error))
+        .and_then(|data| {
+            machine
+                .restore_state(&data)
+                .map_err(|message| format!("cannot restore '{}': {}", path, message))
+        });
+
+    if let Err(message) = result {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
+
+fn save_state(machine: &impl Machine, path: &str) {
+    if let Err(error) = fs::write(path, machine.save_state()) {
+        eprintln!("error: cannot write '{}': {}", path, error);
+        process::exit(1);
+    }
+}
+
+fn open_tracer(options: &Options) -> Option<Tracer> {
+    let path = options.trace_path.as_ref()?;
+    let format = options.trace_format.clone();
+
+    let result = if path == "-" {
+        Tracer::new(io::stdout(), format)
+    } else {
+        fs::File::create(path).and_then(|file| Tracer::new(BufWriter::new(file), format))
+    };
+
+    Some(result.unwrap_or_else(|error| {
+        eprintln!("error: cannot write '{}': {}", path, error);
+        process::exit(1);
+    }))
+}
+
+fn finish_tracer(tracer: Option<Tracer>, options: &Options) {
+    let (Some(tracer), Some(path)) = (tracer, &options.trace_path) else {
+        return;
+    };
+
+    if let Err(error) = tracer.finish() {
+        eprintln!("error: cannot write '{}': {}", path, error);
+        process::exit(1);
+    }
+}
+
+fn serve_gdb(system: &mut System, port: u16) {
+    let result = TcpListener::bind(("127.0.0.1", port)).and_then(|listener| {
+        eprintln!("Waiting for GDB connection on 127.0.0.1:{}", port);
+
+        GdbStub::new(system).serve(&listener)
+    });
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+/// Console device on the host terminal, which stays in raw mode until dropped
+struct Terminal {
+    device: Rc<RefCell<ConsoleDevice>>,
+    #[cfg(unix)]
+    _raw_mode: Option<RawMode>,
+}
+
+fn attach_terminal(system: &mut System, options: &Options) -> Option<Terminal> {
+    let (status_port, data_port) = options.console_ports?;
+    let mut device = ConsoleDevice::new(StdConsole::new(), status_port, data_port);
+    device.set_escape(options.escape);
+
+    let device = Rc::new(RefCell::new(device));
+    system.register_port_device(status_port, Rc::clone(&device));
+    system.register_port_device(data_port, Rc::clone(&device));
+
+    // Input may be piped, in which case it is read as it is
+    Some(Terminal {
+        device,
+        #[cfg(unix)]
+        _raw_mode: RawMode::enable().ok(),
+    })
+}
+
+#[cfg(unix)]
+fn open_pty() -> io::Result<Usart8251> {
+    let console = PtyConsole::open()?;
+    eprintln!("USART on {}", console.get_path().display());
+
+    Ok(Usart8251::new(console))
+}
+
+#[cfg(not(unix))]
+fn open_pty() -> io::Result<Usart8251> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "pseudo-terminals need a Unix host",
+    ))
+}
+
+fn open_usart(options: &Options) -> io::Result<Usart8251> {
+    match options
+        .serial_backend
+        .as_ref()
+        .unwrap_or(&SerialBackend::Pty)
+    {
+        SerialBackend::Pty => open_pty(),
+        SerialBackend::Tcp(port) => {
+            let console = TcpConsole::bind(*port)?;
+            eprintln!("USART listening on 127.0.0.1:{}", console.get_port());
+
+            Ok(Usart8251::new(console))
+        }
+        SerialBackend::File(input, Some(output)) => {
+            StreamConsole::open(input, output).map(Usart8251::new)
+        }
+        SerialBackend::File(input, None) => fs::File::open(input)
+            .map(|input| Usart8251::new(StreamConsole::new(input, io::stdout()))),
+    }
+}
+
+fn attach_usart(system: &mut System, options: &Options) -> Option<Rc<RefCell<Usart8251>>> {
+    let port = options.usart_port?;
+    let mut usart = open_usart(options).unwrap_or_else(|error| {
+        eprintln!("error: cannot open the USART line: {}", error);
+        process::exit(1);
+    });
+    usart.set_receive_interrupt(options.usart_interrupt);
+
+    let usart = Rc::new(RefCell::new(usart));
+    system.register_port_range_device(port..=port + 1, Rc::clone(&usart));
+
+    if options.usart_interrupt.is_some() {
+        system.attach_interrupt_controller(Rc::clone(&usart));
+    }
+
+    Some(usart)
+}
+
+fn run(
+    machine: &mut impl Machine,
+    options: &Options,
+    symbols: &SymbolTable,
+    terminal: Option<Terminal>,
+    usart: Option<Rc<RefCell<Usart8251>>>,
+) {
+    if options.debug {
+        let stdin = io::stdin();
+        let mut debugger = Debugger::new(machine);
+
+        debugger.set_symbols(symbols.clone());
+        debugger
+            .run(stdin.lock(), io::stdout())
+            .unwrap_or_else(|error| {
+                eprintln!("error: {}", error);
+                process::exit(1);
+            });
+
+        if let Some(path) = &options.save_state_path {
+            save_state(machine, path);
+        }
+
+        return;
+    }
+
+    let mut instruction_count: usize = 0;
+    let mut cycle_count: usize = 0;
+
+    while options
+        .max_instructions
+        .map_or(true, |max| instruction_count < max)
+        && options.max_cycles.map_or(true, |max| cycle_count < max)
+    {
+        let instruction_cycles = machine.step();
+
+        if instruction_cycles == 0 {
+            let state = machine.get_state();
+
+            // A halted program may be waiting for a character to interrupt it
+            match &usart {
+                Some(usart) if state.halted && state.interrupt_enabled => {
+                    let mut usart = usart.borrow_mut();
+                    usart.poll();
+
+                    if usart.is_receive_ready() {
+                        continue;
+                    }
+
+                    // Waiting executes nothing towards the budgets, and ended
+                    // input never brings another character
+                    if usart.is_input_ended()
+                        || options.max_instructions.is_some()
+                        || options.max_cycles.is_some()
+                    {
+                        break;
+                    }
+
+                    thread::sleep(Duration::from_millis(1));
+
+                    if terminal.as_ref().is_some_and(|terminal| {
+                        let mut device = terminal.device.borrow_mut();
+                        device.poll();
+                        device.is_escaped()
+                    }) {
+                        break;
+                    }
+
+                    continue;
+                }
+                _ => break,
+            }
+        }
+
+        instruction_count += 1;
+        cycle_count += instruction_cycles;
+
+        if let Some(usart) = usart
+            .as_ref()
+            .filter(|_| instruction_count % USART_POLL_INTERVAL == 0)
+        {
+            usart.borrow_mut().poll();
+        }
+
+        if terminal
+            .as_ref()
+            .is_some_and(|terminal| terminal.device.borrow().is_escaped())
+        {
+            break;
+        }
+    }
+
+    drop(terminal);
+
+    let state = machine.get_state();
+
+    println!();
+    println!("Instructions: {}", instruction_count);
+    println!("Cycles: {}", cycle_count);
+    println!("{}", state.display_with(symbols));
+
+    if let Some(path) = &options.save_hex_path {
+        save_hex(machine, options, path);
+    }
+
+    if let Some(path) = &options.save_state_path {
+        save_state(machine, path);
+    }
+
+    for &(start, end) in &options.dump_ranges {
+        println!();
+        print!(
+            "{}",
+            hex_dump(start, &machine.read_memory_region(start, end))
+        );
+    }
+}
+
+fn disassemble(options: &Options, image: &LoadedImage, symbols: &SymbolTable) {
+    for segment in &image.segments {
+        if options.recursive {
+            let mut entry_points = vec![segment.address];
+            entry_points.extend(image.entry);
+            entry_points.extend(RESTART_VECTORS);
+
+            print!(
+                "{}",
+                FlowDisassembly::trace(&segment.bytes, segment.address, &entry_points)
+            );
+        } else {
+            for line in disassemble_lines(&segment.bytes, segment.address) {
+                println!("{}", line.display_with(symbols));
+            }
+        }
+    }
+}
+
 fn main() {
-    println!("Hello, world!");
+    let options = parse_options(env::args().skip(1).collect()).unwrap_or_else(|message| {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(2);
+    });
+
+    let symbols = match &options.symbols_path {
+        Some(path) => SymbolTable::load(path).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }),
+        None => SymbolTable::new(),
+    };
+
+    if options.boot {
+        let mut machine = boot(&options).unwrap_or_else(|message| {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        });
+
+        if let Some(path) = &options.restore_state_path {
+            restore_state(&mut machine, path);
+        }
+
+        if let Some(tracer) = open_tracer(&options) {
+            machine.attach_tracer(tracer);
+        }
+
+        run(&mut machine, &options, &symbols, None, None);
+        finish_tracer(machine.detach_tracer(), &options);
+        return;
+    }
+
+    let image = read_image(&options).unwrap_or_else(|message| {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    });
+
+    if options.disassemble {
+        disassemble(&options, &image, &symbols);
+    } else if options.cpm {
+        let mut system = BdosSystem::new(StdConsole::new());
+        let arguments: Vec<_> = options
+            .program_arguments
+            .iter()
+            .map(String::as_str)
+            .collect();
+
+        if options.drives.is_empty() {
+            system.set_drive(0, ".");
+        }
+
+        for (drive, directory) in &options.drives {
+            system.set_drive(*drive, directory);
+        }
+
+        image.load_into(&mut system);
+        system.set_command_line(&arguments);
+
+        if let Some(path) = &options.restore_state_path {
+            restore_state(&mut system, path);
+        }
+
+        if let Some(tracer) = open_tracer(&options) {
+            system.attach_tracer(tracer);
+        }
+
+        run(&mut system, &options, &symbols, None, None);
+        finish_tracer(system.detach_tracer(), &options);
+    } else {
+        let mut system = System::new();
+
+        system.load_image(&image);
+
+        if let Some(path) = &options.restore_state_path {
+            restore_state(&mut system, path);
+        }
+
+        if let Some(path) = &options.replay_path {
+            let result = Recording::load(path).and_then(|recording| system.replay(&recording));
+
+            if let Err(message) = result {
+                eprintln!("error: {}", message);
+                process::exit(1);
+            }
+        }
+
+        if options.record_path.is_some() {
+            system.start_recording();
+        }
+
+        if let Some(tracer) = open_tracer(&options) {
+            system.attach_tracer(tracer);
+        }
+
+        let terminal = attach_terminal(&mut system, &options);
+        let usart = attach_usart(&mut system, &options);
+
+        match options.gdb_port {
+            Some(port) => {
+                if options.rewind {
+                    system.enable_rewind(REWIND_INTERVAL, REWIND_CHECKPOINTS);
+                }
+
+                serve_gdb(&mut system, port);
+            }
+            None => {
+                // Only a receive interrupt needs the USART polled between instructions
+                let usart = usart.filter(|_| options.usart_interrupt.is_some());
+                run(&mut system, &options, &symbols, terminal, usart)
+            }
+        }
+
+        if let (Some(recording), Some(path)) = (system.stop_recording(), &options.record_path) {
+            if let Err(message) = recording.save(path) {
+                eprintln!("error: {}", message);
+                process::exit(1);
+            }
+        }
+
+        finish_tracer(system.detach_tracer(), &options);
+    }
 }
@@ -0,0 +1,480 @@
+use std::{collections::BTreeMap, fmt::Display, fs, path::Path};
+
+use crate::{disassembler::Line, instructions::Instruction, system::State};
+
+/// Largest distance from a symbol at which an address is still shown
+/// relative to it, as in `BUFFER+0x10`
+const MAX_SYMBOL_OFFSET: u16 = 0x100;
+
+/// Column at which the source text starts in CP/M assembler listings
+const PRN_SOURCE_COLUMN: usize = 16;
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || "_?@.$".contains(c))
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_?@.$".contains(c))
+}
+
+/// Parses a number as the command line, the debugger and `name = address`
+/// symbol files take it: decimal, or hexadecimal with a `0x` prefix or `H`
+/// suffix
+pub fn parse_number(text: &str) -> Option<usize> {
+    let lowercase = text.to_ascii_lowercase();
+
+    if let Some(hex) = lowercase.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = lowercase.strip_suffix('h') {
+        usize::from_str_radix(hex, 16).ok()
+    } else {
+        lowercase.parse().ok()
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    parse_number(text).and_then(|value| u16::try_from(value).ok())
+}
+
+fn expand_tabs(line: &str) -> String {
+    let mut expanded = String::new();
+
+    for c in line.chars() {
+        if c == '\t' {
+            expanded.push(' ');
+
+            while expanded.len() % 8 != 0 {
+                expanded.push(' ');
+            }
+        } else {
+            expanded.push(c);
+        }
+    }
+
+    expanded
+}
+
+/// Table of names for memory addresses
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    addresses: BTreeMap<String, u16>,
+    /// First name given to each address, used when resolving addresses
+    names: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    /// Loads a symbol file, choosing the format from its extension: `.sym`
+    /// for assembler symbol tables, `.prn` for listings and `name = address`
+    /// assignments for anything else
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("cannot read '{}': {}", path.display(), error))?;
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+
+        let result = match extension.as_deref() {
+            Some("sym") => SymbolTable::parse_sym(&text),
+            Some("prn") => Ok(SymbolTable::parse_prn(&text)),
+            _ => SymbolTable::parse_assignments(&text),
+        };
+
+        result.map_err(|message| format!("{}: {}", path.display(), message))
+    }
+
+    /// Parses lines of `name = address` or `name EQU address`, ignoring blank
+    /// lines and comments starting with `;` or `#`
+    pub fn parse_assignments(text: &str) -> Result<Self, String> {
+        let mut symbols = SymbolTable::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split([';', '#']).next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line
+                .split(['=', ' ', '\t'])
+                .filter(|t| !t.is_empty())
+                .collect();
+            let (name, address) = match tokens[..] {
+                [name, address] if line.contains('=') => (name, address),
+                [name, equ, address] if equ.eq_ignore_ascii_case("equ") => (name, address),
+                _ => return Err(format!("line {}: expected 'name = address'", index + 1)),
+            };
+
+            if !is_identifier(name) {
+                return Err(format!(
+                    "line {}: invalid symbol name '{}'",
+                    index + 1,
+                    name
+                ));
+            }
+
+            let address = parse_address(address)
+                .ok_or_else(|| format!("line {}: invalid address '{}'", index + 1, address))?;
+
+            symbols.insert(name, address);
+        }
+
+        Ok(symbols)
+    }
+
+    /// Parses a `.SYM` file as written by CP/M assemblers and linkers, made
+    /// of hexadecimal address and name pairs
+    pub fn parse_sym(text: &str) -> Result<Self, String> {
+        let mut symbols = SymbolTable::new();
+
+        for (index, line) in text.lines().enumerate() {
+            // CP/M pads text files to whole records with ^Z
+            let line = line.trim_end_matches('\x1A');
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            for pair in tokens.chunks(2) {
+                let [address, name] = pair else {
+                    return Err(format!("line {}: missing name after address", index + 1));
+                };
+                let address = u16::from_str_radix(address, 16)
+                    .map_err(|_| format!("line {}: invalid address '{}'", index + 1, address))?;
+
+                symbols.insert(name, address);
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /// Extracts labels and `EQU` definitions from an assembler listing
+    ///
+    /// Lines start with the address in hexadecimal, followed by either the
+    /// assembled bytes or `=` for equates, with the source text starting at
+    /// column 16. Lines that do not look like this are skipped.
+    pub fn parse_prn(text: &str) -> Self {
+        let mut symbols = SymbolTable::new();
+
+        for line in text.lines() {
+            let line = expand_tabs(line);
+            let mut fields = line.split_whitespace();
+
+            let Some(address) = fields
+                .next()
+                .filter(|field| field.len() == 4)
+                .and_then(|field| u16::from_str_radix(field, 16).ok())
+            else {
+                continue;
+            };
+
+            let Some(source) = line.get(PRN_SOURCE_COLUMN..) else {
+                continue;
+            };
+            let has_bytes = line[..PRN_SOURCE_COLUMN].split_whitespace().count() > 1;
+            let mut tokens = source.split_whitespace();
+            let first = tokens.next().unwrap_or_default();
+            let second = tokens.next().unwrap_or_default();
+
+            let name = if let Some(label) = first.strip_suffix(':') {
+                label
+            } else if second.eq_ignore_ascii_case("equ")
+                || second.eq_ignore_ascii_case("set")
+                || (has_bytes && !source.starts_with(' '))
+            {
+                first
+            } else {
+                continue;
+            };
+
+            if is_identifier(name) {
+                symbols.insert(name, address);
+            }
+        }
+
+        symbols
+    }
+
+    /// Defines `name`, replacing any previous definition of it
+    pub fn insert(&mut self, name: &str, address: u16) {
+        if let Some(previous) = self.addresses.insert(name.to_string(), address) {
+            if self.names.get(&previous).is_some_and(|n| n == name) {
+                self.names.remove(&previous);
+
+                // Another name for the previous address takes over displaying it
+                if let Some(alias) = self
+                    .addresses
+                    .iter()
+                    .find(|(_, alias_address)| **alias_address == previous)
+                    .map(|(alias, _)| alias.clone())
+                {
+                    self.names.insert(previous, alias);
+                }
+            }
+        }
+
+        self.names
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+    }
+
+    pub fn get_address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Name of the symbol defined at exactly `address`
+    pub fn get_name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    /// Describes `address` relative to the closest symbol at or below it,
+    /// as `NAME` or `NAME+0x3`
+    pub fn resolve(&self, address: u16) -> Option<String> {
+        let (symbol_address, name) = self.names.range(..=address).next_back()?;
+        let offset = address - symbol_address;
+
+        match offset {
+            0 => Some(name.clone()),
+            _ if offset < MAX_SYMBOL_OFFSET => Some(format!("{}+{:#x}", name, offset)),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Iterates over all symbols in order of name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.addresses
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address))
+    }
+
+    /// Formats the table in the `.SYM` format, ordered by address
+    pub fn to_sym(&self) -> String {
+        let mut entries: Vec<(u16, &str)> =
+            self.iter().map(|(name, address)| (address, name)).collect();
+        entries.sort();
+
+        entries
+            .chunks(4)
+            .map(|line| {
+                let pairs: Vec<String> = line
+                    .iter()
+                    .map(|(address, name)| format!("{:04X} {}", address, name))
+                    .collect();
+
+                format!("{}\n", pairs.join("\t"))
+            })
+            .collect()
+    }
+}
+
+/// Writes the table as `name = address` assignments, which
+/// `SymbolTable::parse_assignments` reads back
+impl Display for SymbolTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, address) in self.iter() {
+            writeln!(f, "{} = {:#06x}", name, address)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Value displayed with addresses replaced by symbol names where known
+pub struct Symbolic<'a, T> {
+    value: &'a T,
+    symbols: &'a SymbolTable,
+}
+
+impl<'a, T> Symbolic<'a, T> {
+    pub fn new(value: &'a T, symbols: &'a SymbolTable) -> Self {
+        Symbolic { value, symbols }
+    }
+}
+
+impl Display for Symbolic<'_, Instruction> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = self.value.to_string();
+        let name = self
+            .value
+            .get_address_operand()
+            .and_then(|address| Some((address, self.symbols.get_name(address)?)));
+
+        match name {
+            Some((address, name)) => {
+                write!(
+                    f,
+                    "{}",
+                    text.replacen(&format!("{:#06x}", address), name, 1)
+                )
+            }
+            None => write!(f, "{}", text),
+        }
+    }
+}
+
+impl Display for Symbolic<'_, State> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let program_counter = self.value.program_counter.get();
+
+        if let Some(location) = self.symbols.resolve(program_counter) {
+            writeln!(f, "<{}>", location)?;
+        }
+
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Display for Symbolic<'_, Line> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = self.value;
+
+        if let Some(name) = self.symbols.get_name(line.address) {
+            writeln!(f, "{}:", name)?;
+        }
+
+        match &line.instruction {
+            Some(instruction) => write!(
+                f,
+                "{}{}",
+                line.format_prefix(),
+                instruction.display_with(self.symbols)
+            ),
+            None => write!(f, "{}", line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_numbers() {
+        assert_eq!(parse_number("100"), Some(100));
+        assert_eq!(parse_number("0x100"), Some(0x100));
+        assert_eq!(parse_number("0FFH"), Some(0xFF));
+        assert_eq!(parse_number("100000"), Some(100_000));
+        assert_eq!(parse_number("ff"), None);
+        assert_eq!(parse_number("0x"), None);
+    }
+
+    #[test]
+    fn should_parse_assignments() {
+        let symbols = SymbolTable::parse_assignments(
+            "; comment\nSTART = 0x0100\n\nBDOS EQU 5\nbuffer=0080h # trailing\n",
+        )
+        .unwrap();
+
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.get_address("START"), Some(0x0100));
+        assert_eq!(symbols.get_address("BDOS"), Some(0x0005));
+        assert_eq!(symbols.get_name(0x0080), Some("buffer"));
+
+        assert_eq!(
+            SymbolTable::parse_assignments("START = 0x0100\nLOOP 0x0200\n").unwrap_err(),
+            "line 2: expected 'name = address'"
+        );
+        assert_eq!(
+            SymbolTable::parse_assignments("1ST = 0x0100\n").unwrap_err(),
+            "line 1: invalid symbol name '1ST'"
+        );
+    }
+
+    #[test]
+    fn should_parse_sym_and_prn_files() {
+        let symbols = SymbolTable::parse_sym("0005 BDOS\t0100 START\n0103 LOOP\n\x1A\x1A").unwrap();
+
+        assert_eq!(symbols.get_address("LOOP"), Some(0x0103));
+        assert_eq!(symbols.get_address("BDOS"), Some(0x0005));
+
+        let symbols = SymbolTable::parse_prn(
+            " 0100          \tORG\t100H\n \
+             0005 =         BDOS\tEQU\t5\n \
+             0100 C30301    START:\tJMP\tLOOP\n \
+             0103 0E09      LOOP\tMVI\tC,9\n \
+             0105 CD0500    \tCALL\tBDOS\n\
+             0000\n",
+        );
+
+        assert_eq!(
+            symbols.iter().collect::<Vec<_>>(),
+            vec![("BDOS", 0x0005), ("LOOP", 0x0103), ("START", 0x0100)]
+        );
+    }
+
+    #[test]
+    fn should_emit_symbol_files() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("START", 0x0100);
+        symbols.insert("BDOS", 0x0005);
+
+        assert_eq!(symbols.to_string(), "BDOS = 0x0005\nSTART = 0x0100\n");
+        assert_eq!(symbols.to_sym(), "0005 BDOS\t0100 START\n");
+
+        let reloaded = SymbolTable::parse_assignments(&symbols.to_string()).unwrap();
+        assert_eq!(reloaded.get_address("START"), Some(0x0100));
+    }
+
+    #[test]
+    fn should_resolve_addresses() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("START", 0x0100);
+        symbols.insert("ALIAS", 0x0100);
+        symbols.insert("BDOS", 0x0005);
+
+        assert_eq!(symbols.resolve(0x0100).as_deref(), Some("START"));
+        assert_eq!(symbols.resolve(0x0103).as_deref(), Some("START+0x3"));
+        assert_eq!(symbols.resolve(0x0300), None);
+        assert_eq!(symbols.resolve(0x0004), None);
+
+        assert_eq!(
+            Instruction::Call(0x0005).display_with(&symbols).to_string(),
+            "CALL    BDOS"
+        );
+        assert_eq!(
+            Instruction::Jump(0x0104).display_with(&symbols).to_string(),
+            "JMP     0x0104"
+        );
+
+        let mut state = State::new();
+        state.program_counter.set(0x0102);
+
+        assert!(state
+            .display_with(&symbols)
+            .to_string()
+            .starts_with("<START+0x2>\nPC=0x0102"));
+
+        let line = Line::decode(0x0100, &[0xC3, 0x05, 0x00]);
+
+        assert_eq!(
+            line.display_with(&symbols).to_string(),
+            "START:\n0100  c3 05 00  JMP     BDOS"
+        );
+    }
+
+    #[test]
+    fn should_keep_aliases_when_redefining() {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("START", 0x0100);
+        symbols.insert("ENTRY", 0x0100);
+
+        assert_eq!(symbols.get_name(0x0100), Some("START"));
+
+        symbols.insert("START", 0x0200);
+
+        assert_eq!(symbols.get_name(0x0100), Some("ENTRY"));
+        assert_eq!(symbols.get_name(0x0200), Some("START"));
+        assert_eq!(symbols.get_address("ENTRY"), Some(0x0100));
+    }
+}
@@ -0,0 +1,246 @@
+use std::ops::RangeInclusive;
+
+pub use crate::internal::{
+    memory::{bus::MemoryAccess, AccessKind},
+    ports::PortAccess,
+};
+
+use super::State;
+
+pub type BreakpointId = usize;
+
+/// Kinds of access which trigger a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(&self, kind: AccessKind) -> bool {
+        match self {
+            WatchKind::Read => kind == AccessKind::Read,
+            WatchKind::Write => kind == AccessKind::Write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+/// Reason for `System::run` returning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    CycleLimit,
+    Halted,
+    PoweredOff,
+    /// The program counter reached an address breakpoint, or a conditional
+    /// breakpoint evaluated to true, before executing the next instruction
+    Breakpoint(BreakpointId),
+    /// The last instruction executed accessed a watched memory address
+    MemoryWatchpoint(BreakpointId, MemoryAccess),
+    /// The last instruction executed accessed a watched port
+    PortWatchpoint(BreakpointId, PortAccess),
+}
+
+enum Breakpoint {
+    Address(u16),
+    Condition(Box<dyn Fn(&State) -> bool>),
+    Memory(RangeInclusive<u16>, WatchKind),
+    Port(u8, WatchKind),
+}
+
+#[derive(Default)]
+pub struct Breakpoints {
+    next_id: BreakpointId,
+    entries: Vec<(BreakpointId, Breakpoint)>,
+}
+
+impl Breakpoints {
+    fn add(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        let id = self.next_id;
+
+        self.next_id += 1;
+        self.entries.push((id, breakpoint));
+
+        id
+    }
+
+    pub fn add_address(&mut self, address: u16) -> BreakpointId {
+        self.add(Breakpoint::Address(address))
+    }
+
+    pub fn add_condition(&mut self, condition: impl Fn(&State) -> bool + 'static) -> BreakpointId {
+        self.add(Breakpoint::Condition(Box::new(condition)))
+    }
+
+    pub fn add_memory_watch(
+        &mut self,
+        addresses: RangeInclusive<u16>,
+        kind: WatchKind,
+    ) -> BreakpointId {
+        self.add(Breakpoint::Memory(addresses, kind))
+    }
+
+    pub fn add_port_watch(&mut self, port: u8, kind: WatchKind) -> BreakpointId {
+        self.add(Breakpoint::Port(port, kind))
+    }
+
+    pub fn remove(&mut self, id: BreakpointId) -> bool {
+        let length = self.entries.len();
+
+        self.entries.retain(|(entry_id, _)| *entry_id != id);
+
+        self.entries.len() != length
+    }
+
+    pub fn has_memory_watches(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|(_, breakpoint)| matches!(breakpoint, Breakpoint::Memory(_, _)))
+    }
+
+    pub fn has_port_watches(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|(_, breakpoint)| matches!(breakpoint, Breakpoint::Port(_, _)))
+    }
+
+    /// Finds a breakpoint triggered before executing the next instruction
+    pub fn check_state(&self, state: &State) -> Option<StopReason> {
+        self.entries
+            .iter()
+            .find(|(_, breakpoint)| match breakpoint {
+                Breakpoint::Address(address) => state.program_counter.get() == *address,
+                Breakpoint::Condition(condition) => condition(state),
+                _ => false,
+            })
+            .map(|(id, _)| StopReason::Breakpoint(*id))
+    }
+
+    /// Finds a watchpoint triggered by the accesses of the last instruction
+    pub fn check_accesses(
+        &self,
+        memory_accesses: &[MemoryAccess],
+        port_accesses: &[PortAccess],
+    ) -> Option<StopReason> {
+        self.entries
+            .iter()
+            .find_map(|(id, breakpoint)| match breakpoint {
+                Breakpoint::Memory(addresses, kind) => memory_accesses
+                    .iter()
+                    .find(|access| addresses.contains(&access.address) && kind.matches(access.kind))
+                    .map(|access| StopReason::MemoryWatchpoint(*id, *access)),
+                Breakpoint::Port(port, kind) => port_accesses
+                    .iter()
+                    .find(|access| access.port == *port && kind.matches(access.kind))
+                    .map(|access| StopReason::PortWatchpoint(*id, *access)),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+
+    fn create_system() -> System {
+        let mut system = System::new();
+
+        system.load_program(vec![
+            0x3E, 0x05, // MVI A, 0x05
+            0x3D, // DCR A
+            0x32, 0x00, 0x20, // STA 0x2000
+            0xC2, 0x02, 0x00, // JNZ 0x0002
+            0xD3, 0x10, // OUT 0x10
+            0x3A, 0x00, 0x20, // LDA 0x2000
+            0x76, // HLT
+        ]);
+
+        system
+    }
+
+    #[test]
+    fn should_stop_at_address_breakpoints() {
+        let mut system = create_system();
+        let id = system.add_breakpoint(0x0006);
+
+        assert_eq!(system.run(1000), StopReason::Breakpoint(id));
+        assert_eq!(system.get_program_counter(), 0x0006);
+
+        assert_eq!(system.run(1000), StopReason::Breakpoint(id));
+        assert!(system.remove_breakpoint(id));
+        assert_eq!(system.run(1000), StopReason::Halted);
+    }
+
+    #[test]
+    fn should_stop_on_conditions() {
+        let mut system = create_system();
+        let id = system.add_conditional_breakpoint(|state| {
+            state.registers.a == 2 && !state.condition_flags.sign
+        });
+
+        assert_eq!(system.run(1000), StopReason::Breakpoint(id));
+        assert_eq!(system.get_program_counter(), 0x0003);
+    }
+
+    #[test]
+    fn should_stop_on_memory_and_port_accesses() {
+        let mut system = create_system();
+        let read_id = system.add_memory_watchpoint(0x2000..=0x2000, WatchKind::Read);
+        let port_id = system.add_port_watchpoint(0x10, WatchKind::ReadWrite);
+
+        assert_eq!(
+            system.run(1000),
+            StopReason::PortWatchpoint(
+                port_id,
+                PortAccess {
+                    port: 0x10,
+                    value: 0x00,
+                    kind: AccessKind::Write
+                }
+            )
+        );
+
+        assert_eq!(
+            system.run(1000),
+            StopReason::MemoryWatchpoint(
+                read_id,
+                MemoryAccess {
+                    address: 0x2000,
+                    value: 0x00,
+                    kind: AccessKind::Read
+                }
+            )
+        );
+
+        assert_eq!(system.run(1000), StopReason::Halted);
+    }
+
+    #[test]
+    fn should_stop_on_memory_writes() {
+        let mut system = create_system();
+        let id = system.add_memory_watchpoint(0x1000..=0x2FFF, WatchKind::Write);
+
+        assert_eq!(
+            system.run(1000),
+            StopReason::MemoryWatchpoint(
+                id,
+                MemoryAccess {
+                    address: 0x2000,
+                    value: 0x04,
+                    kind: AccessKind::Write
+                }
+            )
+        );
+        assert_eq!(system.get_program_counter(), 0x0006);
+    }
+
+    #[test]
+    fn should_not_watch_instruction_fetches() {
+        let mut system = create_system();
+        system.add_memory_watchpoint(0x0000..=0x00FF, WatchKind::Read);
+
+        assert_eq!(system.run(1000), StopReason::Halted);
+    }
+}
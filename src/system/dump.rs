@@ -0,0 +1,49 @@
+const BYTES_PER_LINE: usize = 16;
+
+/// Formats bytes read from `start` as lines of hex values with their ASCII
+/// representation
+pub fn hex_dump(start: u16, bytes: &[u8]) -> String {
+    let mut output = String::new();
+
+    for (line_index, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let address = start.wrapping_add((line_index * BYTES_PER_LINE) as u16);
+
+        let hex: Vec<String> = line.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let ascii: String = line
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+
+        output.push_str(&format!(
+            "{:04x}  {:<width$}  {}\n",
+            address,
+            hex.join(" "),
+            ascii,
+            width = BYTES_PER_LINE * 3 - 1
+        ));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_format_lines() {
+        let bytes: Vec<u8> = (0x40..0x52).collect();
+
+        assert_eq!(
+            hex_dump(0x0100, &bytes),
+            "0100  40 41 42 43 44 45 46 47 48 49 4a 4b 4c 4d 4e 4f  @ABCDEFGHIJKLMNO\n\
+             0110  50 51                                            PQ\n"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::{fmt, fs, path::Path};
+
+use super::{test::TestSystem, Machine};
+
+/// Outcome of running an exerciser, decided from its console output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Passed,
+    Failed(String),
+}
+
+/// CPU test program run under CP/M console emulation, along with the
+/// messages it prints on success and failure
+pub struct Exerciser {
+    pub name: &'static str,
+    pub file_name: &'static str,
+    success: &'static str,
+    failures: &'static [&'static str],
+}
+
+/// Test programs bundled in `test_roms/`, quickest first
+pub const EXERCISERS: [Exerciser; 4] = [
+    Exerciser {
+        name: "TST8080",
+        file_name: "TST8080.COM",
+        success: "CPU IS OPERATIONAL",
+        failures: &["CPU HAS FAILED"],
+    },
+    Exerciser {
+        name: "8080PRE",
+        file_name: "8080PRE.COM",
+        success: "Preliminary tests complete",
+        failures: &[],
+    },
+    Exerciser {
+        name: "CPUTEST",
+        file_name: "CPUTEST.COM",
+        success: "CPU TESTS OK",
+        failures: &["CPU FAILED", "CHECKSUM ERROR"],
+    },
+    Exerciser {
+        name: "8080EXM",
+        file_name: "8080EXM.COM",
+        success: "Tests complete",
+        failures: &["ERROR"],
+    },
+];
+
+pub struct ExerciserReport {
+    pub name: &'static str,
+    pub output: String,
+    pub instructions: usize,
+    pub cycles: usize,
+    pub verdict: Verdict,
+}
+
+impl fmt::Display for ExerciserReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verdict = match &self.verdict {
+            Verdict::Passed => String::from("PASSED"),
+            Verdict::Failed(reason) => format!("FAILED ({})", reason),
+        };
+
+        write!(
+            f,
+            "{}: {} after {} instructions, {} cycles",
+            self.name, verdict, self.instructions, self.cycles
+        )
+    }
+}
+
+impl Exerciser {
+    /// Finds an exerciser by name or file name, ignoring case
+    pub fn find(name: &str) -> Option<&'static Exerciser> {
+        EXERCISERS.iter().find(|exerciser| {
+            exerciser.name.eq_ignore_ascii_case(name)
+                || exerciser.file_name.eq_ignore_ascii_case(name)
+        })
+    }
+
+    pub fn load(&self, directory: &Path) -> Result<Vec<u8>, String> {
+        let path = directory.join(self.file_name);
+
+        fs::read(&path).map_err(|error| format!("cannot read '{}': {}", path.display(), error))
+    }
+
+    /// Fails on the first line holding a failure message, and otherwise
+    /// passes only if the success message was printed
+    pub fn judge(&self, output: &str) -> Verdict {
+        let failure = output
+            .lines()
+            .find(|line| self.failures.iter().any(|message| line.contains(message)));
+
+        if let Some(line) = failure {
+            return Verdict::Failed(line.trim().to_string());
+        }
+
+        if output.contains(self.success) {
+            Verdict::Passed
+        } else {
+            Verdict::Failed(format!("'{}' was not printed", self.success))
+        }
+    }
+
+    /// Runs `program` until it warm boots, failing if it executes more than
+    /// `max_instructions`
+    pub fn run(&self, program: Vec<u8>, max_instructions: usize) -> ExerciserReport {
+        let mut system = TestSystem::new();
+        system.load_test_program(program);
+        system.capture_output();
+
+        let mut instructions = 0;
+
+        while instructions < max_instructions && system.step() > 0 {
+            instructions += 1;
+        }
+
+        let output = String::from_utf8_lossy(&system.take_captured_output()).into_owned();
+        let verdict = if system.state.enabled {
+            Verdict::Failed(format!(
+                "did not finish within {} instructions",
+                max_instructions
+            ))
+        } else {
+            self.judge(&output)
+        };
+
+        ExerciserReport {
+            name: self.name,
+            output,
+            instructions,
+            cycles: system.state.cycles,
+            verdict,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(name: &str, max_instructions: usize) -> ExerciserReport {
+        let exerciser = Exerciser::find(name).unwrap();
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms");
+        let report = exerciser.run(exerciser.load(&directory).unwrap(), max_instructions);
+
+        assert_eq!(report.verdict, Verdict::Passed, "{}", report.output);
+
+        report
+    }
+
+    #[test]
+    fn should_pass_tst8080() {
+        let report = run("TST8080", 10_000);
+
+        assert_eq!(report.instructions, 651);
+        assert_eq!(report.cycles, 4921);
+    }
+
+    #[test]
+    fn should_pass_8080pre() {
+        run("8080pre.com", 10_000);
+    }
+
+    #[test]
+    fn should_pass_cputest() {
+        let report = run("CPUTEST", 50_000_000);
+
+        assert_eq!(report.cycles, 255_653_380);
+    }
+
+    /// Takes billions of instructions, run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn should_pass_8080exm() {
+        let report = run("8080EXM", usize::MAX);
+
+        assert_eq!(report.output.matches("PASS!").count(), 25);
+    }
+
+    #[test]
+    fn should_judge_failures_from_output() {
+        let exerciser = Exerciser::find("8080EXM").unwrap();
+
+        assert_eq!(
+            exerciser.judge(
+                "dad <b,d,h,sp>................  PASS! crc is:14474ba6\r\n\
+                 aluop nn......................  ERROR **** crc expected:9e922f9e found:00000000\r\n\
+                 Tests complete"
+            ),
+            Verdict::Failed(String::from(
+                "aluop nn......................  ERROR **** crc expected:9e922f9e found:00000000"
+            ))
+        );
+        assert_eq!(
+            Exerciser::find("8080PRE").unwrap().judge("0123"),
+            Verdict::Failed(String::from("'Preliminary tests complete' was not printed"))
+        );
+
+        let report = Exerciser::find("TST8080")
+            .unwrap()
+            .run(vec![0xC3, 0x00, 0x01], 100);
+
+        assert_eq!(
+            report.verdict,
+            Verdict::Failed(String::from("did not finish within 100 instructions"))
+        );
+    }
+}
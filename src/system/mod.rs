@@ -1,15 +1,79 @@
-use crate::internal::{
-    execution::execute_instruction,
-    instructions::{timing::get_instruction_timing, Instruction},
-    memory::AddressableMemory,
-    state::State,
+use std::{collections::VecDeque, ops::RangeInclusive};
+
+use crate::{
+    image::LoadedImage,
+    internal::{
+        execution::execute_instruction,
+        instructions::{timing::get_instruction_timing, Instruction},
+        memory::OPEN_BUS_VALUE,
+        program_counter::decode,
+    },
+};
+
+pub use crate::internal::{
+    interrupt::InterruptController, memory::bus::MemoryDevice, ports::PortDevice, state::State,
 };
 
+pub mod breakpoint;
+pub mod dump;
+pub mod exerciser;
+pub mod recording;
+pub mod rewind;
+pub mod snapshot;
 pub mod test;
+pub mod trace;
+
+use breakpoint::{BreakpointId, Breakpoints, StopReason, WatchKind};
+use recording::{Event, RecordedEvent, Recording};
+use rewind::Rewind;
+use trace::{TraceEntry, Tracer};
+
+/// Common interface over emulated machines, used by host tooling
+pub trait Machine {
+    fn get_state(&self) -> &State;
+    fn get_state_mut(&mut self) -> &mut State;
+
+    /// Executes a single instruction, returning the number of clock cycles
+    /// taken, or 0 if the machine cannot make progress
+    fn step(&mut self) -> usize;
+
+    /// Reads memory for display, without device side effects or recorded
+    /// accesses
+    fn read_memory_region(&self, address_start: u16, address_end: u16) -> Vec<u8> {
+        self.get_state()
+            .memory
+            .inspect_range(address_start, address_end)
+    }
+
+    /// Serializes the machine into a versioned snapshot
+    fn save_state(&self) -> Vec<u8> {
+        snapshot::save(self.get_state(), None)
+    }
+
+    /// Restores a snapshot written by `save_state`
+    fn restore_state(&mut self, data: &[u8]) -> Result<(), String> {
+        snapshot::restore(self.get_state_mut(), data).map(|_| ())
+    }
+}
+
+/// Decodes an instruction placed on the data bus during interrupt acknowledge,
+/// where bytes not supplied by the device read as 0xFF
+fn decode_data_bus(bytes: Vec<u8>) -> Instruction {
+    let mut bytes = bytes.into_iter();
+
+    decode(|| bytes.next().unwrap_or(OPEN_BUS_VALUE))
+}
 
 pub struct System {
     state: State,
     interrupt_instruction: Option<Instruction>,
+    interrupt_acknowledge_cycles: usize,
+    interrupt_controller: Option<Box<dyn InterruptController>>,
+    breakpoints: Breakpoints,
+    recording: Option<Recording>,
+    replay_events: VecDeque<RecordedEvent>,
+    rewind: Option<Rewind>,
+    tracer: Option<Tracer>,
 }
 
 impl System {
@@ -17,57 +81,700 @@ impl System {
         System {
             state: State::new(),
             interrupt_instruction: None,
+            interrupt_acknowledge_cycles: 0,
+            interrupt_controller: None,
+            breakpoints: Breakpoints::default(),
+            recording: None,
+            replay_events: VecDeque::new(),
+            rewind: None,
+            tracer: None,
         }
     }
 
     pub fn load_program(&mut self, program_bytecode: Vec<u8>) {
-        let address_end = program_bytecode.len() as u16;
+        self.load_program_at(0x00, program_bytecode);
+    }
 
-        self.state
-            .memory
-            .set_range(0x00, address_end, program_bytecode);
+    /// Writes `program_bytecode` into memory starting at `address`, bypassing
+    /// ROM protection
+    pub fn load_program_at(&mut self, address: u16, program_bytecode: Vec<u8>) {
+        self.state.memory.load(address, program_bytecode);
+    }
+
+    /// Loads every segment of `image` and jumps to its entry address
+    pub fn load_image(&mut self, image: &LoadedImage) {
+        image.load_into(self);
+    }
+
+    pub fn map_ram(&mut self, addresses: RangeInclusive<u16>) {
+        self.state.memory.map_ram(addresses);
+    }
+
+    /// Maps read-only memory at `start` and fills it with `contents`
+    pub fn map_rom(&mut self, start: u16, contents: Vec<u8>) {
+        if contents.is_empty() {
+            return;
+        }
+
+        let end = start.wrapping_add((contents.len() - 1) as u16);
+
+        // Contents running past 0xFFFF continue at 0x0000
+        if contents.len() >= 0x10000 {
+            self.state.memory.map_rom(0x0000..=0xFFFF);
+        } else if end < start {
+            self.state.memory.map_rom(start..=0xFFFF);
+            self.state.memory.map_rom(0x0000..=end);
+        } else {
+            self.state.memory.map_rom(start..=end);
+        }
+
+        self.state.memory.load(start, contents);
+    }
+
+    pub fn map_unmapped(&mut self, addresses: RangeInclusive<u16>) {
+        self.state.memory.map_unmapped(addresses);
+    }
+
+    pub fn map_memory_device(
+        &mut self,
+        addresses: RangeInclusive<u16>,
+        device: impl MemoryDevice + 'static,
+    ) {
+        self.state.memory.map_device(addresses, Box::new(device));
     }
 
     pub fn read_memory_region(&self, address_start: u16, address_end: u16) -> Vec<u8> {
-        self.state.memory.get_range(address_start, address_end)
+        self.state.memory.inspect_range(address_start, address_end)
     }
 
-    pub fn run(&mut self, max_clock_cycles: usize) {
-        let mut clock_cycles: usize = 0;
+    /// Executes a single instruction, or acknowledges a pending interrupt if
+    /// interrupts are enabled, returning the number of clock cycles taken
+    ///
+    /// Returns 0 without doing anything if the system is powered off, or if
+    /// it is halted and no interrupt can be acknowledged.
+    pub fn step(&mut self) -> usize {
+        self.apply_replay_events();
+        self.apply_journaled_events();
 
-        while self.state.enabled && clock_cycles < max_clock_cycles {
-            let instruction = self
-                .state
-                .program_counter
-                .get_next_instruction(&self.state.memory);
-            let instruction_cycles = get_instruction_timing(&self.state, &instruction);
+        let cycles = self.execute();
 
-            execute_instruction(&mut self.state, &instruction);
+        if cycles > 0 {
+            self.advance_rewind();
+        }
 
-            clock_cycles += instruction_cycles;
+        cycles
+    }
 
-            if let Some(interrupt_instruction) = &self.interrupt_instruction {
-                self.state.interrupt_enabled = false;
+    fn execute(&mut self) -> usize {
+        if !self.state.enabled {
+            return 0;
+        }
 
-                let interrupt_cycles = get_instruction_timing(&self.state, &interrupt_instruction);
+        self.state.memory.take_accesses();
+        self.state.ports.take_accesses();
 
-                execute_instruction(&mut self.state, &interrupt_instruction);
+        let interrupt_delayed = std::mem::take(&mut self.state.interrupt_delayed);
 
-                self.interrupt_instruction = None;
-                clock_cycles += interrupt_cycles;
+        if self.state.interrupt_enabled && !interrupt_delayed {
+            if let Some(interrupt_instruction) = self.take_interrupt_request() {
+                return self.acknowledge_interrupt(interrupt_instruction);
             }
         }
+
+        if self.state.halted {
+            return 0;
+        }
+
+        let address = self.state.program_counter.get();
+        let instruction = self
+            .state
+            .program_counter
+            .get_next_instruction(&self.state.memory);
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&TraceEntry::fetched(&self.state, address));
+        }
+
+        let instruction_cycles = get_instruction_timing(&self.state, &instruction);
+
+        execute_instruction(&mut self.state, &instruction);
+
+        self.state.cycles += instruction_cycles;
+        instruction_cycles
     }
 
+    fn take_interrupt_request(&mut self) -> Option<Instruction> {
+        if let Some(interrupt_instruction) = self.interrupt_instruction.take() {
+            return Some(interrupt_instruction);
+        }
+
+        // Going over history again, the journal latches the interrupts the
+        // controller requested the first time
+        if self.rewind.as_ref().is_some_and(Rewind::is_behind) {
+            return None;
+        }
+
+        let controller = self.interrupt_controller.as_mut()?;
+
+        if !controller.is_requesting() {
+            return None;
+        }
+
+        let bytes = controller.acknowledge();
+
+        // Replays latch the recorded instruction in place of the controller
+        self.record(Event::Interrupt(bytes.clone()));
+
+        Some(decode_data_bus(bytes))
+    }
+
+    fn acknowledge_interrupt(&mut self, interrupt_instruction: Instruction) -> usize {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&TraceEntry::new(
+                &self.state,
+                self.state.program_counter.get(),
+                interrupt_instruction.encode(),
+                true,
+            ));
+        }
+
+        self.state.interrupt_enabled = false;
+        self.state.halted = false;
+
+        let interrupt_cycles = get_instruction_timing(&self.state, &interrupt_instruction)
+            + self.interrupt_acknowledge_cycles;
+
+        execute_instruction(&mut self.state, &interrupt_instruction);
+
+        self.state.cycles += interrupt_cycles;
+        interrupt_cycles
+    }
+
+    /// Runs until at least `max_clock_cycles` have elapsed, the system is
+    /// powered off, the processor halts with no interrupt to wake it, or a
+    /// breakpoint is hit
+    pub fn run(&mut self, max_clock_cycles: usize) -> StopReason {
+        let mut clock_cycles: usize = 0;
+
+        while clock_cycles < max_clock_cycles {
+            let instruction_cycles = self.step();
+
+            if instruction_cycles == 0 {
+                return if self.state.enabled {
+                    StopReason::Halted
+                } else {
+                    StopReason::PoweredOff
+                };
+            }
+
+            clock_cycles += instruction_cycles;
+
+            if let Some(reason) = self.check_breakpoints() {
+                return reason;
+            }
+        }
+
+        StopReason::CycleLimit
+    }
+
+    fn check_breakpoints(&mut self) -> Option<StopReason> {
+        let memory_accesses = self.state.memory.take_accesses();
+        let port_accesses = self.state.ports.take_accesses();
+
+        self.breakpoints
+            .check_accesses(&memory_accesses, &port_accesses)
+            .or_else(|| self.breakpoints.check_state(&self.state))
+    }
+
+    fn update_access_recording(&mut self) {
+        self.state
+            .memory
+            .set_recording(self.breakpoints.has_memory_watches());
+        self.state
+            .ports
+            .set_recording(self.breakpoints.has_port_watches());
+    }
+
+    /// Stops `run` before executing the instruction at `address`
+    pub fn add_breakpoint(&mut self, address: u16) -> BreakpointId {
+        self.breakpoints.add_address(address)
+    }
+
+    /// Stops `run` before executing an instruction when `condition` holds
+    pub fn add_conditional_breakpoint(
+        &mut self,
+        condition: impl Fn(&State) -> bool + 'static,
+    ) -> BreakpointId {
+        self.breakpoints.add_condition(condition)
+    }
+
+    /// Stops `run` after an instruction accesses memory within `addresses`
+    pub fn add_memory_watchpoint(
+        &mut self,
+        addresses: RangeInclusive<u16>,
+        kind: WatchKind,
+    ) -> BreakpointId {
+        let id = self.breakpoints.add_memory_watch(addresses, kind);
+        self.update_access_recording();
+        id
+    }
+
+    /// Stops `run` after an `IN` or `OUT` instruction accesses `port`
+    pub fn add_port_watchpoint(&mut self, port: u8, kind: WatchKind) -> BreakpointId {
+        let id = self.breakpoints.add_port_watch(port, kind);
+        self.update_access_recording();
+        id
+    }
+
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> bool {
+        let removed = self.breakpoints.remove(id);
+        self.update_access_recording();
+        removed
+    }
+
+    /// Latches an interrupt request, which is acknowledged once interrupts
+    /// are enabled
     pub fn interrupt(&mut self, subroutine_address: u8) {
-        self.interrupt_instruction = Some(Instruction::Restart(subroutine_address));
+        self.interrupt_with_instruction(Instruction::Restart(subroutine_address));
+    }
+
+    /// Latches an interrupt request which executes `instruction` when
+    /// acknowledged, without advancing the program counter
+    pub fn interrupt_with_instruction(&mut self, instruction: Instruction) {
+        self.record(Event::Interrupt(instruction.encode()));
+        self.interrupt_instruction = Some(instruction);
+    }
+
+    /// Latches an interrupt request which executes the instruction encoded
+    /// by `bytes` when acknowledged, e.g. a `CALL` supplied by an 8228
+    pub fn interrupt_with_bytes(&mut self, bytes: &[u8]) {
+        self.interrupt_with_instruction(decode_data_bus(bytes.to_vec()));
+    }
+
+    /// Connects a controller to the INT line, which is checked in addition to
+    /// requests latched through `interrupt`
+    pub fn attach_interrupt_controller(&mut self, controller: impl InterruptController + 'static) {
+        self.interrupt_controller = Some(Box::new(controller));
+    }
+
+    /// Sets the extra clock cycles taken by each interrupt acknowledge, such
+    /// as wait states inserted by the interrupt controller
+    pub fn set_interrupt_acknowledge_cycles(&mut self, cycles: usize) {
+        self.interrupt_acknowledge_cycles = cycles;
+    }
+
+    pub fn get_program_counter(&self) -> u16 {
+        self.state.program_counter.get()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.state.halted
+    }
+
+    pub fn is_powered_on(&self) -> bool {
+        self.state.enabled
+    }
+
+    pub fn power_on(&mut self) {
+        self.state.enabled = true;
+    }
+
+    /// Stops execution regardless of the processor state, unlike `HLT` which
+    /// can be resumed from by an interrupt
+    pub fn power_off(&mut self) {
+        self.state.enabled = false;
     }
 
     pub fn set_input(&mut self, port: u8, value: u8) {
-        self.state.inputs.set(port, value);
+        self.record(Event::Input { port, value });
+        self.state.ports.latch.set_input(port, value);
     }
 
     pub fn get_output(&self, port: u8) -> u8 {
-        self.state.outputs.get(port)
+        self.state.ports.latch.get_output(port)
+    }
+
+    pub fn register_port_device(&mut self, port: u8, device: impl PortDevice + 'static) {
+        self.register_port_range_device(port..=port, device);
+    }
+
+    pub fn register_port_range_device(
+        &mut self,
+        ports: RangeInclusive<u8>,
+        device: impl PortDevice + 'static,
+    ) {
+        self.state.ports.register(ports, Box::new(device));
+    }
+
+    pub fn get_cycle_count(&self) -> usize {
+        self.state.cycles
+    }
+
+    /// Snapshots the current state and records every following `set_input`
+    /// and interrupt request with the cycle count at which it was made
+    ///
+    /// Interrupts acknowledged from an attached controller are recorded as
+    /// they are acknowledged, so a replay should run without the controller.
+    /// Other effects of attached devices are not recorded.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording::new(self.save_state()));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    fn record(&mut self, event: Event) {
+        let cycle = self.state.cycles;
+
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.journal(event.clone());
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(cycle, event);
+        }
+    }
+
+    /// Restores the starting snapshot of `recording` and reapplies its
+    /// events as execution reaches the cycle counts they were recorded at
+    pub fn replay(&mut self, recording: &Recording) -> Result<(), String> {
+        self.restore_state(&recording.snapshot)?;
+        self.replay_events = recording.events.iter().cloned().collect();
+
+        Ok(())
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        !self.replay_events.is_empty()
+    }
+
+    fn apply_replay_events(&mut self) {
+        while let Some(recorded) = self.replay_events.pop_front() {
+            if recorded.cycle > self.state.cycles {
+                self.replay_events.push_front(recorded);
+                break;
+            }
+
+            match recorded.event {
+                Event::Input { port, value } => self.set_input(port, value),
+                Event::Interrupt(bytes) => self.interrupt_with_bytes(&bytes),
+            }
+        }
+    }
+
+    /// Writes a trace entry for each instruction executed and interrupt
+    /// acknowledged from now on
+    pub fn attach_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn detach_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Keeps a checkpoint every `interval` instructions, up to `capacity` of
+    /// them, so execution can be stepped backwards
+    ///
+    /// History starts at the current state. Devices attached to the system
+    /// are not part of checkpoints and must behave deterministically.
+    pub fn enable_rewind(&mut self, interval: usize, capacity: usize) {
+        self.rewind = Some(Rewind::new(interval, capacity, self.save_state()));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Number of instructions executed since rewinding was enabled, less any
+    /// stepped back over
+    pub fn get_rewind_position(&self) -> Option<usize> {
+        self.rewind.as_ref().map(Rewind::get_position)
+    }
+
+    fn apply_journaled_events(&mut self) {
+        let Some(rewind) = self.rewind.as_ref() else {
+            return;
+        };
+
+        for event in rewind.get_events() {
+            match event {
+                Event::Input { port, value } => self.state.ports.latch.set_input(port, value),
+                Event::Interrupt(bytes) => {
+                    self.interrupt_instruction = Some(decode_data_bus(bytes));
+                }
+            }
+        }
+    }
+
+    fn advance_rewind(&mut self) {
+        let Some(rewind) = self.rewind.as_mut() else {
+            return;
+        };
+
+        if rewind.advance() {
+            rewind.add_checkpoint(snapshot::save(
+                &self.state,
+                self.interrupt_instruction.as_ref(),
+            ));
+        }
+    }
+
+    /// Restores the closest checkpoint and executes forward to `position`
+    fn rewind_to(&mut self, position: usize) {
+        let Some(snapshot) = self
+            .rewind
+            .as_mut()
+            .and_then(|rewind| rewind.seek(position))
+        else {
+            return;
+        };
+
+        self.interrupt_instruction =
+            snapshot::restore(&mut self.state, &snapshot).expect("checkpoints are valid snapshots");
+
+        // Execution already recorded or traced is not recorded or traced again
+        let recording = self.recording.take();
+        let tracer = self.tracer.take();
+
+        while self
+            .get_rewind_position()
+            .is_some_and(|current| current < position)
+        {
+            if self.step() == 0 {
+                break;
+            }
+        }
+
+        self.recording = recording;
+        self.tracer = tracer;
+    }
+
+    /// Goes back to the state before the last instruction or interrupt
+    /// acknowledge, returning false at the start of the history
+    pub fn reverse_step(&mut self) -> bool {
+        let Some(rewind) = self.rewind.as_ref() else {
+            return false;
+        };
+        let position = rewind.get_position();
+
+        if position == rewind.get_start() {
+            return false;
+        }
+
+        self.rewind_to(position - 1);
+        true
+    }
+
+    /// Goes back to the latest point in the history where `run` would have
+    /// stopped for a breakpoint or watchpoint, or to the start of the history
+    /// if there is none
+    pub fn reverse_continue(&mut self) -> Option<StopReason> {
+        let rewind = self.rewind.as_ref()?;
+        let origin = rewind.get_position();
+        let start = rewind.get_start();
+        let checkpoints = rewind.get_checkpoints_before(origin);
+
+        // Each checkpoint covers the stops after its own position, up to the
+        // position of the next later checkpoint
+        let mut end = origin;
+
+        for checkpoint in checkpoints {
+            self.rewind_to(checkpoint);
+
+            let mut last_stop = None;
+
+            while let Some(position) = self.get_rewind_position().filter(|&p| p + 1 < end) {
+                if self.step() == 0 {
+                    break;
+                }
+
+                if let Some(reason) = self.check_breakpoints() {
+                    last_stop = Some((position + 1, reason));
+                }
+            }
+
+            if let Some((position, reason)) = last_stop {
+                self.rewind_to(position);
+                return Some(reason);
+            }
+
+            end = checkpoint + 1;
+        }
+
+        self.rewind_to(start);
+        None
+    }
+}
+
+impl Machine for System {
+    fn get_state(&self) -> &State {
+        &self.state
+    }
+
+    fn get_state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    fn step(&mut self) -> usize {
+        System::step(self)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        snapshot::save(&self.state, self.interrupt_instruction.as_ref())
+    }
+
+    fn restore_state(&mut self, data: &[u8]) -> Result<(), String> {
+        self.interrupt_instruction = snapshot::restore(&mut self.state, data)?;
+        Ok(())
+    }
+}
+
+impl Default for System {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::memory::AddressableMemory;
+
+    const NOP: u8 = 0x00;
+    const EI: u8 = 0xFB;
+    const HLT: u8 = 0x76;
+
+    fn get_stack_top(system: &System) -> u16 {
+        let stack_pointer = system.state.registers.stack_pointer;
+        let bytes = system.read_memory_region(stack_pointer, stack_pointer.wrapping_add(1));
+
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+
+    #[test]
+    fn should_keep_interrupt_latched_while_disabled() {
+        let mut system = System::new();
+        system.load_program(vec![NOP, NOP, NOP]);
+        system.interrupt(1);
+
+        system.run(12);
+
+        assert_eq!(system.state.program_counter.get(), 0x0003);
+        assert!(system.interrupt_instruction.is_some());
+    }
+
+    #[test]
+    fn should_delay_interrupts_until_after_instruction_following_ei() {
+        let mut system = System::new();
+        system.load_program(vec![EI, NOP, NOP]);
+        system.interrupt(1);
+
+        assert_eq!(system.step(), 4);
+        assert_eq!(system.step(), 4);
+        assert_eq!(system.state.program_counter.get(), 0x0002);
+
+        assert_eq!(system.step(), 11);
+        assert_eq!(system.state.program_counter.get(), 0x0008);
+        assert_eq!(get_stack_top(&system), 0x0002);
+    }
+
+    #[test]
+    fn should_disable_interrupts_on_acknowledge() {
+        let mut system = System::new();
+        system.load_program(vec![EI, NOP]);
+
+        system.step();
+        system.step();
+        system.interrupt(2);
+        system.step();
+
+        assert_eq!(system.state.program_counter.get(), 0x0010);
+        assert!(!system.state.interrupt_enabled);
+
+        system.interrupt(3);
+        system.step();
+
+        assert_eq!(system.state.program_counter.get(), 0x0011);
+    }
+
+    #[test]
+    fn should_acknowledge_call_without_advancing_program_counter() {
+        let mut system = System::new();
+        system.load_program(vec![EI, NOP, NOP]);
+        system.set_interrupt_acknowledge_cycles(2);
+
+        system.step();
+        system.step();
+        system.interrupt_with_bytes(&[0xCD, 0x34, 0x12]);
+
+        assert_eq!(system.step(), 19);
+        assert_eq!(system.state.program_counter.get(), 0x1234);
+        assert_eq!(get_stack_top(&system), 0x0002);
+    }
+
+    #[test]
+    fn should_resume_from_halt_on_interrupt() {
+        let mut system = System::new();
+        system.load_program(vec![EI, HLT, NOP]);
+
+        system.run(100);
+
+        assert!(system.is_halted());
+        assert_eq!(system.state.program_counter.get(), 0x0002);
+        assert_eq!(system.step(), 0);
+
+        system.interrupt(7);
+
+        assert_eq!(system.step(), 11);
+        assert!(!system.is_halted());
+        assert_eq!(system.state.program_counter.get(), 0x0038);
+        assert_eq!(get_stack_top(&system), 0x0002);
+    }
+
+    #[test]
+    fn should_stay_halted_when_interrupts_disabled() {
+        let mut system = System::new();
+        system.load_program(vec![HLT]);
+        system.interrupt(1);
+
+        system.run(100);
+
+        assert!(system.is_halted());
+        assert_eq!(system.state.program_counter.get(), 0x0001);
+        assert_eq!(system.step(), 0);
+    }
+
+    #[test]
+    fn should_not_run_while_powered_off() {
+        let mut system = System::new();
+        system.load_program(vec![NOP, NOP]);
+
+        system.power_off();
+        system.run(100);
+
+        assert_eq!(system.state.program_counter.get(), 0x0000);
+
+        system.power_on();
+        system.step();
+
+        assert_eq!(system.state.program_counter.get(), 0x0001);
+    }
+
+    #[test]
+    fn should_wrap_programs_and_rom_past_end_of_memory() {
+        let mut system = System::new();
+        system.load_program_at(0xFFFE, vec![0x01, 0x02, 0x03, 0x04]);
+
+        assert_eq!(system.read_memory_region(0xFFFE, 0xFFFF), vec![0x01, 0x02]);
+        assert_eq!(system.read_memory_region(0x0000, 0x0001), vec![0x03, 0x04]);
+
+        system.map_rom(0xFFFF, vec![0x05, 0x06]);
+        system.state.memory.set(0xFFFF, 0x00);
+        system.state.memory.set(0x0000, 0x00);
+        system.state.memory.set(0x0001, 0x07);
+
+        assert_eq!(system.read_memory_region(0xFFFF, 0xFFFF), vec![0x05]);
+        assert_eq!(system.read_memory_region(0x0000, 0x0001), vec![0x06, 0x07]);
     }
 }
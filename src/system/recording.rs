@@ -0,0 +1,262 @@
+use std::fs;
+
+use super::snapshot::Reader;
+
+/// Identifies recording files
+const MAGIC: &[u8; 8] = b"I8080REC";
+
+/// Version of the layout written by `Recording::to_bytes`
+pub const VERSION: u16 = 1;
+
+const INPUT: u8 = 0;
+const INTERRUPT: u8 = 1;
+
+/// Host-driven change applied to a `System` between instructions
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Value latched on an input port by `set_input`
+    Input { port: u8, value: u8 },
+    /// Encoded instruction latched by one of the `interrupt` methods
+    Interrupt(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedEvent {
+    /// Clock cycle count of the system when the event was applied
+    pub cycle: usize,
+    pub event: Event,
+}
+
+/// Snapshot of the starting state followed by every host event applied
+/// since, enough to reproduce an execution exactly
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+    pub snapshot: Vec<u8>,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    pub fn new(snapshot: Vec<u8>) -> Self {
+        Recording {
+            snapshot,
+            events: vec![],
+        }
+    }
+
+    pub fn push(&mut self, cycle: usize, event: Event) {
+        self.events.push(RecordedEvent { cycle, event });
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend(VERSION.to_le_bytes());
+        data.extend((self.snapshot.len() as u64).to_le_bytes());
+        data.extend(&self.snapshot);
+        data.extend((self.events.len() as u64).to_le_bytes());
+
+        for recorded in &self.events {
+            data.extend((recorded.cycle as u64).to_le_bytes());
+
+            match &recorded.event {
+                Event::Input { port, value } => data.extend([INPUT, *port, *value]),
+                Event::Interrupt(bytes) => {
+                    data.extend([INTERRUPT, bytes.len() as u8]);
+                    data.extend(bytes);
+                }
+            }
+        }
+
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(data, "recording");
+
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(String::from("not a recording file"));
+        }
+
+        let version = reader.word()?;
+
+        if version != VERSION {
+            return Err(format!(
+                "unsupported recording version {}, expected {}",
+                version, VERSION
+            ));
+        }
+
+        let snapshot_length = reader.long()? as usize;
+        let mut recording = Recording::new(reader.take(snapshot_length)?.to_vec());
+        let event_count = reader.long()?;
+
+        for _ in 0..event_count {
+            let cycle = reader.long()? as usize;
+
+            let event = match reader.byte()? {
+                INPUT => Event::Input {
+                    port: reader.byte()?,
+                    value: reader.byte()?,
+                },
+                INTERRUPT => {
+                    let length = reader.byte()? as usize;
+                    Event::Interrupt(reader.take(length)?.to_vec())
+                }
+                kind => return Err(format!("unknown event type {:#04x}", kind)),
+            };
+
+            if recording
+                .events
+                .last()
+                .is_some_and(|previous| previous.cycle > cycle)
+            {
+                return Err(format!("event at cycle {} is out of order", cycle));
+            }
+
+            recording.push(cycle, event);
+        }
+
+        if !reader.data.is_empty() {
+            return Err(String::from("unexpected data after recording"));
+        }
+
+        Ok(recording)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|error| format!("cannot read '{}': {}", path, error))?;
+
+        Recording::from_bytes(&data).map_err(|message| format!("{}: {}", path, message))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_bytes())
+            .map_err(|error| format!("cannot write '{}': {}", path, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::{InterruptController, Machine, System};
+
+    /// Adds the value on port 0x10 to a running total at 0x2000 until the
+    /// RST 1 handler stores 0xFF on port 0x20
+    fn accumulator() -> System {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x31, 0x00, 0x10, // LXI SP, 0x1000
+            0xFB, // EI
+            0x21, 0x00, 0x20, // LXI H, 0x2000
+            0xDB, 0x10, // loop: IN 0x10
+            0x86, // ADD M
+            0x77, // MOV M, A
+            0xC3, 0x07, 0x00, // JMP loop
+            0x3E, 0xFF, // RST 1: MVI A, 0xFF
+            0xD3, 0x20, // OUT 0x20
+            0x76, // HLT
+        ]);
+        system.load_program_at(0x0008, vec![0xC3, 0x0E, 0x00]);
+        system
+    }
+
+    fn drive(system: &mut System) {
+        system.run(100);
+        system.set_input(0x10, 0x03);
+        system.run(250);
+        system.set_input(0x10, 0x10);
+        system.run(75);
+        system.interrupt(1);
+        system.run(500);
+    }
+
+    #[test]
+    fn should_replay_recorded_events() {
+        let mut system = accumulator();
+        system.start_recording();
+        drive(&mut system);
+
+        let recording = system.stop_recording().unwrap();
+
+        assert_eq!(recording.events.len(), 3);
+        assert_eq!(recording.events[2].event, Event::Interrupt(vec![0xCF]));
+        assert_eq!(system.get_output(0x20), 0xFF);
+        assert!(system.is_halted());
+
+        let recording = Recording::from_bytes(&recording.to_bytes()).unwrap();
+        let mut replayed = System::new();
+        replayed.replay(&recording).unwrap();
+        replayed.run(1000);
+
+        assert!(replayed.is_halted());
+        assert_eq!(replayed.save_state(), system.save_state());
+    }
+
+    /// Requests a single RST 1
+    struct OneShot(bool);
+
+    impl InterruptController for OneShot {
+        fn is_requesting(&self) -> bool {
+            self.0
+        }
+
+        fn acknowledge(&mut self) -> Vec<u8> {
+            self.0 = false;
+            vec![0xCF]
+        }
+    }
+
+    #[test]
+    fn should_record_controller_interrupts() {
+        let mut system = accumulator();
+        system.start_recording();
+        system.set_input(0x10, 0x01);
+        system.run(200);
+        system.attach_interrupt_controller(OneShot(true));
+        system.run(500);
+
+        let recording = system.stop_recording().unwrap();
+
+        assert_eq!(recording.events.len(), 2);
+        assert_eq!(recording.events[1].event, Event::Interrupt(vec![0xCF]));
+        assert!(system.is_halted());
+
+        let mut replayed = System::new();
+        replayed.replay(&recording).unwrap();
+        replayed.run(1000);
+
+        assert_eq!(replayed.save_state(), system.save_state());
+    }
+
+    #[test]
+    fn should_reject_invalid_recordings() {
+        let recording = Recording {
+            snapshot: vec![1, 2, 3],
+            events: vec![
+                RecordedEvent {
+                    cycle: 20,
+                    event: Event::Input {
+                        port: 0x10,
+                        value: 0x01,
+                    },
+                },
+                RecordedEvent {
+                    cycle: 10,
+                    event: Event::Interrupt(vec![0xFF]),
+                },
+            ],
+        };
+        let data = recording.to_bytes();
+        let error = |data: &[u8]| Recording::from_bytes(data).unwrap_err();
+
+        assert_eq!(error(b"I8080SNP\x01\x00"), "not a recording file");
+        assert_eq!(error(&data[..30]), "recording is truncated");
+        assert_eq!(error(&data), "event at cycle 10 is out of order");
+
+        let mut replayed = System::new();
+
+        assert_eq!(
+            replayed.replay(&recording).unwrap_err(),
+            "not a snapshot file"
+        );
+    }
+}
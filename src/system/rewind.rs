@@ -0,0 +1,339 @@
+use std::collections::VecDeque;
+
+use super::recording::Event;
+
+/// Snapshot taken after `position` instructions had executed
+struct Checkpoint {
+    position: usize,
+    snapshot: Vec<u8>,
+}
+
+/// History kept for stepping a `System` backwards
+///
+/// Snapshots are taken every `interval` instructions into a ring buffer of
+/// `capacity` entries, and host events are journaled with the number of
+/// instructions executed before they were applied. Going back restores the
+/// closest earlier checkpoint and executes forward again, reapplying the
+/// journal, so memory-mapped and port devices must behave deterministically
+/// for the history to be exact.
+pub struct Rewind {
+    interval: usize,
+    capacity: usize,
+    checkpoints: VecDeque<Checkpoint>,
+    journal: Vec<(usize, Event)>,
+    /// Instructions executed since rewinding was enabled
+    position: usize,
+    /// Furthest position reached, beyond `position` after going back
+    head: usize,
+}
+
+impl Rewind {
+    pub fn new(interval: usize, capacity: usize, snapshot: Vec<u8>) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            checkpoints: VecDeque::from([Checkpoint {
+                position: 0,
+                snapshot,
+            }]),
+            journal: vec![],
+            position: 0,
+            head: 0,
+        }
+    }
+
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    /// Position of the oldest checkpoint, the furthest back history goes
+    pub fn get_start(&self) -> usize {
+        self.checkpoints[0].position
+    }
+
+    /// Whether events journaled at the current position are replayed rather
+    /// than applied live, after going back
+    pub fn is_behind(&self) -> bool {
+        self.position < self.head
+    }
+
+    /// Journals an event applied by the host, discarding the history ahead
+    /// of the current position
+    pub fn journal(&mut self, event: Event) {
+        if self.is_behind() {
+            let position = self.position;
+
+            self.journal.retain(|(index, _)| *index < position);
+            self.checkpoints
+                .retain(|checkpoint| checkpoint.position <= position);
+            self.head = position;
+        }
+
+        self.journal.push((self.position, event));
+    }
+
+    /// Events to reapply before executing the next instruction
+    pub fn get_events(&self) -> Vec<Event> {
+        if !self.is_behind() {
+            return vec![];
+        }
+
+        self.journal
+            .iter()
+            .filter(|(index, _)| *index == self.position)
+            .map(|(_, event)| event.clone())
+            .collect()
+    }
+
+    /// Counts an executed instruction, returning true if a checkpoint is due
+    pub fn advance(&mut self) -> bool {
+        self.position += 1;
+        self.head = self.head.max(self.position);
+
+        self.position % self.interval == 0
+            && self
+                .checkpoints
+                .back()
+                .map_or(true, |checkpoint| checkpoint.position < self.position)
+    }
+
+    pub fn add_checkpoint(&mut self, snapshot: Vec<u8>) {
+        self.checkpoints.push_back(Checkpoint {
+            position: self.position,
+            snapshot,
+        });
+
+        if self.checkpoints.len() > self.capacity {
+            self.checkpoints.pop_front();
+
+            let start = self.get_start();
+            self.journal.retain(|(index, _)| *index >= start);
+        }
+    }
+
+    /// Finds the latest checkpoint at or before `position`, moving there
+    pub fn seek(&mut self, position: usize) -> Option<Vec<u8>> {
+        let checkpoint = self
+            .checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.position <= position)?;
+
+        self.position = checkpoint.position;
+
+        Some(checkpoint.snapshot.clone())
+    }
+
+    /// Positions of checkpoints before `position`, latest first
+    pub fn get_checkpoints_before(&self, position: usize) -> Vec<usize> {
+        self.checkpoints
+            .iter()
+            .rev()
+            .map(|checkpoint| checkpoint.position)
+            .filter(|checkpoint| *checkpoint < position)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{breakpoint::StopReason, InterruptController, Machine, System, WatchKind};
+
+    /// Calls a subroutine whose `SHLD` overwrites its own return address
+    fn corrupt_stack() -> System {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x31, 0x00, 0x10, // LXI SP, 0x1000
+            0x06, 0x08, // MVI B, 0x08
+            0x05, // loop: DCR B
+            0xC2, 0x05, 0x00, // JNZ loop
+            0xCD, 0x10, 0x00, // CALL 0x0010
+            0x76, // HLT
+            0x00, 0x00, 0x00, // padding
+            0xE5, // 0x0010: PUSH H
+            0x21, 0x00, 0x20, // LXI H, 0x2000
+            0x22, 0xFE, 0x0F, // SHLD 0x0FFE
+            0xE1, // POP H
+            0xC9, // RET
+        ]);
+        system.enable_rewind(4, 16);
+        system
+    }
+
+    #[test]
+    fn should_step_backwards_to_earlier_states() {
+        let mut system = corrupt_stack();
+        let mut states = vec![system.save_state()];
+
+        while system.get_program_counter() != 0x2000 {
+            system.step();
+            states.push(system.save_state());
+        }
+
+        assert_eq!(system.get_rewind_position(), Some(states.len() - 1));
+
+        while let Some(expected) = states.pop() {
+            assert_eq!(system.save_state(), expected);
+            assert_eq!(system.reverse_step(), !states.is_empty());
+        }
+    }
+
+    #[test]
+    fn should_reverse_continue_to_watchpoints() {
+        let mut system = corrupt_stack();
+
+        while system.get_program_counter() != 0x2000 {
+            system.step();
+        }
+
+        let id = system.add_memory_watchpoint(0x0FFE..=0x0FFF, WatchKind::Write);
+
+        assert!(matches!(
+            system.reverse_continue(),
+            Some(StopReason::MemoryWatchpoint(watch, access))
+                if watch == id && access.value == 0x00
+        ));
+        assert_eq!(system.get_program_counter(), 0x0017);
+
+        system.reverse_step();
+
+        assert_eq!(system.get_program_counter(), 0x0014);
+
+        // The CALL pushing the return address wrote there before
+        assert!(matches!(
+            system.reverse_continue(),
+            Some(StopReason::MemoryWatchpoint(watch, _)) if watch == id
+        ));
+        assert_eq!(system.get_program_counter(), 0x0010);
+
+        assert_eq!(system.reverse_continue(), None);
+        assert_eq!(system.get_rewind_position(), Some(0));
+    }
+
+    #[test]
+    fn should_replay_host_events_after_going_back() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0xDB, 0x10, // loop: IN 0x10
+            0x80, // ADD B
+            0x47, // MOV B, A
+            0xC3, 0x00, 0x00, // JMP loop
+        ]);
+        system.enable_rewind(5, 4);
+
+        for value in 1..=6 {
+            system.set_input(0x10, value);
+
+            for _ in 0..4 {
+                system.step();
+            }
+        }
+
+        let expected = system.save_state();
+
+        for _ in 0..10 {
+            assert!(system.reverse_step());
+        }
+
+        while system.get_rewind_position() != Some(24) {
+            system.step();
+        }
+
+        assert_eq!(system.save_state(), expected);
+
+        // New input while behind replaces the history ahead
+        for _ in 0..4 {
+            system.reverse_step();
+        }
+
+        system.set_input(0x10, 0x40);
+
+        for _ in 0..4 {
+            system.step();
+        }
+
+        assert_eq!(system.get_state().registers.b, 1 + 2 + 3 + 4 + 5 + 0x40);
+
+        for _ in 0..4 {
+            system.reverse_step();
+        }
+
+        for _ in 0..4 {
+            system.step();
+        }
+
+        assert_eq!(system.get_state().registers.b, 1 + 2 + 3 + 4 + 5 + 0x40);
+    }
+
+    /// Requests a single RST 1
+    struct OneShot(bool);
+
+    impl InterruptController for OneShot {
+        fn is_requesting(&self) -> bool {
+            self.0
+        }
+
+        fn acknowledge(&mut self) -> Vec<u8> {
+            self.0 = false;
+            vec![0xCF]
+        }
+    }
+
+    #[test]
+    fn should_replay_controller_interrupts_after_going_back() {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x31, 0x00, 0x10, // LXI SP, 0x1000
+            0xFB, // EI
+            0x04, // loop: INR B
+            0xC3, 0x04, 0x00, // JMP loop
+            0x0C, // RST 1: INR C
+            0xFB, // EI
+            0xC9, // RET
+        ]);
+        system.enable_rewind(4, 16);
+
+        for _ in 0..6 {
+            system.step();
+        }
+
+        system.attach_interrupt_controller(OneShot(true));
+
+        for _ in 0..10 {
+            system.step();
+        }
+
+        let expected = system.save_state();
+
+        assert_eq!(system.get_state().registers.c, 1);
+
+        for _ in 0..12 {
+            assert!(system.reverse_step());
+        }
+
+        for _ in 0..12 {
+            system.step();
+        }
+
+        assert_eq!(system.save_state(), expected);
+    }
+
+    #[test]
+    fn should_only_keep_capacity_checkpoints() {
+        let mut system = corrupt_stack();
+        system.enable_rewind(2, 3);
+
+        for _ in 0..20 {
+            system.step();
+        }
+
+        let mut steps = 0;
+
+        while system.reverse_step() {
+            steps += 1;
+        }
+
+        assert_eq!(steps, 20 - 16);
+        assert_eq!(system.get_rewind_position(), Some(16));
+    }
+}
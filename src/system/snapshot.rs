@@ -0,0 +1,265 @@
+use crate::{
+    instructions::Instruction,
+    internal::{program_counter::decode_instruction, state::State},
+};
+
+/// Identifies snapshot files
+const MAGIC: &[u8; 8] = b"I8080SNP";
+
+/// Version of the layout written by `save`
+pub const VERSION: u16 = 1;
+
+const ENABLED: u8 = 1 << 0;
+const HALTED: u8 = 1 << 1;
+const INTERRUPT_ENABLED: u8 = 1 << 2;
+const INTERRUPT_DELAYED: u8 = 1 << 3;
+
+/// Cursor over little-endian file data that fails on truncation
+pub(super) struct Reader<'a> {
+    pub(super) data: &'a [u8],
+    kind: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub(super) fn new(data: &'a [u8], kind: &'static str) -> Self {
+        Reader { data, kind }
+    }
+
+    pub(super) fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < length {
+            return Err(format!("{} is truncated", self.kind));
+        }
+
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    pub(super) fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(super) fn word(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(super) fn long(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+/// Serializes the processor state, all of RAM and ROM, the port latches
+/// and an interrupt instruction waiting to be acknowledged
+///
+/// Memory-mapped and port devices keep their own state, which is not part
+/// of the snapshot.
+pub fn save(state: &State, pending_interrupt: Option<&Instruction>) -> Vec<u8> {
+    let mut data = MAGIC.to_vec();
+    data.extend(VERSION.to_le_bytes());
+
+    let flags = [
+        (state.enabled, ENABLED),
+        (state.halted, HALTED),
+        (state.interrupt_enabled, INTERRUPT_ENABLED),
+        (state.interrupt_delayed, INTERRUPT_DELAYED),
+    ];
+    data.push(
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .fold(0, |byte, (_, bit)| byte | bit),
+    );
+
+    data.extend(state.program_counter.get().to_le_bytes());
+    data.extend(state.get_psw().to_le_bytes());
+
+    let registers = &state.registers;
+    data.extend([
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+    ]);
+    data.extend(registers.stack_pointer.to_le_bytes());
+    data.extend((state.cycles as u64).to_le_bytes());
+
+    data.extend(state.memory.peek_range(0x0000, 0xFFFF));
+
+    let latch = &state.ports.latch;
+    data.extend((0..=0xFF).map(|port| latch.get_input(port)));
+    data.extend((0..=0xFF).map(|port| latch.get_output(port)));
+
+    let interrupt = pending_interrupt
+        .map(Instruction::encode)
+        .unwrap_or_default();
+    data.push(interrupt.len() as u8);
+    data.extend(interrupt);
+
+    data
+}
+
+/// Restores a snapshot written by `save` into `state`, returning the
+/// pending interrupt instruction it held
+///
+/// `state` is left untouched if the snapshot is invalid.
+pub fn restore(state: &mut State, data: &[u8]) -> Result<Option<Instruction>, String> {
+    let mut reader = Reader::new(data, "snapshot");
+
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(String::from("not a snapshot file"));
+    }
+
+    let version = reader.word()?;
+
+    if version != VERSION {
+        return Err(format!(
+            "unsupported snapshot version {}, expected {}",
+            version, VERSION
+        ));
+    }
+
+    let flags = reader.byte()?;
+    let program_counter = reader.word()?;
+    let psw = reader.word()?;
+    let registers = reader.take(6)?;
+    let stack_pointer = reader.word()?;
+    let cycles = reader.long()?;
+    let memory = reader.take(0x10000)?;
+    let inputs = reader.take(0x100)?;
+    let outputs = reader.take(0x100)?;
+
+    let interrupt_length = reader.byte()? as usize;
+    let pending_interrupt = match interrupt_length {
+        0 => None,
+        _ => {
+            let bytes = reader.take(interrupt_length)?;
+
+            match decode_instruction(bytes) {
+                Some((instruction, length)) if length == bytes.len() => Some(instruction),
+                _ => return Err(String::from("invalid pending interrupt instruction")),
+            }
+        }
+    };
+
+    if !reader.data.is_empty() {
+        return Err(String::from("unexpected data after snapshot"));
+    }
+
+    state.enabled = flags & ENABLED != 0;
+    state.halted = flags & HALTED != 0;
+    state.interrupt_enabled = flags & INTERRUPT_ENABLED != 0;
+    state.interrupt_delayed = flags & INTERRUPT_DELAYED != 0;
+    state.program_counter.set(program_counter);
+    state.set_psw(psw);
+
+    let state_registers = &mut state.registers;
+    state_registers.b = registers[0];
+    state_registers.c = registers[1];
+    state_registers.d = registers[2];
+    state_registers.e = registers[3];
+    state_registers.h = registers[4];
+    state_registers.l = registers[5];
+    state_registers.stack_pointer = stack_pointer;
+    state.cycles = cycles as usize;
+
+    state.memory.load(0x0000, memory.to_vec());
+
+    for port in 0..=0xFF {
+        state.ports.latch.set_input(port, inputs[port as usize]);
+        state.ports.latch.set_output(port, outputs[port as usize]);
+    }
+
+    Ok(pending_interrupt)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{Machine, System};
+
+    fn program() -> System {
+        let mut system = System::new();
+        system.load_program(vec![
+            0x31, 0x00, 0x10, // LXI SP, 0x1000
+            0xDB, 0x10, // IN 0x10
+            0x3C, // INR A
+            0xD3, 0x11, // OUT 0x11
+            0x32, 0x00, 0x20, // STA 0x2000
+            0xFB, // EI
+            0xC3, 0x05, 0x00, // JMP 0x0005
+        ]);
+        system.set_input(0x10, 0x41);
+        system
+    }
+
+    #[test]
+    fn should_restore_saved_state() {
+        let mut system = program();
+
+        for _ in 0..6 {
+            system.step();
+        }
+
+        system.interrupt(2);
+
+        let snapshot = system.save_state();
+        let expected = format!("{}", system.get_state());
+
+        let mut restored = System::new();
+        restored.restore_state(&snapshot).unwrap();
+
+        assert_eq!(format!("{}", restored.get_state()), expected);
+        assert_eq!(restored.get_cycle_count(), system.get_cycle_count());
+        assert_eq!(restored.read_memory_region(0x2000, 0x2000), vec![0x42]);
+        assert_eq!(restored.get_output(0x11), 0x42);
+        assert_eq!(restored.save_state(), snapshot);
+
+        // EI delays the pending RST 2 until after the jump
+        for _ in 0..2 {
+            system.step();
+            restored.step();
+        }
+
+        assert_eq!(restored.get_program_counter(), 0x0010);
+        assert_eq!(
+            format!("{}", restored.get_state()),
+            format!("{}", system.get_state())
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_snapshots() {
+        let mut system = program();
+        let snapshot = system.save_state();
+
+        assert_eq!(
+            system.restore_state(b"not a snapshot").unwrap_err(),
+            "not a snapshot file"
+        );
+
+        let mut future = snapshot.clone();
+        future[8] = 99;
+
+        assert_eq!(
+            system.restore_state(&future).unwrap_err(),
+            "unsupported snapshot version 99, expected 1"
+        );
+        assert_eq!(
+            system.restore_state(&snapshot[..1000]).unwrap_err(),
+            "snapshot is truncated"
+        );
+
+        let mut trailing = snapshot.clone();
+        trailing.push(0);
+
+        assert_eq!(
+            system.restore_state(&trailing).unwrap_err(),
+            "unexpected data after snapshot"
+        );
+    }
+}
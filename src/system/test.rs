@@ -1,12 +1,19 @@
 use crate::internal::{
     execution::execute_instruction,
-    instructions::{Instruction, Register, RegisterPair},
+    instructions::{timing::get_instruction_timing, Instruction, Register, RegisterPair},
     memory::AddressableMemory,
     state::State,
 };
 
+use super::{
+    trace::{TraceEntry, Tracer},
+    Machine,
+};
+
 pub struct TestSystem {
     pub state: State,
+    tracer: Option<Tracer>,
+    captured_output: Option<Vec<u8>>,
 }
 
 impl TestSystem {
@@ -23,7 +30,11 @@ impl TestSystem {
         state.memory.set(0x0006, 0x00);
         state.memory.set(0x0007, 0xC9);
 
-        TestSystem { state }
+        TestSystem {
+            state,
+            tracer: None,
+            captured_output: None,
+        }
     }
 
     pub fn load_test_program(&mut self, program_bytecode: Vec<u8>) {
@@ -34,17 +45,36 @@ impl TestSystem {
             .set_range(0x100, address_end, program_bytecode);
     }
 
+    /// Collects console output in a buffer instead of printing it
+    pub fn capture_output(&mut self) {
+        self.captured_output.get_or_insert_with(Vec::new);
+    }
+
+    pub fn take_captured_output(&mut self) -> Vec<u8> {
+        self.captured_output
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn write_console(&mut self, value: u8) {
+        match self.captured_output.as_mut() {
+            Some(output) => output.push(value),
+            None => print!("{}", char::from(value)),
+        }
+    }
+
     fn print(&mut self) {
         let operation = self.state.get_register(&Register::C);
 
         match operation {
-            2 => print!("{}", char::from(self.state.get_register(&Register::E))),
+            2 => self.write_console(self.state.get_register(&Register::E)),
             9 => {
                 let mut address = self.state.get_register_pair(&RegisterPair::DE);
                 let mut value = self.state.memory.get(address);
 
                 while value != b'$' {
-                    print!("{}", char::from(value));
+                    self.write_console(value);
 
                     address = address.wrapping_add(1);
                     value = self.state.memory.get(address);
@@ -54,20 +84,64 @@ impl TestSystem {
         }
     }
 
-    pub fn run_current_instruction(&mut self) {
-        // print!("{:#06x} ", self.state.program_counter.get());
+    pub fn attach_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn detach_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
 
+    pub fn run_current_instruction(&mut self) -> usize {
+        let address = self.state.program_counter.get();
         let instruction = self
             .state
             .program_counter
             .get_next_instruction(&self.state.memory);
 
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&TraceEntry::fetched(&self.state, address));
+        }
+
+        let instruction_cycles = get_instruction_timing(&self.state, &instruction);
+
         execute_instruction(&mut self.state, &instruction);
 
+        self.state.cycles += instruction_cycles;
+
         if let Instruction::Output(_) = &instruction {
             self.print()
         }
 
-        // println!("{}:   {:#04x}", &instruction, self.state.registers.a);
+        // Programs exit by returning to 0x0000, where HLT ends the test
+        if self.state.halted {
+            self.state.enabled = false;
+        }
+
+        instruction_cycles
+    }
+}
+
+impl Machine for TestSystem {
+    fn get_state(&self) -> &State {
+        &self.state
+    }
+
+    fn get_state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+
+    fn step(&mut self) -> usize {
+        if !self.state.enabled {
+            return 0;
+        }
+
+        self.run_current_instruction()
+    }
+}
+
+impl Default for TestSystem {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,512 @@
+use std::io::{self, Write};
+
+use crate::internal::{program_counter::decode_instruction, state::State};
+
+use super::snapshot::Reader;
+
+/// Identifies binary trace files
+const MAGIC: &[u8; 8] = b"I8080TRC";
+
+/// Version of the binary record layout
+pub const VERSION: u16 = 1;
+
+/// Size of each binary record following the header
+pub const RECORD_SIZE: usize = 24;
+
+const INTERRUPT: u8 = 1 << 7;
+
+/// Default text format, with every register on its own
+pub const DEFAULT_TEMPLATE: &str = "{pc}  {bytes:8}  {mnemonic:16}  \
+    A={a} F={f} B={b} C={c} D={d} E={e} H={h} L={l} SP={sp} CYC={cycles}";
+
+/// Text format with register pairs, as printed by many other emulators
+pub const PAIRS_TEMPLATE: &str =
+    "PC: {pc}, AF: {af}, BC: {bc}, DE: {de}, HL: {hl}, SP: {sp}, CYC: {cycles}";
+
+/// Processor state before an instruction executed, along with its bytes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Whether the instruction was supplied during interrupt acknowledge
+    /// rather than fetched from `address`
+    pub interrupt: bool,
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub stack_pointer: u16,
+    pub cycles: u64,
+}
+
+impl TraceEntry {
+    pub fn new(state: &State, address: u16, bytes: Vec<u8>, interrupt: bool) -> Self {
+        let registers = &state.registers;
+
+        TraceEntry {
+            address,
+            bytes,
+            interrupt,
+            a: registers.a,
+            f: state.condition_flags.get_byte(),
+            b: registers.b,
+            c: registers.c,
+            d: registers.d,
+            e: registers.e,
+            h: registers.h,
+            l: registers.l,
+            stack_pointer: registers.stack_pointer,
+            cycles: state.cycles as u64,
+        }
+    }
+
+    /// Entry for the instruction just fetched from `address`, with its bytes
+    /// read up to the program counter without any device side effects
+    pub fn fetched(state: &State, address: u16) -> Self {
+        let length = state.program_counter.get().wrapping_sub(address);
+        let bytes = (0..length)
+            .map(|offset| state.memory.inspect(address.wrapping_add(offset)))
+            .collect();
+
+        TraceEntry::new(state, address, bytes, false)
+    }
+
+    fn get_mnemonic(&self) -> String {
+        let mnemonic = match decode_instruction(&self.bytes) {
+            Some((instruction, _)) => instruction
+                .to_string()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => String::from("???"),
+        };
+
+        if self.interrupt {
+            format!("INT {}", mnemonic)
+        } else {
+            mnemonic
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let mut record = [0; RECORD_SIZE];
+        let length = self.bytes.len().min(3);
+
+        record[0..2].copy_from_slice(&self.address.to_le_bytes());
+        record[2] = length as u8 | if self.interrupt { INTERRUPT } else { 0 };
+        record[3..3 + length].copy_from_slice(&self.bytes[..length]);
+        record[6..14].copy_from_slice(&[
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+        ]);
+        record[14..16].copy_from_slice(&self.stack_pointer.to_le_bytes());
+        record[16..24].copy_from_slice(&self.cycles.to_le_bytes());
+
+        record
+    }
+
+    fn from_bytes(record: &[u8]) -> Self {
+        let length = (record[2] & 0x03) as usize;
+
+        TraceEntry {
+            address: u16::from_le_bytes([record[0], record[1]]),
+            bytes: record[3..3 + length].to_vec(),
+            interrupt: record[2] & INTERRUPT != 0,
+            a: record[6],
+            f: record[7],
+            b: record[8],
+            c: record[9],
+            d: record[10],
+            e: record[11],
+            h: record[12],
+            l: record[13],
+            stack_pointer: u16::from_le_bytes([record[14], record[15]]),
+            cycles: u64::from_le_bytes(record[16..24].try_into().unwrap()),
+        }
+    }
+}
+
+/// Values which can be placed in a text template as `{name}` or, padded
+/// to a minimum width, `{name:width}`
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    Pc,
+    Bytes,
+    Opcode,
+    Mnemonic,
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Sp,
+    Af,
+    Bc,
+    De,
+    Hl,
+    Cycles,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        let field = match name {
+            "pc" => Field::Pc,
+            "bytes" => Field::Bytes,
+            "opcode" => Field::Opcode,
+            "mnemonic" => Field::Mnemonic,
+            "a" => Field::A,
+            "f" => Field::F,
+            "b" => Field::B,
+            "c" => Field::C,
+            "d" => Field::D,
+            "e" => Field::E,
+            "h" => Field::H,
+            "l" => Field::L,
+            "sp" => Field::Sp,
+            "af" => Field::Af,
+            "bc" => Field::Bc,
+            "de" => Field::De,
+            "hl" => Field::Hl,
+            "cycles" => Field::Cycles,
+            _ => return None,
+        };
+
+        Some(field)
+    }
+
+    fn format(&self, entry: &TraceEntry) -> String {
+        let pair = |high: u8, low: u8| format!("{:04X}", u16::from_be_bytes([high, low]));
+
+        match self {
+            Field::Pc => format!("{:04X}", entry.address),
+            Field::Bytes => entry
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Field::Opcode => format!("{:02X}", entry.bytes.first().copied().unwrap_or(0)),
+            Field::Mnemonic => entry.get_mnemonic(),
+            Field::A => format!("{:02X}", entry.a),
+            Field::F => format!("{:02X}", entry.f),
+            Field::B => format!("{:02X}", entry.b),
+            Field::C => format!("{:02X}", entry.c),
+            Field::D => format!("{:02X}", entry.d),
+            Field::E => format!("{:02X}", entry.e),
+            Field::H => format!("{:02X}", entry.h),
+            Field::L => format!("{:02X}", entry.l),
+            Field::Sp => format!("{:04X}", entry.stack_pointer),
+            Field::Af => pair(entry.a, entry.f),
+            Field::Bc => pair(entry.b, entry.c),
+            Field::De => pair(entry.d, entry.e),
+            Field::Hl => pair(entry.h, entry.l),
+            Field::Cycles => entry.cycles.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Field(Field, usize),
+}
+
+/// Parsed text template
+#[derive(Clone, Debug)]
+pub struct Template(Vec<Segment>);
+
+/// How a `Tracer` writes each instruction
+#[derive(Clone, Debug)]
+pub enum TraceFormat {
+    /// One line per instruction, rendered from a template
+    Text(Template),
+    /// A header followed by fixed-size little-endian records
+    Binary,
+}
+
+impl TraceFormat {
+    /// Parses a text template such as `"{pc} {mnemonic:12} A={a}"`, where
+    /// 8-bit values are two hex digits, addresses and register pairs four, and
+    /// the cycle count is decimal
+    pub fn text(template: &str) -> Result<Self, String> {
+        let mut segments = vec![];
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unterminated field in '{}'", template))?
+                + start;
+            let placeholder = &rest[start + 1..end];
+            let (name, width) = match placeholder.split_once(':') {
+                Some((name, width)) => (
+                    name,
+                    width
+                        .parse()
+                        .map_err(|_| format!("invalid width in '{{{}}}'", placeholder))?,
+                ),
+                None => (placeholder, 0),
+            };
+            let field =
+                Field::from_name(name).ok_or_else(|| format!("unknown field '{{{}}}'", name))?;
+
+            segments.push(Segment::Field(field, width));
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Ok(TraceFormat::Text(Template(segments)))
+    }
+
+    /// Parses a format name, `text`, `pairs` or `binary`, or a text template
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "text" => TraceFormat::text(DEFAULT_TEMPLATE),
+            "pairs" => TraceFormat::text(PAIRS_TEMPLATE),
+            "binary" => Ok(TraceFormat::Binary),
+            _ if name.contains('{') => TraceFormat::text(name),
+            _ => Err(format!("unknown trace format '{}'", name)),
+        }
+    }
+
+    /// Renders an entry as a line of text, without the line ending, or
+    /// `None` for the binary format
+    pub fn format_line(&self, entry: &TraceEntry) -> Option<String> {
+        let TraceFormat::Text(Template(segments)) = self else {
+            return None;
+        };
+
+        let line: String = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(field, width) => format!("{:<width$}", field.format(entry)),
+            })
+            .collect();
+
+        Some(line.trim_end().to_string())
+    }
+}
+
+impl Default for TraceFormat {
+    fn default() -> Self {
+        TraceFormat::text(DEFAULT_TEMPLATE).unwrap()
+    }
+}
+
+/// Writes a trace entry for every instruction executed to a sink
+///
+/// Write errors stop tracing and are reported by `finish`, so tracing never
+/// interrupts execution.
+pub struct Tracer {
+    sink: Box<dyn Write>,
+    format: TraceFormat,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    /// Creates a tracer, writing the header of the binary format straight
+    /// away
+    pub fn new(sink: impl Write + 'static, format: TraceFormat) -> io::Result<Self> {
+        let mut sink: Box<dyn Write> = Box::new(sink);
+
+        if let TraceFormat::Binary = format {
+            sink.write_all(MAGIC)?;
+            sink.write_all(&VERSION.to_le_bytes())?;
+        }
+
+        Ok(Tracer {
+            sink,
+            format,
+            error: None,
+        })
+    }
+
+    pub fn trace(&mut self, entry: &TraceEntry) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = match self.format.format_line(entry) {
+            Some(line) => writeln!(self.sink, "{}", line),
+            None => self.sink.write_all(&entry.to_bytes()),
+        };
+
+        self.error = result.err();
+    }
+
+    /// Flushes the sink, returning the first error hit while tracing
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.sink.flush()
+    }
+}
+
+/// Reads back the entries of a binary trace
+pub fn parse_binary(data: &[u8]) -> Result<Vec<TraceEntry>, String> {
+    let mut reader = Reader::new(data, "trace");
+
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(String::from("not a binary trace file"));
+    }
+
+    let version = reader.word()?;
+
+    if version != VERSION {
+        return Err(format!(
+            "unsupported trace version {}, expected {}",
+            version, VERSION
+        ));
+    }
+
+    let mut entries = vec![];
+
+    while !reader.data.is_empty() {
+        entries.push(TraceEntry::from_bytes(reader.take(RECORD_SIZE)?));
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::system::{Machine, System};
+
+    /// Sink which can still be read after being handed to a tracer
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(format: TraceFormat) -> Vec<u8> {
+        let buffer = SharedBuffer::default();
+        let mut system = System::new();
+        system.load_program(vec![
+            0x31, 0x00, 0x10, // LXI SP, 0x1000
+            0x3E, 0x42, // MVI A, 0x42
+            0xFB, // EI
+            0x08, // NOP (undocumented)
+            0x76, // HLT
+        ]);
+        system.attach_tracer(Tracer::new(buffer.clone(), format).unwrap());
+        system.run(100);
+        system.interrupt(1);
+        system.step();
+        system.detach_tracer().unwrap().finish().unwrap();
+
+        buffer.0.take()
+    }
+
+    #[test]
+    fn should_trace_each_instruction_as_text() {
+        let text = String::from_utf8(trace(TraceFormat::default())).unwrap();
+
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            vec![
+                "0000  31 00 10  LXI SP, 0x1000    A=00 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=0000 CYC=0",
+                "0003  3E 42     MVI A, 0x42       A=00 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=1000 CYC=10",
+                "0005  FB        EI                A=42 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=1000 CYC=17",
+                "0006  08        NOP               A=42 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=1000 CYC=21",
+                "0007  76        HLT               A=42 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=1000 CYC=25",
+                "0008  CF        INT RST 0x01      A=42 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=1000 CYC=32",
+            ]
+        );
+
+        let pairs = String::from_utf8(trace(TraceFormat::from_name("pairs").unwrap())).unwrap();
+
+        assert_eq!(
+            pairs.lines().nth(2),
+            Some("PC: 0005, AF: 4202, BC: 0000, DE: 0000, HL: 0000, SP: 1000, CYC: 17")
+        );
+    }
+
+    #[test]
+    fn should_read_back_binary_traces() {
+        let data = trace(TraceFormat::Binary);
+        let entries = parse_binary(&data).unwrap();
+
+        assert_eq!(data.len(), 10 + 6 * RECORD_SIZE);
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[1].bytes, vec![0x3E, 0x42]);
+        assert_eq!(entries[1].stack_pointer, 0x1000);
+        assert_eq!(entries[4].cycles, 25);
+        assert!(entries[5].interrupt);
+        assert_eq!(
+            TraceFormat::default().format_line(&entries[3]),
+            Some(String::from(
+                "0006  08        NOP               A=42 F=02 B=00 C=00 D=00 E=00 H=00 L=00 SP=1000 CYC=21"
+            ))
+        );
+
+        assert_eq!(
+            parse_binary(&data[..data.len() - 1]).unwrap_err(),
+            "trace is truncated"
+        );
+    }
+
+    #[test]
+    fn should_not_trace_rewind_replays() {
+        let buffer = SharedBuffer::default();
+        let mut system = System::new();
+        system.load_program(vec![
+            0x3E, 0x01, // MVI A, 0x01
+            0x3C, // INR A
+            0x3C, // INR A
+            0x76, // HLT
+        ]);
+        system.enable_rewind(100, 4);
+        system.attach_tracer(Tracer::new(buffer.clone(), TraceFormat::default()).unwrap());
+        system.run(100);
+
+        assert!(system.reverse_step());
+        assert!(system.reverse_step());
+        assert_eq!(system.get_state().program_counter.get(), 0x0003);
+
+        system.step();
+        system.detach_tracer().unwrap().finish().unwrap();
+
+        let text = String::from_utf8(buffer.0.take()).unwrap();
+        let addresses: Vec<_> = text.lines().map(|line| &line[..4]).collect();
+
+        assert_eq!(addresses, vec!["0000", "0002", "0003", "0004", "0003"]);
+    }
+
+    #[test]
+    fn should_reject_invalid_templates() {
+        let error = |name: &str| TraceFormat::from_name(name).unwrap_err();
+
+        assert_eq!(error("json"), "unknown trace format 'json'");
+        assert_eq!(error("{pc} {ix}"), "unknown field '{ix}'");
+        assert_eq!(error("{pc:wide}"), "invalid width in '{pc:wide}'");
+        assert_eq!(error("{pc"), "unterminated field in '{pc'");
+    }
+}